@@ -0,0 +1,5 @@
+-- Add migration script here
+
+ALTER TABLE issue_delivery_queue ADD COLUMN n_attempts SMALLINT NOT NULL DEFAULT 0;
+
+ALTER TABLE issue_delivery_queue ADD COLUMN execute_after TIMESTAMPTZ NOT NULL DEFAULT now();
\ No newline at end of file
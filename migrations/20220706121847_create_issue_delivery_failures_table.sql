@@ -0,0 +1,10 @@
+-- Add migration script here
+
+CREATE TABLE issue_delivery_failures (
+    newsletter_issue_id UUID NOT NULL REFERENCES newsletter_issues (id),
+    subscriber_email TEXT NOT NULL,
+    error_message TEXT NOT NULL,
+    n_attempts SMALLINT NOT NULL,
+    failed_at TIMESTAMPTZ NOT NULL,
+    PRIMARY KEY(newsletter_issue_id, subscriber_email)
+);
\ No newline at end of file
@@ -0,0 +1,19 @@
+-- Add migration script here
+
+ALTER TABLE newsletter_issues ADD COLUMN n_total_recipients INTEGER NOT NULL DEFAULT 0;
+
+ALTER TABLE newsletter_issues ADD COLUMN n_delivered INTEGER NOT NULL DEFAULT 0;
+
+ALTER TABLE newsletter_issues ADD COLUMN n_failed INTEGER NOT NULL DEFAULT 0;
+
+ALTER TABLE newsletter_issues ADD COLUMN completed_at TIMESTAMPTZ NULL;
+
+-- Issues published before progress tracking existed only know what is still queued.
+UPDATE newsletter_issues
+SET n_total_recipients = (
+    SELECT COUNT(*)
+    FROM issue_delivery_queue
+    WHERE newsletter_issue_id = newsletter_issues.id
+);
+
+UPDATE newsletter_issues SET completed_at = published_at WHERE n_total_recipients = 0;
\ No newline at end of file
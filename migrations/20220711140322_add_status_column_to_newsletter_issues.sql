@@ -0,0 +1,14 @@
+-- Add migration script here
+
+BEGIN;
+
+ALTER TABLE newsletter_issues ADD COLUMN status TEXT NULL;
+
+UPDATE newsletter_issues SET status = 'published';
+
+ALTER TABLE newsletter_issues ALTER COLUMN status SET NOT NULL;
+
+-- Drafts haven't been published yet
+ALTER TABLE newsletter_issues ALTER COLUMN published_at DROP NOT NULL;
+
+COMMIT;
\ No newline at end of file
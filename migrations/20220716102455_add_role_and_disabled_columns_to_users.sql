@@ -0,0 +1,15 @@
+-- Add migration script here
+
+BEGIN;
+
+-- Existing users keep every permission they had
+ALTER TABLE users ADD COLUMN role TEXT NULL;
+
+UPDATE users SET role = 'owner';
+
+ALTER TABLE users ALTER COLUMN role SET NOT NULL;
+ALTER TABLE users ADD CONSTRAINT users_role_check CHECK (role IN ('owner', 'editor', 'viewer'));
+
+ALTER TABLE users ADD COLUMN disabled BOOLEAN NOT NULL DEFAULT false;
+
+COMMIT;
\ No newline at end of file
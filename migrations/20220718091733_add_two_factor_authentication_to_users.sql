@@ -0,0 +1,16 @@
+-- Add migration script here
+
+BEGIN;
+
+-- Base32, as shown to authenticator apps. NULL while two-factor authentication is off.
+ALTER TABLE users ADD COLUMN totp_secret TEXT NULL;
+-- The last time step a code was accepted for, so that a code can't be used twice
+ALTER TABLE users ADD COLUMN totp_last_used_step BIGINT NULL;
+
+CREATE TABLE recovery_codes (
+    user_id UUID NOT NULL REFERENCES users (id) ON DELETE CASCADE,
+    code_hash TEXT NOT NULL,
+    PRIMARY KEY (user_id, code_hash)
+);
+
+COMMIT;
\ No newline at end of file
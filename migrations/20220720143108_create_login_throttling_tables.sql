@@ -0,0 +1,23 @@
+-- Add migration script here
+
+BEGIN;
+
+-- Keyed by `username:<username>` or `ip:<client IP>`
+CREATE TABLE login_throttles (
+    throttle_key TEXT NOT NULL PRIMARY KEY,
+    n_failures INTEGER NOT NULL,
+    last_failure_at timestamptz NOT NULL,
+    locked_until timestamptz NULL
+);
+
+-- Audit trail, kept when the throttles are reset
+CREATE TABLE failed_logins (
+    id uuid NOT NULL PRIMARY KEY,
+    username TEXT NOT NULL,
+    client_ip TEXT NOT NULL,
+    reason TEXT NOT NULL,
+    attempted_at timestamptz NOT NULL
+);
+CREATE INDEX failed_logins_attempted_at_index ON failed_logins (attempted_at);
+
+COMMIT;
\ No newline at end of file
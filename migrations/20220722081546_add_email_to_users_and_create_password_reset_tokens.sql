@@ -0,0 +1,17 @@
+-- Add migration script here
+
+BEGIN;
+
+-- Where password reset links are sent, optional
+ALTER TABLE users ADD COLUMN email TEXT NULL;
+CREATE UNIQUE INDEX users_email_index ON users (lower(email));
+
+-- Only the SHA-256 hash of the tokens is stored, the tokens themselves are in the emails
+CREATE TABLE password_reset_tokens (
+    token_hash TEXT NOT NULL PRIMARY KEY,
+    user_id uuid NOT NULL REFERENCES users (id) ON DELETE CASCADE,
+    created_at timestamptz NOT NULL,
+    expires_at timestamptz NOT NULL
+);
+
+COMMIT;
\ No newline at end of file
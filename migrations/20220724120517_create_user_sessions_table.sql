@@ -0,0 +1,16 @@
+-- Add migration script here
+
+BEGIN;
+
+-- The admin sessions, the session cookie only refers to one of them by id
+CREATE TABLE user_sessions (
+    id uuid NOT NULL PRIMARY KEY,
+    user_id uuid NOT NULL REFERENCES users (id) ON DELETE CASCADE,
+    created_at timestamptz NOT NULL,
+    last_seen_at timestamptz NOT NULL,
+    client_ip TEXT NOT NULL,
+    user_agent TEXT NOT NULL
+);
+CREATE INDEX user_sessions_user_id_index ON user_sessions (user_id);
+
+COMMIT;
\ No newline at end of file
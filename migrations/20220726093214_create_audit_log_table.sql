@@ -0,0 +1,17 @@
+-- Add migration script here
+
+BEGIN;
+
+-- Who did what from where. The username is copied, so that the entries outlive the user.
+CREATE TABLE audit_log (
+    id uuid NOT NULL PRIMARY KEY,
+    actor_id uuid NULL REFERENCES users (id) ON DELETE SET NULL,
+    actor_username TEXT NOT NULL,
+    action TEXT NOT NULL,
+    target TEXT NULL,
+    client_ip TEXT NOT NULL,
+    occurred_at timestamptz NOT NULL
+);
+CREATE INDEX audit_log_occurred_at_index ON audit_log (occurred_at);
+
+COMMIT;
\ No newline at end of file
@@ -0,0 +1,6 @@
+-- Add migration script here
+
+-- Unsubscribing now deletes the confirmation tokens, so that an old confirmation link
+-- can't subscribe anyone again. Do the same for those who have already unsubscribed.
+DELETE FROM subscription_tokens
+WHERE subscriber_id IN (SELECT id FROM subscriptions WHERE status = 'unsubscribed');
\ No newline at end of file
@@ -0,0 +1,8 @@
+-- Add migration script here
+BEGIN;
+    -- Tokens created before now get a full TTL from now on
+    ALTER TABLE subscription_tokens ADD COLUMN created_at timestamptz NULL;
+    UPDATE subscription_tokens SET created_at = now();
+    ALTER TABLE subscription_tokens ALTER COLUMN created_at SET NOT NULL;
+    CREATE INDEX subscription_tokens_subscriber_id_idx ON subscription_tokens (subscriber_id);
+COMMIT;
\ No newline at end of file
@@ -0,0 +1,2 @@
+-- Add migration script here
+ALTER TABLE subscription_tokens ADD COLUMN consumed_at timestamptz NULL;
\ No newline at end of file
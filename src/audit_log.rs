@@ -0,0 +1,328 @@
+use std::future::{ready, Ready};
+
+use actix_web::{web, FromRequest, HttpMessage, HttpRequest};
+use anyhow::Context;
+use chrono::{DateTime, NaiveDate, Utc};
+use sqlx::{PgExecutor, PgPool, Postgres, Transaction};
+use uuid::Uuid;
+
+use crate::{
+    authentication::{client_ip, UserId},
+    configuration::LoginThrottlingSettings,
+    utils::e500,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AuditAction {
+    LogIn,
+    LogOut,
+    ChangePassword,
+    ResetForgottenPassword,
+    EnableTwoFactor,
+    DisableTwoFactor,
+    RevokeSession,
+    RevokeOtherSessions,
+    PublishNewsletter,
+    CreateDraft,
+    UpdateDraft,
+    DeleteDraft,
+    SendTestDraft,
+    PublishDraft,
+    CancelIssue,
+    RescheduleIssue,
+    RequeueFailedDelivery,
+    AddUser,
+    ChangeUserRole,
+    ChangeUserEmail,
+    DisableUser,
+    EnableUser,
+    DeleteUser,
+}
+
+impl AuditAction {
+    pub const ALL: [AuditAction; 23] = [
+        AuditAction::LogIn,
+        AuditAction::LogOut,
+        AuditAction::ChangePassword,
+        AuditAction::ResetForgottenPassword,
+        AuditAction::EnableTwoFactor,
+        AuditAction::DisableTwoFactor,
+        AuditAction::RevokeSession,
+        AuditAction::RevokeOtherSessions,
+        AuditAction::PublishNewsletter,
+        AuditAction::CreateDraft,
+        AuditAction::UpdateDraft,
+        AuditAction::DeleteDraft,
+        AuditAction::SendTestDraft,
+        AuditAction::PublishDraft,
+        AuditAction::CancelIssue,
+        AuditAction::RescheduleIssue,
+        AuditAction::RequeueFailedDelivery,
+        AuditAction::AddUser,
+        AuditAction::ChangeUserRole,
+        AuditAction::ChangeUserEmail,
+        AuditAction::DisableUser,
+        AuditAction::EnableUser,
+        AuditAction::DeleteUser,
+    ];
+
+    pub fn parse(s: &str) -> Result<Self, String> {
+        Self::ALL
+            .into_iter()
+            .find(|action| action.as_str() == s)
+            .ok_or_else(|| format!("{} is not an audited action.", s))
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AuditAction::LogIn => "log_in",
+            AuditAction::LogOut => "log_out",
+            AuditAction::ChangePassword => "change_password",
+            AuditAction::ResetForgottenPassword => "reset_forgotten_password",
+            AuditAction::EnableTwoFactor => "enable_two_factor",
+            AuditAction::DisableTwoFactor => "disable_two_factor",
+            AuditAction::RevokeSession => "revoke_session",
+            AuditAction::RevokeOtherSessions => "revoke_other_sessions",
+            AuditAction::PublishNewsletter => "publish_newsletter",
+            AuditAction::CreateDraft => "create_draft",
+            AuditAction::UpdateDraft => "update_draft",
+            AuditAction::DeleteDraft => "delete_draft",
+            AuditAction::SendTestDraft => "send_test_draft",
+            AuditAction::PublishDraft => "publish_draft",
+            AuditAction::CancelIssue => "cancel_issue",
+            AuditAction::RescheduleIssue => "reschedule_issue",
+            AuditAction::RequeueFailedDelivery => "requeue_failed_delivery",
+            AuditAction::AddUser => "add_user",
+            AuditAction::ChangeUserRole => "change_user_role",
+            AuditAction::ChangeUserEmail => "change_user_email",
+            AuditAction::DisableUser => "disable_user",
+            AuditAction::EnableUser => "enable_user",
+            AuditAction::DeleteUser => "delete_user",
+        }
+    }
+}
+
+impl std::fmt::Display for AuditAction {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Pass a transaction as the executor to only keep the entry if the action itself is committed.
+#[tracing::instrument(name = "Record an audit event", skip(executor))]
+pub async fn record_audit_event<'c>(
+    executor: impl PgExecutor<'c>,
+    actor_id: Uuid,
+    action: AuditAction,
+    target: Option<&str>,
+    client_ip: &str,
+) -> Result<(), anyhow::Error> {
+    sqlx::query!(
+        r#"
+        INSERT INTO audit_log (id, actor_id, actor_username, action, target, client_ip, occurred_at)
+        VALUES (
+            $1,
+            $2,
+            COALESCE((SELECT username FROM users WHERE id = $2), 'unknown'),
+            $3,
+            $4,
+            $5,
+            now()
+        )
+    "#,
+        Uuid::new_v4(),
+        actor_id,
+        action.as_str(),
+        target,
+        client_ip
+    )
+    .execute(executor)
+    .await
+    .context("Failed recording the audit event.")?;
+
+    Ok(())
+}
+
+/// Records what the logged in user does, must be extracted behind `reject_anonymous_users`.
+pub struct Auditor {
+    actor_id: Uuid,
+    client_ip: String,
+    db_pool: web::Data<PgPool>,
+}
+
+impl Auditor {
+    pub async fn record(
+        &self,
+        action: AuditAction,
+        target: Option<&str>,
+    ) -> Result<(), anyhow::Error> {
+        record_audit_event(
+            self.db_pool.get_ref(),
+            self.actor_id,
+            action,
+            target,
+            &self.client_ip,
+        )
+        .await
+    }
+
+    pub async fn record_in(
+        &self,
+        transaction: &mut Transaction<'_, Postgres>,
+        action: AuditAction,
+        target: Option<&str>,
+    ) -> Result<(), anyhow::Error> {
+        record_audit_event(transaction, self.actor_id, action, target, &self.client_ip).await
+    }
+}
+
+impl FromRequest for Auditor {
+    type Error = actix_web::Error;
+    type Future = Ready<Result<Auditor, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _: &mut actix_web::dev::Payload) -> Self::Future {
+        ready(auditor(req))
+    }
+}
+
+fn auditor(req: &HttpRequest) -> Result<Auditor, actix_web::Error> {
+    let actor_id = req
+        .extensions()
+        .get::<UserId>()
+        .map(|user_id| **user_id)
+        .context("The user id is missing, is `reject_anonymous_users` in place?")
+        .map_err(e500)?;
+    let settings = req
+        .app_data::<web::Data<LoginThrottlingSettings>>()
+        .context("The login throttling settings are missing from the application data.")
+        .map_err(e500)?;
+    let db_pool = req
+        .app_data::<web::Data<PgPool>>()
+        .context("The database pool is missing from the application data.")
+        .map_err(e500)?
+        .clone();
+
+    Ok(Auditor {
+        actor_id,
+        client_ip: client_ip(req, settings),
+        db_pool,
+    })
+}
+
+pub struct AuditEvent {
+    pub occurred_at: DateTime<Utc>,
+    pub actor_username: String,
+    pub action: String,
+    pub target: Option<String>,
+    pub client_ip: String,
+}
+
+/// Every field is optional, the dates are inclusive and in UTC.
+#[derive(Debug, Default)]
+pub struct AuditLogFilter {
+    pub actor: Option<String>,
+    pub action: Option<AuditAction>,
+    pub since: Option<NaiveDate>,
+    pub until: Option<NaiveDate>,
+}
+
+impl AuditLogFilter {
+    fn bounds(&self) -> (Option<DateTime<Utc>>, Option<DateTime<Utc>>) {
+        let start_of = |date: NaiveDate| DateTime::<Utc>::from_utc(date.and_hms(0, 0, 0), Utc);
+        (
+            self.since.map(start_of),
+            self.until.and_then(|until| until.succ_opt()).map(start_of),
+        )
+    }
+}
+
+#[tracing::instrument(name = "Count audit events", skip(db_pool))]
+pub async fn count_audit_events(
+    filter: &AuditLogFilter,
+    db_pool: &PgPool,
+) -> Result<i64, anyhow::Error> {
+    let (since, until) = filter.bounds();
+    let row = sqlx::query!(
+        r#"
+        SELECT COUNT(*) AS "count!"
+        FROM audit_log
+        WHERE ($1::TEXT IS NULL OR lower(actor_username) = lower($1))
+            AND ($2::TEXT IS NULL OR action = $2)
+            AND ($3::TIMESTAMPTZ IS NULL OR occurred_at >= $3)
+            AND ($4::TIMESTAMPTZ IS NULL OR occurred_at < $4)
+    "#,
+        filter.actor,
+        filter.action.map(|action| action.as_str()),
+        since,
+        until
+    )
+    .fetch_one(db_pool)
+    .await
+    .context("Failed to count audit events.")?;
+
+    Ok(row.count)
+}
+
+/// The most recent events first, all of them if `limit` is `None`.
+#[tracing::instrument(name = "List audit events", skip(db_pool))]
+pub async fn list_audit_events(
+    filter: &AuditLogFilter,
+    limit: Option<i64>,
+    offset: i64,
+    db_pool: &PgPool,
+) -> Result<Vec<AuditEvent>, anyhow::Error> {
+    let (since, until) = filter.bounds();
+    let events = sqlx::query_as!(
+        AuditEvent,
+        r#"
+        SELECT occurred_at, actor_username, action, target, client_ip
+        FROM audit_log
+        WHERE ($1::TEXT IS NULL OR lower(actor_username) = lower($1))
+            AND ($2::TEXT IS NULL OR action = $2)
+            AND ($3::TIMESTAMPTZ IS NULL OR occurred_at >= $3)
+            AND ($4::TIMESTAMPTZ IS NULL OR occurred_at < $4)
+        ORDER BY occurred_at DESC
+        LIMIT $5
+        OFFSET $6
+    "#,
+        filter.actor,
+        filter.action.map(|action| action.as_str()),
+        since,
+        until,
+        limit,
+        offset
+    )
+    .fetch_all(db_pool)
+    .await
+    .context("Failed to fetch audit events.")?;
+
+    Ok(events)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{AuditAction, AuditLogFilter};
+    use chrono::NaiveDate;
+
+    #[test]
+    fn actions_round_trip_through_their_name() {
+        for action in AuditAction::ALL {
+            assert_eq!(AuditAction::parse(action.as_str()), Ok(action));
+        }
+        assert!(AuditAction::parse("launch_missiles").is_err());
+    }
+
+    #[test]
+    fn the_until_date_is_inclusive() {
+        let filter = AuditLogFilter {
+            since: NaiveDate::from_ymd_opt(2022, 7, 1),
+            until: NaiveDate::from_ymd_opt(2022, 7, 31),
+            ..Default::default()
+        };
+
+        let (since, until) = filter.bounds();
+
+        assert_eq!(since.unwrap().to_rfc3339(), "2022-07-01T00:00:00+00:00");
+        assert_eq!(until.unwrap().to_rfc3339(), "2022-08-01T00:00:00+00:00");
+    }
+}
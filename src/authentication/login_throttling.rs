@@ -0,0 +1,236 @@
+use std::time::Duration;
+
+use actix_web::HttpRequest;
+use anyhow::Context;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::configuration::LoginThrottlingSettings;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FailedLoginReason {
+    InvalidCredentials,
+    InvalidTwoFactorCode,
+    /// The attempt was refused without checking the credentials.
+    LockedOut,
+}
+
+impl FailedLoginReason {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            FailedLoginReason::InvalidCredentials => "invalid_credentials",
+            FailedLoginReason::InvalidTwoFactorCode => "invalid_two_factor_code",
+            FailedLoginReason::LockedOut => "locked_out",
+        }
+    }
+}
+
+/// Only trusts the forwarding headers when told to, see `LoginThrottlingSettings`.
+pub fn client_ip(request: &HttpRequest, settings: &LoginThrottlingSettings) -> String {
+    let connection_info = request.connection_info();
+    let client_ip = if settings.trust_forwarded_headers {
+        connection_info.realip_remote_addr()
+    } else {
+        connection_info.peer_addr()
+    };
+
+    client_ip.unwrap_or("unknown").to_string()
+}
+
+fn username_key(username: &str) -> String {
+    format!("username:{}", username)
+}
+
+fn ip_key(client_ip: &str) -> String {
+    format!("ip:{}", client_ip)
+}
+
+/// How long logins are refused for, either for `username` or from `client_ip`.
+#[tracing::instrument(name = "Get the login lockout", skip(db_pool))]
+pub async fn get_login_lockout(
+    username: &str,
+    client_ip: &str,
+    db_pool: &PgPool,
+) -> Result<Option<Duration>, anyhow::Error> {
+    let row = sqlx::query!(
+        r#"
+        SELECT EXTRACT(EPOCH FROM MAX(locked_until) - now())::FLOAT8 AS remaining_seconds
+        FROM login_throttles
+        WHERE throttle_key IN ($1, $2) AND locked_until > now()
+    "#,
+        username_key(username),
+        ip_key(client_ip)
+    )
+    .fetch_one(db_pool)
+    .await
+    .context("Failed fetching the login throttles.")?;
+
+    Ok(row
+        .remaining_seconds
+        .map(|remaining_seconds| Duration::from_secs_f64(remaining_seconds.max(0.0))))
+}
+
+/// Keeps an audit record of the attempt, and counts it against both the username and the client IP
+/// unless it was refused upfront: waiting out a lockout must not make it longer.
+/// The records of the attempts that can't count anymore, older than a lockout, are deleted
+/// on the way so that failing logins can't grow the table without bounds.
+#[tracing::instrument(name = "Record a failed login", skip(settings, db_pool))]
+pub async fn record_failed_login(
+    username: &str,
+    client_ip: &str,
+    reason: FailedLoginReason,
+    settings: &LoginThrottlingSettings,
+    db_pool: &PgPool,
+) -> Result<(), anyhow::Error> {
+    let mut transaction = db_pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool.")?;
+
+    sqlx::query!(
+        r#"
+        INSERT INTO failed_logins (id, username, client_ip, reason, attempted_at)
+        VALUES ($1, $2, $3, $4, now())
+    "#,
+        Uuid::new_v4(),
+        username,
+        client_ip,
+        reason.as_str()
+    )
+    .execute(&mut transaction)
+    .await
+    .context("Failed recording the failed login.")?;
+    sqlx::query!(
+        "DELETE FROM failed_logins WHERE attempted_at < now() - make_interval(secs => $1)",
+        settings.lockout_seconds as f64
+    )
+    .execute(&mut transaction)
+    .await
+    .context("Failed deleting the outdated failed logins.")?;
+
+    if reason != FailedLoginReason::LockedOut {
+        for (key, max_attempts) in [
+            (username_key(username), settings.max_attempts_per_username),
+            (ip_key(client_ip), settings.max_attempts_per_ip),
+        ] {
+            let n_failures = sqlx::query!(
+                r#"
+                INSERT INTO login_throttles (throttle_key, n_failures, last_failure_at)
+                VALUES ($1, 1, now())
+                ON CONFLICT (throttle_key) DO UPDATE
+                SET
+                    n_failures = CASE
+                        WHEN login_throttles.last_failure_at < now() - make_interval(secs => $2)
+                        THEN 1
+                        ELSE login_throttles.n_failures + 1
+                    END,
+                    last_failure_at = now()
+                RETURNING n_failures
+            "#,
+                key,
+                settings.lockout_seconds as f64
+            )
+            .fetch_one(&mut transaction)
+            .await
+            .context("Failed counting the failed login.")?
+            .n_failures;
+
+            let lockout = lockout_after(n_failures as u32, max_attempts, settings);
+            if !lockout.is_zero() {
+                sqlx::query!(
+                    r#"
+                    UPDATE login_throttles
+                    SET locked_until = now() + make_interval(secs => $2)
+                    WHERE throttle_key = $1
+                "#,
+                    key,
+                    lockout.as_secs_f64()
+                )
+                .execute(&mut transaction)
+                .await
+                .context("Failed locking logins out.")?;
+            }
+        }
+    }
+
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit the failed login.")?;
+    Ok(())
+}
+
+/// A successful login wipes the slate clean for the username, not for the client IP:
+/// an attacker could otherwise reset it by logging into their own account.
+#[tracing::instrument(name = "Clear the failed logins of a username", skip(db_pool))]
+pub async fn clear_failed_logins(username: &str, db_pool: &PgPool) -> Result<(), anyhow::Error> {
+    sqlx::query!(
+        "DELETE FROM login_throttles WHERE throttle_key = $1",
+        username_key(username)
+    )
+    .execute(db_pool)
+    .await
+    .context("Failed clearing the failed logins.")?;
+
+    Ok(())
+}
+
+/// Nothing for the first free attempts, then a delay doubling with every failure,
+/// up to a full lockout once `max_attempts` is reached.
+fn lockout_after(
+    n_failures: u32,
+    max_attempts: u32,
+    settings: &LoginThrottlingSettings,
+) -> Duration {
+    if n_failures >= max_attempts {
+        return settings.lockout();
+    }
+    if n_failures <= settings.free_attempts {
+        return Duration::ZERO;
+    }
+
+    let exponent = (n_failures - settings.free_attempts - 1).min(31);
+    Duration::from_secs(1 << exponent).min(settings.lockout())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::lockout_after;
+    use crate::configuration::LoginThrottlingSettings;
+    use std::time::Duration;
+
+    fn settings() -> LoginThrottlingSettings {
+        LoginThrottlingSettings {
+            free_attempts: 3,
+            max_attempts_per_username: 10,
+            max_attempts_per_ip: 50,
+            lockout_seconds: 900,
+            trust_forwarded_headers: false,
+        }
+    }
+
+    #[test]
+    fn the_first_failures_are_free() {
+        for n_failures in 0..=3 {
+            assert_eq!(lockout_after(n_failures, 10, &settings()), Duration::ZERO);
+        }
+    }
+
+    #[test]
+    fn the_delay_doubles_with_every_failure_after_that() {
+        assert_eq!(lockout_after(4, 10, &settings()), Duration::from_secs(1));
+        assert_eq!(lockout_after(5, 10, &settings()), Duration::from_secs(2));
+        assert_eq!(lockout_after(9, 10, &settings()), Duration::from_secs(32));
+    }
+
+    #[test]
+    fn reaching_the_maximum_locks_out() {
+        assert_eq!(lockout_after(10, 10, &settings()), Duration::from_secs(900));
+        assert_eq!(lockout_after(11, 10, &settings()), Duration::from_secs(900));
+    }
+
+    #[test]
+    fn delays_never_exceed_the_lockout() {
+        assert_eq!(lockout_after(49, 50, &settings()), Duration::from_secs(900));
+    }
+}
@@ -2,14 +2,17 @@ use actix_web::{
     body::MessageBody,
     dev::{ServiceRequest, ServiceResponse},
     error::InternalError,
-    FromRequest, HttpMessage,
+    web, FromRequest, HttpMessage,
 };
 use actix_web_lab::middleware::Next;
+use anyhow::Context;
+use sqlx::PgPool;
 use uuid::Uuid;
 
+use super::{touch_user_session, Role};
 use crate::{
     session_state::TypedSession,
-    utils::{e500, see_other},
+    utils::{e403, e500, see_other},
 };
 
 #[derive(Copy, Clone, Debug)]
@@ -29,6 +32,9 @@ impl std::ops::Deref for UserId {
     }
 }
 
+/// Lets logged in users through, with their `UserId` and `Role` in the request extensions.
+/// Users that have been deleted or disabled since they logged in are logged out,
+/// as are the sessions that have been revoked.
 pub async fn reject_anonymous_users(
     mut req: ServiceRequest,
     next: Next<impl MessageBody>,
@@ -38,15 +44,87 @@ pub async fn reject_anonymous_users(
         TypedSession::from_request(http_request, payload).await
     }?;
 
-    match session.get_user_id().map_err(e500)? {
-        Some(user_id) => {
+    let user_id = match session.get_user_id().map_err(e500)? {
+        Some(user_id) => user_id,
+        None => return Err(redirect_to_login("The user has not logged in.")),
+    };
+
+    let db_pool = req
+        .app_data::<web::Data<PgPool>>()
+        .context("The database pool is missing from the application data.")
+        .map_err(e500)?;
+    let is_active_session = match session.get_session_id().map_err(e500)? {
+        Some(session_id) => touch_user_session(user_id, session_id, db_pool)
+            .await
+            .map_err(e500)?,
+        None => false,
+    };
+    if !is_active_session {
+        session.log_out();
+        return Err(redirect_to_login("The session has been revoked."));
+    }
+
+    match get_active_role(user_id, db_pool).await.map_err(e500)? {
+        Some(role) => {
             req.extensions_mut().insert(UserId(user_id));
+            req.extensions_mut().insert(role);
             next.call(req).await
         }
         None => {
-            let response = see_other("/login");
-            let e = anyhow::anyhow!("The user has not logged in.");
-            Err(InternalError::from_response(e, response).into())
+            session.log_out();
+            Err(redirect_to_login("The user has been disabled or deleted."))
         }
     }
 }
+
+/// Must be wrapped inside `reject_anonymous_users`.
+pub async fn require_editor(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, actix_web::Error> {
+    require_role(Role::Editor, &req)?;
+    next.call(req).await
+}
+
+/// Must be wrapped inside `reject_anonymous_users`.
+pub async fn require_owner(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, actix_web::Error> {
+    require_role(Role::Owner, &req)?;
+    next.call(req).await
+}
+
+fn require_role(required: Role, req: &ServiceRequest) -> Result<(), actix_web::Error> {
+    let role = req
+        .extensions()
+        .get::<Role>()
+        .copied()
+        .context("The role of the user is missing, is `reject_anonymous_users` in place?")
+        .map_err(e500)?;
+
+    if role < required {
+        return Err(e403(format!("This action requires the {} role.", required)));
+    }
+    Ok(())
+}
+
+fn redirect_to_login(reason: &'static str) -> actix_web::Error {
+    let response = see_other("/login");
+    let e = anyhow::anyhow!(reason);
+    InternalError::from_response(e, response).into()
+}
+
+#[tracing::instrument(name = "Get the role of an active user", skip(db_pool))]
+async fn get_active_role(user_id: Uuid, db_pool: &PgPool) -> Result<Option<Role>, anyhow::Error> {
+    let row = sqlx::query!(
+        "SELECT role FROM users WHERE id = $1 AND NOT disabled",
+        user_id
+    )
+    .fetch_optional(db_pool)
+    .await
+    .context("Failed fetching the role of the user.")?;
+
+    row.map(|row| Role::parse(&row.role).map_err(anyhow::Error::msg))
+        .transpose()
+}
@@ -1,6 +1,17 @@
+mod login_throttling;
 mod middleware;
 mod password;
+mod password_reset;
+mod role;
+mod two_factor;
+mod user_sessions;
+mod users;
 
-pub use middleware::reject_anonymous_users;
-pub use middleware::UserId;
+pub use login_throttling::*;
+pub use middleware::{reject_anonymous_users, require_editor, require_owner, UserId};
 pub use password::*;
+pub use password_reset::*;
+pub use role::Role;
+pub use two_factor::*;
+pub use user_sessions::*;
+pub use users::*;
@@ -5,7 +5,7 @@ use argon2::{
 };
 use rand::rngs::OsRng;
 use secrecy::{ExposeSecret, Secret};
-use sqlx::PgPool;
+use sqlx::{PgExecutor, PgPool};
 use uuid::Uuid;
 
 use crate::telemetry::spawn_blocking_with_tracing;
@@ -63,7 +63,7 @@ pub async fn get_stored_user_credentials(
     pool: &PgPool,
 ) -> Result<Option<(uuid::Uuid, Secret<String>)>, anyhow::Error> {
     let row = sqlx::query!(
-        r#"SELECT id, password_hash FROM users WHERE username = $1"#,
+        r#"SELECT id, password_hash FROM users WHERE username = $1 AND NOT disabled"#,
         username
     )
     .fetch_optional(pool)
@@ -80,16 +80,30 @@ pub async fn change_password(
     new_password: Secret<String>,
     db_pool: &PgPool,
 ) -> Result<(), anyhow::Error> {
-    let password_hash = spawn_blocking_with_tracing(move || compute_password_hash(new_password))
+    let password_hash = hash_password(new_password).await?;
+    store_password_hash(db_pool, user_id, &password_hash).await
+}
+
+/// Hashing takes a while, do it before starting a transaction to store the hash in.
+pub async fn hash_password(password: Secret<String>) -> Result<Secret<String>, anyhow::Error> {
+    spawn_blocking_with_tracing(move || compute_password_hash(password))
         .await?
-        .context("Failed computing hash for the new password.")?;
+        .context("Failed computing hash for the new password.")
+}
 
+/// Pass a transaction as the executor to change the password along with other changes.
+#[tracing::instrument(name = "Store a password hash", skip(executor, password_hash))]
+pub async fn store_password_hash<'c>(
+    executor: impl PgExecutor<'c>,
+    user_id: Uuid,
+    password_hash: &Secret<String>,
+) -> Result<(), anyhow::Error> {
     sqlx::query!(
         r#"UPDATE users SET password_hash = $1 WHERE id = $2"#,
         password_hash.expose_secret(),
         user_id
     )
-    .execute(db_pool)
+    .execute(executor)
     .await
     .context("Failed changing user's password.")?;
 
@@ -116,7 +130,12 @@ pub fn verify_password_hash(
         .map_err(AuthError::InvalidCredentials)
 }
 
-fn compute_password_hash(password: Secret<String>) -> Result<Secret<String>, anyhow::Error> {
+/// Passwords must be between 12 and 128 characters long.
+pub fn is_valid_password_length(password: &Secret<String>) -> bool {
+    (12..=128).contains(&password.expose_secret().chars().count())
+}
+
+pub fn compute_password_hash(password: Secret<String>) -> Result<Secret<String>, anyhow::Error> {
     let salt = SaltString::generate(&mut OsRng);
     let password_hash = Argon2::new(
         Algorithm::Argon2id,
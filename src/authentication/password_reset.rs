@@ -0,0 +1,182 @@
+use anyhow::Context;
+use rand::{distributions::Alphanumeric, rngs::OsRng, Rng};
+use secrecy::{ExposeSecret, Secret};
+use sha2::{Digest, Sha256};
+use sqlx::PgPool;
+
+use super::password::{hash_password, store_password_hash};
+use crate::domains::SubscriberEmail;
+
+/// How long a reset link can be used for.
+const TOKEN_TTL_SECONDS: f64 = 60.0 * 60.0;
+/// At most one reset email per user in this long, so that the form can't be used to flood
+/// their inbox.
+const RESEND_COOLDOWN_SECONDS: f64 = 60.0;
+
+pub struct PasswordResetToken {
+    pub username: String,
+    pub token: Secret<String>,
+}
+
+/// The tokens are random enough that a fast hash is enough to protect them.
+fn hash_token(token: &str) -> String {
+    hex::encode(Sha256::digest(token.as_bytes()))
+}
+
+/// Returns `None` when no enabled user has this email address,
+/// or when one has been sent a reset link moments ago.
+#[tracing::instrument(name = "Create a password reset token", skip(db_pool))]
+pub async fn create_password_reset_token(
+    email: &SubscriberEmail,
+    db_pool: &PgPool,
+) -> Result<Option<PasswordResetToken>, anyhow::Error> {
+    let mut transaction = db_pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool.")?;
+
+    let user = sqlx::query!(
+        r#"
+        SELECT id, username FROM users
+        WHERE lower(email) = lower($1) AND NOT disabled
+        FOR UPDATE
+    "#,
+        email.as_ref()
+    )
+    .fetch_optional(&mut transaction)
+    .await
+    .context("Failed fetching the user by email address.")?;
+    let user = match user {
+        Some(user) => user,
+        None => return Ok(None),
+    };
+
+    let was_sent_recently = sqlx::query!(
+        r#"
+        SELECT EXISTS (
+            SELECT 1 FROM password_reset_tokens
+            WHERE user_id = $1 AND created_at > now() - make_interval(secs => $2)
+        ) AS "was_sent_recently!"
+    "#,
+        user.id,
+        RESEND_COOLDOWN_SECONDS
+    )
+    .fetch_one(&mut transaction)
+    .await
+    .context("Failed fetching the recent password reset tokens.")?
+    .was_sent_recently;
+    if was_sent_recently {
+        return Ok(None);
+    }
+
+    let token: String = OsRng
+        .sample_iter(&Alphanumeric)
+        .map(char::from)
+        .take(32)
+        .collect();
+    sqlx::query!(
+        r#"
+        INSERT INTO password_reset_tokens (token_hash, user_id, created_at, expires_at)
+        VALUES ($1, $2, now(), now() + make_interval(secs => $3))
+    "#,
+        hash_token(&token),
+        user.id,
+        TOKEN_TTL_SECONDS
+    )
+    .execute(&mut transaction)
+    .await
+    .context("Failed saving the password reset token.")?;
+
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit the password reset token.")?;
+    Ok(Some(PasswordResetToken {
+        username: user.username,
+        token: Secret::new(token),
+    }))
+}
+
+#[tracing::instrument(name = "Check a password reset token", skip(token, db_pool))]
+pub async fn is_valid_password_reset_token(
+    token: &str,
+    db_pool: &PgPool,
+) -> Result<bool, anyhow::Error> {
+    let is_valid = sqlx::query!(
+        r#"
+        SELECT EXISTS (
+            SELECT 1 FROM password_reset_tokens WHERE token_hash = $1 AND expires_at > now()
+        ) AS "is_valid!"
+    "#,
+        hash_token(token)
+    )
+    .fetch_one(db_pool)
+    .await
+    .context("Failed fetching the password reset token.")?
+    .is_valid;
+
+    Ok(is_valid)
+}
+
+/// Uses the token up, along with every other reset token of the user,
+/// and logs them out of every session: whoever got hold of the old password is locked out.
+/// Returns the username, or `None` if the token is unknown or has expired.
+/// The new password is only hashed for a valid token, invalid ones can't make us do the work.
+#[tracing::instrument(
+    name = "Reset a password with a token",
+    skip(token, new_password, db_pool)
+)]
+pub async fn reset_password_with_token(
+    token: &Secret<String>,
+    new_password: Secret<String>,
+    db_pool: &PgPool,
+) -> Result<Option<String>, anyhow::Error> {
+    if !is_valid_password_reset_token(token.expose_secret(), db_pool).await? {
+        return Ok(None);
+    }
+    let password_hash = hash_password(new_password).await?;
+
+    // The token is checked again, it might have been used or expired meanwhile
+    let mut transaction = db_pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool.")?;
+
+    // Locked until the transaction ends, so that the token can't be used twice concurrently
+    let user = sqlx::query!(
+        r#"
+        SELECT users.id, users.username
+        FROM password_reset_tokens
+        JOIN users ON users.id = password_reset_tokens.user_id
+        WHERE token_hash = $1 AND expires_at > now()
+        FOR UPDATE OF password_reset_tokens
+    "#,
+        hash_token(token.expose_secret())
+    )
+    .fetch_optional(&mut transaction)
+    .await
+    .context("Failed fetching the password reset token.")?;
+    let user = match user {
+        Some(user) => user,
+        None => return Ok(None),
+    };
+
+    store_password_hash(&mut transaction, user.id, &password_hash).await?;
+    sqlx::query!(
+        "DELETE FROM password_reset_tokens WHERE user_id = $1",
+        user.id
+    )
+    .execute(&mut transaction)
+    .await
+    .context("Failed using up the password reset tokens.")?;
+    sqlx::query!("DELETE FROM user_sessions WHERE user_id = $1", user.id)
+        .execute(&mut transaction)
+        .await
+        .context("Failed revoking the sessions of the user.")?;
+
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit the use of the password reset token.")?;
+    Ok(Some(user.username))
+}
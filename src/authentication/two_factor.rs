@@ -0,0 +1,359 @@
+use anyhow::Context;
+use data_encoding::BASE32_NOPAD;
+use hmac::{Hmac, Mac};
+use rand::{rngs::OsRng, Rng, RngCore};
+use secrecy::{ExposeSecret, Secret};
+use sha1::Sha1;
+use sha2::{Digest, Sha256};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+const ISSUER: &str = "Rusty Mailer";
+const N_DIGITS: u32 = 6;
+const STEP_SECONDS: i64 = 30;
+/// Codes of the previous and next time steps are accepted as well,
+/// to make up for the clock of the user's device being slightly off.
+const ALLOWED_DRIFT: i64 = 1;
+const N_RECOVERY_CODES: usize = 10;
+/// Leaves out the characters that are easily mistaken for one another (0/o, 1/l).
+const RECOVERY_CODE_ALPHABET: &[u8] = b"23456789abcdefghijkmnpqrstuvwxyz";
+
+/// The shared secret of a time-based one-time password (RFC 6238),
+/// with the defaults every authenticator app supports: HMAC-SHA1, 6 digits, 30 seconds.
+#[derive(Debug)]
+pub struct TotpSecret(Secret<String>);
+
+impl TotpSecret {
+    pub fn generate() -> Self {
+        let mut key = [0u8; 20];
+        OsRng.fill_bytes(&mut key);
+        Self(Secret::new(BASE32_NOPAD.encode(&key)))
+    }
+
+    pub fn parse(base32: String) -> Result<Self, String> {
+        BASE32_NOPAD
+            .decode(base32.as_bytes())
+            .map_err(|_| "The TOTP secret is not valid base32.".to_string())?;
+        Ok(Self(Secret::new(base32)))
+    }
+
+    /// The secret as authenticator apps expect it to be typed in.
+    pub fn expose_base32(&self) -> &str {
+        self.0.expose_secret()
+    }
+
+    /// What authenticator apps scan, see
+    /// https://github.com/google/google-authenticator/wiki/Key-Uri-Format
+    pub fn otpauth_uri(&self, username: &str) -> String {
+        format!(
+            "otpauth://totp/{issuer}:{username}?secret={secret}&issuer={issuer}",
+            issuer = urlencoding::encode(ISSUER),
+            username = urlencoding::encode(username),
+            secret = self.expose_base32(),
+        )
+    }
+
+    /// The code an authenticator app shows at `unix_time`.
+    pub fn code(&self, unix_time: i64) -> String {
+        self.code_at(unix_time.div_euclid(STEP_SECONDS))
+    }
+
+    /// Returns the time step `code` belongs to, if it is valid at `unix_time`.
+    pub fn verify(&self, code: &str, unix_time: i64) -> Option<i64> {
+        let code = code.trim();
+        if code.len() != N_DIGITS as usize {
+            return None;
+        }
+        let current_step = unix_time.div_euclid(STEP_SECONDS);
+        (current_step - ALLOWED_DRIFT..=current_step + ALLOWED_DRIFT)
+            .find(|step| self.code_at(*step) == code)
+    }
+
+    fn code_at(&self, step: i64) -> String {
+        let key = BASE32_NOPAD
+            .decode(self.expose_base32().as_bytes())
+            .expect("The TOTP secret was checked when parsed.");
+        let mut mac = Hmac::<Sha1>::new_from_slice(&key).expect("HMAC can take a key of any size.");
+        mac.update(&step.to_be_bytes());
+        let hash = mac.finalize().into_bytes();
+
+        // Dynamic truncation, RFC 4226 section 5.3
+        let offset = (hash[hash.len() - 1] & 0x0f) as usize;
+        let binary = u32::from_be_bytes(hash[offset..offset + 4].try_into().unwrap()) & 0x7fff_ffff;
+        format!(
+            "{:0width$}",
+            binary % 10u32.pow(N_DIGITS),
+            width = N_DIGITS as usize
+        )
+    }
+}
+
+/// One-off codes to log in with when the authenticator app is lost.
+pub fn generate_recovery_codes() -> Vec<String> {
+    let mut rng = OsRng;
+    let mut chunk = || -> String {
+        (0..5)
+            .map(|_| RECOVERY_CODE_ALPHABET[rng.gen_range(0..RECOVERY_CODE_ALPHABET.len())] as char)
+            .collect()
+    };
+
+    (0..N_RECOVERY_CODES)
+        .map(|_| format!("{}-{}", chunk(), chunk()))
+        .collect()
+}
+
+/// Recovery codes are random enough that a fast hash is enough to protect them.
+fn hash_recovery_code(code: &str) -> String {
+    hex::encode(Sha256::digest(code.trim().to_lowercase().as_bytes()))
+}
+
+#[tracing::instrument(name = "Get the TOTP secret of a user", skip(db_pool))]
+pub async fn get_totp_secret(
+    user_id: Uuid,
+    db_pool: &PgPool,
+) -> Result<Option<TotpSecret>, anyhow::Error> {
+    let row = sqlx::query!("SELECT totp_secret FROM users WHERE id = $1", user_id)
+        .fetch_optional(db_pool)
+        .await
+        .context("Failed fetching the TOTP secret of the user.")?;
+
+    row.and_then(|row| row.totp_secret)
+        .map(|secret| TotpSecret::parse(secret).map_err(anyhow::Error::msg))
+        .transpose()
+}
+
+/// Turns two-factor authentication on, replacing the previous recovery codes if any.
+/// `verified_step` is the time step of the code the user set it up with, it can't be used again.
+#[tracing::instrument(
+    name = "Save the two-factor authentication settings of a user",
+    skip(secret, recovery_codes, db_pool)
+)]
+pub async fn save_two_factor_settings(
+    user_id: Uuid,
+    secret: &TotpSecret,
+    verified_step: i64,
+    recovery_codes: &[String],
+    db_pool: &PgPool,
+) -> Result<(), anyhow::Error> {
+    let mut transaction = db_pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool.")?;
+
+    sqlx::query!(
+        r#"
+        UPDATE users
+        SET totp_secret = $2, totp_last_used_step = $3
+        WHERE id = $1
+    "#,
+        user_id,
+        secret.expose_base32(),
+        verified_step
+    )
+    .execute(&mut transaction)
+    .await
+    .context("Failed saving the TOTP secret.")?;
+
+    sqlx::query!("DELETE FROM recovery_codes WHERE user_id = $1", user_id)
+        .execute(&mut transaction)
+        .await
+        .context("Failed deleting the previous recovery codes.")?;
+    let code_hashes: Vec<String> = recovery_codes
+        .iter()
+        .map(|code| hash_recovery_code(code))
+        .collect();
+    sqlx::query!(
+        r#"
+        INSERT INTO recovery_codes (user_id, code_hash)
+        SELECT $1, code_hash FROM UNNEST($2::TEXT[]) AS code_hash
+    "#,
+        user_id,
+        &code_hashes
+    )
+    .execute(&mut transaction)
+    .await
+    .context("Failed saving the recovery codes.")?;
+
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit the two-factor authentication settings.")?;
+    Ok(())
+}
+
+#[tracing::instrument(
+    name = "Remove the two-factor authentication settings of a user",
+    skip(db_pool)
+)]
+pub async fn remove_two_factor_settings(
+    user_id: Uuid,
+    db_pool: &PgPool,
+) -> Result<(), anyhow::Error> {
+    let mut transaction = db_pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool.")?;
+
+    sqlx::query!(
+        "UPDATE users SET totp_secret = NULL, totp_last_used_step = NULL WHERE id = $1",
+        user_id
+    )
+    .execute(&mut transaction)
+    .await
+    .context("Failed removing the TOTP secret.")?;
+    sqlx::query!("DELETE FROM recovery_codes WHERE user_id = $1", user_id)
+        .execute(&mut transaction)
+        .await
+        .context("Failed deleting the recovery codes.")?;
+
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit the two-factor authentication settings.")?;
+    Ok(())
+}
+
+/// Accepts either a code from the authenticator app, which can't be used twice,
+/// or one of the recovery codes, which is used up.
+#[tracing::instrument(name = "Verify a second factor", skip(code, db_pool))]
+pub async fn verify_second_factor(
+    user_id: Uuid,
+    code: &str,
+    db_pool: &PgPool,
+) -> Result<bool, anyhow::Error> {
+    let mut transaction = db_pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool.")?;
+
+    let row = sqlx::query!(
+        "SELECT totp_secret, totp_last_used_step FROM users WHERE id = $1 FOR UPDATE",
+        user_id
+    )
+    .fetch_optional(&mut transaction)
+    .await
+    .context("Failed fetching the TOTP secret of the user.")?;
+    let (secret, last_used_step) = match row {
+        Some(row) => match row.totp_secret {
+            Some(secret) => (
+                TotpSecret::parse(secret).map_err(anyhow::Error::msg)?,
+                row.totp_last_used_step,
+            ),
+            None => return Ok(false),
+        },
+        None => return Ok(false),
+    };
+
+    let step = secret
+        .verify(code, chrono::Utc::now().timestamp())
+        .filter(|step| last_used_step.is_none_or(|last_used_step| *step > last_used_step));
+    let is_valid = match step {
+        Some(step) => {
+            sqlx::query!(
+                "UPDATE users SET totp_last_used_step = $2 WHERE id = $1",
+                user_id,
+                step
+            )
+            .execute(&mut transaction)
+            .await
+            .context("Failed recording the use of the TOTP code.")?;
+            true
+        }
+        None => {
+            sqlx::query!(
+                "DELETE FROM recovery_codes WHERE user_id = $1 AND code_hash = $2",
+                user_id,
+                hash_recovery_code(code)
+            )
+            .execute(&mut transaction)
+            .await
+            .context("Failed using up the recovery code.")?
+            .rows_affected()
+                == 1
+        }
+    };
+
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit the use of the second factor.")?;
+    Ok(is_valid)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{generate_recovery_codes, hash_recovery_code, TotpSecret};
+    use claim::{assert_none, assert_some_eq};
+    use data_encoding::BASE32_NOPAD;
+
+    /// The SHA1 secret of RFC 6238's test vectors
+    fn rfc_secret() -> TotpSecret {
+        TotpSecret::parse(BASE32_NOPAD.encode(b"12345678901234567890")).unwrap()
+    }
+
+    #[test]
+    fn codes_match_the_rfc_test_vectors() {
+        // The last 6 digits of the 8 digit codes in RFC 6238, appendix B
+        let secret = rfc_secret();
+        for (unix_time, code) in [
+            (59, "287082"),
+            (1111111109, "081804"),
+            (1111111111, "050471"),
+            (1234567890, "005924"),
+            (2000000000, "279037"),
+        ] {
+            assert_some_eq!(secret.verify(code, unix_time), unix_time / 30);
+        }
+    }
+
+    #[test]
+    fn codes_of_the_neighbouring_time_steps_are_accepted() {
+        let secret = rfc_secret();
+
+        assert_some_eq!(secret.verify("287082", 59 + 30), 1);
+        assert_some_eq!(secret.verify("287082", 59 - 30), 1);
+        assert_none!(secret.verify("287082", 59 + 60));
+    }
+
+    #[test]
+    fn malformed_codes_are_rejected() {
+        let secret = rfc_secret();
+
+        assert_none!(secret.verify("", 59));
+        assert_none!(secret.verify("28708", 59));
+        assert_none!(secret.verify("2870820", 59));
+    }
+
+    #[test]
+    fn the_otpauth_uri_carries_the_secret_and_the_account() {
+        let secret = TotpSecret::generate();
+
+        let uri = secret.otpauth_uri("ursula le guin");
+
+        assert_eq!(
+            uri,
+            format!(
+                "otpauth://totp/Rusty%20Mailer:ursula%20le%20guin?secret={}&issuer=Rusty%20Mailer",
+                secret.expose_base32()
+            )
+        );
+    }
+
+    #[test]
+    fn recovery_codes_are_unique_and_forgiving_about_case() {
+        let codes = generate_recovery_codes();
+
+        assert_eq!(codes.len(), 10);
+        assert!(codes
+            .iter()
+            .all(|code| code.len() == 11 && code.as_bytes()[5] == b'-'));
+        let mut unique = codes.clone();
+        unique.sort();
+        unique.dedup();
+        assert_eq!(unique.len(), codes.len());
+        assert_eq!(
+            hash_recovery_code(&codes[0]),
+            hash_recovery_code(&format!(" {} ", codes[0].to_uppercase()))
+        );
+    }
+}
@@ -0,0 +1,151 @@
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+/// Sessions expire from Redis after a day without changes (actix-session's default),
+/// the ones that haven't been seen for that long are gone.
+const IDLE_TIMEOUT_SECONDS: f64 = 24.0 * 60.0 * 60.0;
+
+pub struct UserSession {
+    pub id: Uuid,
+    pub created_at: DateTime<Utc>,
+    pub last_seen_at: DateTime<Utc>,
+    pub client_ip: String,
+    pub user_agent: String,
+}
+
+/// Records a new login of the user, and forgets their sessions that have expired meanwhile.
+#[tracing::instrument(name = "Start a user session", skip(db_pool))]
+pub async fn start_user_session(
+    user_id: Uuid,
+    client_ip: &str,
+    user_agent: &str,
+    db_pool: &PgPool,
+) -> Result<Uuid, anyhow::Error> {
+    let mut transaction = db_pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool.")?;
+
+    sqlx::query!(
+        r#"
+        DELETE FROM user_sessions
+        WHERE user_id = $1 AND last_seen_at < now() - make_interval(secs => $2)
+    "#,
+        user_id,
+        IDLE_TIMEOUT_SECONDS
+    )
+    .execute(&mut transaction)
+    .await
+    .context("Failed deleting the expired user sessions.")?;
+
+    let session_id = Uuid::new_v4();
+    sqlx::query!(
+        r#"
+        INSERT INTO user_sessions (id, user_id, created_at, last_seen_at, client_ip, user_agent)
+        VALUES ($1, $2, now(), now(), $3, $4)
+    "#,
+        session_id,
+        user_id,
+        client_ip,
+        user_agent
+    )
+    .execute(&mut transaction)
+    .await
+    .context("Failed saving the user session.")?;
+
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit the user session.")?;
+    Ok(session_id)
+}
+
+/// Returns `false` if the session has been revoked or has expired.
+#[tracing::instrument(name = "Touch a user session", skip(db_pool))]
+pub async fn touch_user_session(
+    user_id: Uuid,
+    session_id: Uuid,
+    db_pool: &PgPool,
+) -> Result<bool, anyhow::Error> {
+    let n_updated = sqlx::query!(
+        r#"
+        UPDATE user_sessions
+        SET last_seen_at = now()
+        WHERE id = $1 AND user_id = $2 AND last_seen_at >= now() - make_interval(secs => $3)
+    "#,
+        session_id,
+        user_id,
+        IDLE_TIMEOUT_SECONDS
+    )
+    .execute(db_pool)
+    .await
+    .context("Failed updating the user session.")?
+    .rows_affected();
+
+    Ok(n_updated == 1)
+}
+
+/// The sessions of the user that haven't expired, most recently seen first.
+#[tracing::instrument(name = "List the sessions of a user", skip(db_pool))]
+pub async fn list_user_sessions(
+    user_id: Uuid,
+    db_pool: &PgPool,
+) -> Result<Vec<UserSession>, anyhow::Error> {
+    let sessions = sqlx::query_as!(
+        UserSession,
+        r#"
+        SELECT id, created_at, last_seen_at, client_ip, user_agent
+        FROM user_sessions
+        WHERE user_id = $1 AND last_seen_at >= now() - make_interval(secs => $2)
+        ORDER BY last_seen_at DESC
+    "#,
+        user_id,
+        IDLE_TIMEOUT_SECONDS
+    )
+    .fetch_all(db_pool)
+    .await
+    .context("Failed fetching the sessions of the user.")?;
+
+    Ok(sessions)
+}
+
+/// Returns `false` if the user has no such session.
+#[tracing::instrument(name = "Revoke a user session", skip(db_pool))]
+pub async fn revoke_user_session(
+    user_id: Uuid,
+    session_id: Uuid,
+    db_pool: &PgPool,
+) -> Result<bool, anyhow::Error> {
+    let n_deleted = sqlx::query!(
+        "DELETE FROM user_sessions WHERE id = $1 AND user_id = $2",
+        session_id,
+        user_id
+    )
+    .execute(db_pool)
+    .await
+    .context("Failed deleting the user session.")?
+    .rows_affected();
+
+    Ok(n_deleted == 1)
+}
+
+/// Logs the user out everywhere but in `current_session_id`.
+#[tracing::instrument(name = "Revoke the other sessions of a user", skip(db_pool))]
+pub async fn revoke_other_user_sessions(
+    user_id: Uuid,
+    current_session_id: Uuid,
+    db_pool: &PgPool,
+) -> Result<(), anyhow::Error> {
+    sqlx::query!(
+        "DELETE FROM user_sessions WHERE user_id = $1 AND id <> $2",
+        user_id,
+        current_session_id
+    )
+    .execute(db_pool)
+    .await
+    .context("Failed deleting the other sessions of the user.")?;
+
+    Ok(())
+}
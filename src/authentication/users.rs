@@ -0,0 +1,306 @@
+use anyhow::Context;
+use secrecy::{ExposeSecret, Secret};
+use sqlx::{PgPool, Postgres, Transaction};
+use uuid::Uuid;
+
+use super::password::{
+    compute_password_hash, hash_password, is_valid_password_length, store_password_hash,
+};
+use super::Role;
+use crate::{domains::SubscriberEmail, telemetry::spawn_blocking_with_tracing};
+
+#[derive(thiserror::Error, Debug)]
+pub enum UserManagementError {
+    #[error("Usernames can't be empty.")]
+    InvalidUsername,
+    #[error("Passwords must be between 12 and 128 characters long.")]
+    InvalidPassword,
+    #[error("There is already a user named {0}.")]
+    UsernameTaken(String),
+    #[error("Another user already has this email address.")]
+    EmailTaken,
+    #[error("There is no such user.")]
+    UnknownUser,
+    #[error("There must be at least one enabled owner left.")]
+    LastOwner,
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+pub struct User {
+    pub id: Uuid,
+    pub username: String,
+    /// Where password reset links are sent.
+    pub email: Option<String>,
+    pub role: Role,
+    pub disabled: bool,
+}
+
+#[tracing::instrument(name = "Create a user", skip(password, db_pool))]
+pub async fn create_user(
+    username: &str,
+    password: Secret<String>,
+    role: Role,
+    db_pool: &PgPool,
+) -> Result<Uuid, UserManagementError> {
+    let username = username.trim();
+    if username.is_empty() {
+        return Err(UserManagementError::InvalidUsername);
+    }
+    if !is_valid_password_length(&password) {
+        return Err(UserManagementError::InvalidPassword);
+    }
+
+    let password_hash = spawn_blocking_with_tracing(move || compute_password_hash(password))
+        .await
+        .context("Failed to spawn blocking task.")?
+        .context("Failed computing hash for the password.")?;
+
+    let user_id = Uuid::new_v4();
+    let n_inserted = sqlx::query!(
+        r#"
+        INSERT INTO users (id, username, password_hash, role)
+        VALUES ($1, $2, $3, $4)
+        ON CONFLICT (username) DO NOTHING
+    "#,
+        user_id,
+        username,
+        password_hash.expose_secret(),
+        role.as_str()
+    )
+    .execute(db_pool)
+    .await
+    .context("Failed inserting the new user.")?
+    .rows_affected();
+
+    if n_inserted == 0 {
+        return Err(UserManagementError::UsernameTaken(username.into()));
+    }
+    Ok(user_id)
+}
+
+#[tracing::instrument(name = "List users", skip(db_pool))]
+pub async fn list_users(db_pool: &PgPool) -> Result<Vec<User>, anyhow::Error> {
+    let rows =
+        sqlx::query!("SELECT id, username, email, role, disabled FROM users ORDER BY username")
+            .fetch_all(db_pool)
+            .await
+            .context("Failed fetching the users.")?;
+
+    rows.into_iter()
+        .map(|row| {
+            Ok(User {
+                id: row.id,
+                username: row.username,
+                email: row.email,
+                role: Role::parse(&row.role).map_err(anyhow::Error::msg)?,
+                disabled: row.disabled,
+            })
+        })
+        .collect()
+}
+
+#[tracing::instrument(name = "Find a user by username", skip(db_pool))]
+pub async fn find_user_id(username: &str, db_pool: &PgPool) -> Result<Uuid, UserManagementError> {
+    let row = sqlx::query!("SELECT id FROM users WHERE username = $1", username)
+        .fetch_optional(db_pool)
+        .await
+        .context("Failed fetching the user.")?
+        .ok_or(UserManagementError::UnknownUser)?;
+
+    Ok(row.id)
+}
+
+/// The user is logged out of every session, along with the password they may have leaked.
+#[tracing::instrument(name = "Reset the password of a user", skip(password, db_pool))]
+pub async fn reset_password(
+    user_id: Uuid,
+    password: Secret<String>,
+    db_pool: &PgPool,
+) -> Result<(), UserManagementError> {
+    if !is_valid_password_length(&password) {
+        return Err(UserManagementError::InvalidPassword);
+    }
+    find_user(user_id, db_pool).await?;
+
+    let password_hash = hash_password(password).await?;
+    let mut transaction = begin(db_pool).await?;
+    store_password_hash(&mut transaction, user_id, &password_hash).await?;
+    sqlx::query!("DELETE FROM user_sessions WHERE user_id = $1", user_id)
+        .execute(&mut transaction)
+        .await
+        .context("Failed revoking the sessions of the user.")?;
+
+    commit(transaction).await
+}
+
+#[tracing::instrument(name = "Change the role of a user", skip(db_pool))]
+pub async fn set_role(
+    user_id: Uuid,
+    role: Role,
+    db_pool: &PgPool,
+) -> Result<(), UserManagementError> {
+    let mut transaction = begin(db_pool).await?;
+    if role != Role::Owner {
+        ensure_not_the_last_owner(user_id, &mut transaction).await?;
+    }
+
+    sqlx::query!(
+        "UPDATE users SET role = $2 WHERE id = $1",
+        user_id,
+        role.as_str()
+    )
+    .execute(&mut transaction)
+    .await
+    .context("Failed changing the role of the user.")?;
+
+    commit(transaction).await
+}
+
+/// `None` removes the email address, and with it the ability to reset the password by email.
+#[tracing::instrument(name = "Change the email address of a user", skip(db_pool))]
+pub async fn set_email(
+    user_id: Uuid,
+    email: Option<&SubscriberEmail>,
+    db_pool: &PgPool,
+) -> Result<(), UserManagementError> {
+    let mut transaction = begin(db_pool).await?;
+    let email = email.map(|email| email.as_ref());
+
+    if let Some(email) = email {
+        let is_taken = sqlx::query!(
+            r#"
+            SELECT EXISTS (
+                SELECT 1 FROM users WHERE lower(email) = lower($1) AND id <> $2
+            ) AS "is_taken!"
+        "#,
+            email,
+            user_id
+        )
+        .fetch_one(&mut transaction)
+        .await
+        .context("Failed checking whether the email address is taken.")?
+        .is_taken;
+        if is_taken {
+            return Err(UserManagementError::EmailTaken);
+        }
+    }
+
+    let n_updated = sqlx::query!("UPDATE users SET email = $2 WHERE id = $1", user_id, email)
+        .execute(&mut transaction)
+        .await
+        .context("Failed changing the email address of the user.")?
+        .rows_affected();
+    if n_updated == 0 {
+        return Err(UserManagementError::UnknownUser);
+    }
+
+    commit(transaction).await
+}
+
+/// Disabled users can't log in, and are logged out of their current sessions.
+#[tracing::instrument(name = "Enable or disable a user", skip(db_pool))]
+pub async fn set_disabled(
+    user_id: Uuid,
+    disabled: bool,
+    db_pool: &PgPool,
+) -> Result<(), UserManagementError> {
+    let mut transaction = begin(db_pool).await?;
+    if disabled {
+        ensure_not_the_last_owner(user_id, &mut transaction).await?;
+    }
+
+    sqlx::query!(
+        "UPDATE users SET disabled = $2 WHERE id = $1",
+        user_id,
+        disabled
+    )
+    .execute(&mut transaction)
+    .await
+    .context("Failed updating the user.")?;
+
+    commit(transaction).await
+}
+
+#[tracing::instrument(name = "Delete a user", skip(db_pool))]
+pub async fn delete_user(user_id: Uuid, db_pool: &PgPool) -> Result<(), UserManagementError> {
+    let mut transaction = begin(db_pool).await?;
+    ensure_not_the_last_owner(user_id, &mut transaction).await?;
+
+    sqlx::query!("DELETE FROM idempotency WHERE user_id = $1", user_id)
+        .execute(&mut transaction)
+        .await
+        .context("Failed deleting the saved responses of the user.")?;
+    sqlx::query!("DELETE FROM users WHERE id = $1", user_id)
+        .execute(&mut transaction)
+        .await
+        .context("Failed deleting the user.")?;
+
+    commit(transaction).await
+}
+
+#[tracing::instrument(name = "Get a user", skip(db_pool))]
+pub async fn find_user(user_id: Uuid, db_pool: &PgPool) -> Result<User, UserManagementError> {
+    let row = sqlx::query!(
+        "SELECT id, username, email, role, disabled FROM users WHERE id = $1",
+        user_id
+    )
+    .fetch_optional(db_pool)
+    .await
+    .context("Failed fetching the user.")?
+    .ok_or(UserManagementError::UnknownUser)?;
+
+    Ok(User {
+        id: row.id,
+        username: row.username,
+        email: row.email,
+        role: Role::parse(&row.role).map_err(anyhow::Error::msg)?,
+        disabled: row.disabled,
+    })
+}
+
+/// Fails if `user_id` doesn't exist, or is the last enabled owner
+/// (who is about to stop being one).
+/// Every user is locked, so that two concurrent changes can't remove the last two owners.
+async fn ensure_not_the_last_owner(
+    user_id: Uuid,
+    transaction: &mut Transaction<'_, Postgres>,
+) -> Result<(), UserManagementError> {
+    let users = sqlx::query!("SELECT id, role, disabled FROM users FOR UPDATE")
+        .fetch_all(transaction)
+        .await
+        .context("Failed fetching the users.")?;
+    let n_enabled_owners = |ids: &dyn Fn(Uuid) -> bool| {
+        users
+            .iter()
+            .filter(|user| ids(user.id) && user.role == Role::Owner.as_str() && !user.disabled)
+            .count()
+    };
+
+    if !users.iter().any(|user| user.id == user_id) {
+        return Err(UserManagementError::UnknownUser);
+    }
+    if n_enabled_owners(&|id| id == user_id) == 1 && n_enabled_owners(&|id| id != user_id) == 0 {
+        return Err(UserManagementError::LastOwner);
+    }
+
+    Ok(())
+}
+
+async fn begin(db_pool: &PgPool) -> Result<Transaction<'static, Postgres>, UserManagementError> {
+    let transaction = db_pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool.")?;
+
+    Ok(transaction)
+}
+
+async fn commit(transaction: Transaction<'_, Postgres>) -> Result<(), UserManagementError> {
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit the changes to the user.")?;
+
+    Ok(())
+}
@@ -7,7 +7,9 @@ use sqlx::ConnectOptions;
 use std::time;
 
 use crate::domains::SubscriberEmail;
-use crate::email_client::EmailClient;
+use crate::email_client::{
+    EmailClient, EmailTransport, FileTransport, PostmarkTransport, RateLimiter, SmtpTransport,
+};
 
 pub fn get_configuration() -> Result<Settings, config::ConfigError> {
     let mut settings = config::Config::default();
@@ -35,15 +37,116 @@ pub struct Settings {
     pub database: DBSettings,
     pub application: ApplicationSettings,
     pub email_client: EmailClientSettings,
+    pub issue_delivery: IssueDeliverySettings,
+    pub login_throttling: LoginThrottlingSettings,
+    pub subscriptions: SubscriptionSettings,
     pub redis_uri: Secret<String>,
 }
 
 #[derive(serde::Deserialize, Clone)]
 pub struct EmailClientSettings {
+    #[serde(default)]
+    pub transport: EmailTransportKind,
+    // Postmark API
     pub base_url: String,
     pub sender_email: String,
     pub authorization_token: Secret<String>,
     pub timeout_milliseconds: u64,
+    // Only needed by the `smtp` transport
+    pub smtp: Option<SmtpSettings>,
+    // Only needed by the `file` transport
+    pub outbox_directory: Option<String>,
+    // Each process enforces it on its own: divide the provider's limits between them
+    #[serde(default)]
+    pub rate_limit_per_process: RateLimitSettings,
+}
+
+/// Leave a limit out to not enforce it.
+#[derive(serde::Deserialize, Clone, Debug, Default)]
+pub struct RateLimitSettings {
+    pub messages_per_second: Option<u32>,
+    pub messages_per_hour: Option<u32>,
+}
+
+/// How emails leave the application: through the Postmark API,
+/// an SMTP server, or as `.eml` files for local development.
+#[derive(serde::Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum EmailTransportKind {
+    #[default]
+    Postmark,
+    Smtp,
+    File,
+}
+
+#[derive(serde::Deserialize, Clone)]
+pub struct SmtpSettings {
+    pub host: String,
+    // Prevent error when deserializing string to u16
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub port: u16,
+    pub username: Option<String>,
+    pub password: Option<Secret<String>>,
+    // Refuse to send emails to servers that don't support STARTTLS
+    pub require_tls: bool,
+}
+
+#[derive(serde::Deserialize, Clone)]
+pub struct IssueDeliverySettings {
+    // Number of failed attempts after which a delivery is given up
+    pub max_attempts: i16,
+    pub base_backoff_seconds: u64,
+    pub max_backoff_seconds: u64,
+    // Number of emails sent per request to the email API, at most 500
+    pub batch_size: usize,
+    // Number of workers delivering issues concurrently in each process,
+    // each of them holds a connection from the pool while sending a batch
+    pub n_workers: usize,
+    // How long a worker waits before looking at the queue again once it's empty,
+    // only used while the queue listener is disconnected. It's also how long
+    // the listener waits before trying to reconnect.
+    pub empty_queue_poll_interval_milliseconds: u64,
+    // How long a worker waits before trying again after an unexpected error
+    pub error_poll_interval_milliseconds: u64,
+}
+
+#[derive(serde::Deserialize, Clone, Debug)]
+pub struct LoginThrottlingSettings {
+    // Failed logins let through before the next attempts get delayed, by 1s, 2s, 4s...
+    pub free_attempts: u32,
+    // Failed logins after which the username, or the client IP, is locked out
+    pub max_attempts_per_username: u32,
+    pub max_attempts_per_ip: u32,
+    // How long a lockout lasts, and how long failed logins are remembered for
+    pub lockout_seconds: u64,
+    // Take the client IP from the `Forwarded` or `X-Forwarded-For` header set by
+    // a reverse proxy, rather than from the connection, which comes from the proxy.
+    // Only enable it behind a proxy: clients can set these headers themselves.
+    pub trust_forwarded_headers: bool,
+}
+
+impl LoginThrottlingSettings {
+    pub fn lockout(&self) -> time::Duration {
+        time::Duration::from_secs(self.lockout_seconds)
+    }
+}
+
+#[derive(serde::Deserialize, Clone, Debug)]
+pub struct SubscriptionSettings {
+    // How long a confirmation link stays valid
+    pub confirmation_token_ttl_seconds: u64,
+    // How often pending subscriptions whose links have all expired are deleted
+    pub purge_interval_seconds: u64,
+}
+
+impl SubscriptionSettings {
+    pub fn confirmation_token_ttl(&self) -> time::Duration {
+        time::Duration::from_secs(self.confirmation_token_ttl_seconds)
+    }
+
+    pub fn purge_interval(&self) -> time::Duration {
+        time::Duration::from_secs(self.purge_interval_seconds)
+    }
 }
 
 #[derive(serde::Deserialize, Clone)]
@@ -54,6 +157,16 @@ pub struct ApplicationSettings {
     pub port: u16,
     pub base_url: String,
     pub hmac_secret: Secret<String>,
+    // Serve published issues to anyone under `/archive`
+    pub public_archive: bool,
+    // How long in-flight requests and deliveries get to complete on shutdown
+    pub drain_timeout_seconds: u64,
+}
+
+impl ApplicationSettings {
+    pub fn drain_timeout(&self) -> time::Duration {
+        time::Duration::from_secs(self.drain_timeout_seconds)
+    }
 }
 
 #[derive(serde::Deserialize, Debug, Clone)]
@@ -77,12 +190,26 @@ impl EmailClientSettings {
     pub fn client(self) -> EmailClient {
         let sender_email = self.sender().expect("Invalid sender email address.");
         let timeout = self.timeout();
-        EmailClient::new(
-            self.base_url,
-            sender_email,
-            self.authorization_token,
-            timeout,
-        )
+        let transport: Box<dyn EmailTransport> = match self.transport {
+            EmailTransportKind::Postmark => Box::new(PostmarkTransport::new(
+                self.base_url,
+                self.authorization_token,
+                timeout,
+            )),
+            EmailTransportKind::Smtp => {
+                let smtp = self.smtp.expect("Missing SMTP settings.");
+                Box::new(SmtpTransport::new(&smtp, timeout).expect("Invalid SMTP settings."))
+            }
+            EmailTransportKind::File => {
+                let directory = self.outbox_directory.expect("Missing outbox directory.");
+                Box::new(
+                    FileTransport::new(directory).expect("Failed to create the outbox directory."),
+                )
+            }
+        };
+
+        EmailClient::new(sender_email, transport)
+            .with_rate_limiter(RateLimiter::new(&self.rate_limit_per_process))
     }
 
     pub fn sender(&self) -> Result<SubscriberEmail, String> {
@@ -94,6 +221,28 @@ impl EmailClientSettings {
     }
 }
 
+impl IssueDeliverySettings {
+    /// How long to wait before retrying a delivery that has already failed `n_attempts` times.
+    /// The delay doubles on every failed attempt, up to `max_backoff_seconds`.
+    pub fn backoff(&self, n_attempts: i16) -> time::Duration {
+        let exponent = n_attempts.clamp(0, 31) as u32;
+        let delay = self
+            .base_backoff_seconds
+            .saturating_mul(2u64.saturating_pow(exponent))
+            .min(self.max_backoff_seconds);
+
+        time::Duration::from_secs(delay)
+    }
+
+    pub fn empty_queue_poll_interval(&self) -> time::Duration {
+        time::Duration::from_millis(self.empty_queue_poll_interval_milliseconds)
+    }
+
+    pub fn error_poll_interval(&self) -> time::Duration {
+        time::Duration::from_millis(self.error_poll_interval_milliseconds)
+    }
+}
+
 impl DBSettings {
     pub fn without_db(&self) -> PgConnectOptions {
         let ssl_mode = if self.require_ssl {
@@ -161,3 +310,38 @@ impl TryFrom<String> for Environment {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::IssueDeliverySettings;
+    use std::time::Duration;
+
+    fn settings() -> IssueDeliverySettings {
+        IssueDeliverySettings {
+            max_attempts: 5,
+            base_backoff_seconds: 30,
+            max_backoff_seconds: 3600,
+            batch_size: 100,
+            n_workers: 1,
+            empty_queue_poll_interval_milliseconds: 10000,
+            error_poll_interval_milliseconds: 1000,
+        }
+    }
+
+    #[test]
+    fn backoff_doubles_on_every_failed_attempt() {
+        let settings = settings();
+
+        assert_eq!(settings.backoff(0), Duration::from_secs(30));
+        assert_eq!(settings.backoff(1), Duration::from_secs(60));
+        assert_eq!(settings.backoff(2), Duration::from_secs(120));
+    }
+
+    #[test]
+    fn backoff_never_exceeds_the_configured_maximum() {
+        let settings = settings();
+
+        assert_eq!(settings.backoff(10), Duration::from_secs(3600));
+        assert_eq!(settings.backoff(i16::MAX), Duration::from_secs(3600));
+    }
+}
@@ -1,9 +1,13 @@
 mod idempotency;
 mod new_subscriber;
+mod send_at;
 mod subscriber_email;
 mod subscriber_name;
+mod unsubscribe_token;
 
 pub use idempotency::*;
 pub use new_subscriber::NewSubscriber;
+pub use send_at::SendAt;
 pub use subscriber_email::SubscriberEmail;
 pub use subscriber_name::SubscriberName;
+pub use unsubscribe_token::UnsubscribeToken;
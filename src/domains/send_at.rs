@@ -0,0 +1,88 @@
+use chrono::{DateTime, NaiveDateTime, Utc};
+
+/// The moment a newsletter issue should go out, always in UTC.
+#[derive(Debug, Clone, Copy)]
+pub struct SendAt(DateTime<Utc>);
+
+impl SendAt {
+    /// Accepts both the format produced by `<input type="datetime-local">`
+    /// (interpreted as UTC) and RFC 3339 timestamps.
+    pub fn parse(value: &str) -> Result<SendAt, String> {
+        let value = value.trim();
+
+        if let Ok(datetime) = DateTime::parse_from_rfc3339(value) {
+            return Ok(Self(datetime.with_timezone(&Utc)));
+        }
+
+        ["%Y-%m-%dT%H:%M", "%Y-%m-%dT%H:%M:%S"]
+            .iter()
+            .find_map(|format| NaiveDateTime::parse_from_str(value, format).ok())
+            .map(|datetime| Self(DateTime::from_utc(datetime, Utc)))
+            .ok_or_else(|| format!("{} is not a valid date and time", value))
+    }
+
+    /// Turn an optional form field into a `SendAt`, treating an empty value as missing.
+    pub fn parse_optional(value: Option<&str>) -> Result<Option<SendAt>, String> {
+        match value {
+            Some(value) if !value.trim().is_empty() => Self::parse(value).map(Some),
+            _ => Ok(None),
+        }
+    }
+
+    pub fn is_in_the_future(&self) -> bool {
+        self.0 > Utc::now()
+    }
+}
+
+impl From<SendAt> for DateTime<Utc> {
+    fn from(send_at: SendAt) -> Self {
+        send_at.0
+    }
+}
+
+impl std::fmt::Display for SendAt {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0.format("%Y-%m-%d %H:%M UTC"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SendAt;
+    use chrono::{DateTime, TimeZone, Utc};
+    use claim::{assert_err, assert_none, assert_ok};
+
+    #[test]
+    fn datetime_local_input_is_parsed_as_utc() {
+        let send_at = SendAt::parse("2022-07-10T08:30").unwrap();
+        assert_eq!(
+            DateTime::<Utc>::from(send_at),
+            Utc.ymd(2022, 7, 10).and_hms(8, 30, 0)
+        );
+    }
+
+    #[test]
+    fn datetime_local_input_with_seconds_is_accepted() {
+        assert_ok!(SendAt::parse("2022-07-10T08:30:15"));
+    }
+
+    #[test]
+    fn rfc3339_timestamps_are_converted_to_utc() {
+        let send_at = SendAt::parse("2022-07-10T15:30:00+07:00").unwrap();
+        assert_eq!(
+            DateTime::<Utc>::from(send_at),
+            Utc.ymd(2022, 7, 10).and_hms(8, 30, 0)
+        );
+    }
+
+    #[test]
+    fn garbage_is_rejected() {
+        assert_err!(SendAt::parse("next tuesday"));
+    }
+
+    #[test]
+    fn empty_optional_value_is_treated_as_missing() {
+        assert_none!(SendAt::parse_optional(Some("  ")).unwrap());
+        assert_none!(SendAt::parse_optional(None).unwrap());
+    }
+}
@@ -0,0 +1,115 @@
+use hmac::{Hmac, Mac};
+use secrecy::{ExposeSecret, Secret};
+use sha2::Sha256;
+use uuid::Uuid;
+
+/// Identifies a subscriber in the unsubscribe link of a newsletter issue.
+///
+/// The subscriber ID is signed with the application's HMAC secret,
+/// so a token can't be forged to unsubscribe somebody else.
+#[derive(Debug)]
+pub struct UnsubscribeToken {
+    subscriber_id: Uuid,
+    token: String,
+}
+
+impl UnsubscribeToken {
+    pub fn generate(subscriber_id: Uuid, hmac_secret: &Secret<String>) -> Self {
+        let mut mac = new_mac(hmac_secret);
+        mac.update(subscriber_id.as_bytes());
+        let signature = hex::encode(mac.finalize().into_bytes());
+
+        Self {
+            subscriber_id,
+            token: format!("{}.{}", subscriber_id.to_simple(), signature),
+        }
+    }
+
+    pub fn parse(token: String, hmac_secret: &Secret<String>) -> Result<Self, String> {
+        let invalid = || "The unsubscribe token is invalid.".to_string();
+
+        let (subscriber_id, signature) = token.split_once('.').ok_or_else(invalid)?;
+        let subscriber_id = Uuid::parse_str(subscriber_id).map_err(|_| invalid())?;
+        let signature = hex::decode(signature).map_err(|_| invalid())?;
+
+        let mut mac = new_mac(hmac_secret);
+        mac.update(subscriber_id.as_bytes());
+        mac.verify_slice(&signature).map_err(|_| invalid())?;
+
+        Ok(Self {
+            subscriber_id,
+            token,
+        })
+    }
+
+    pub fn subscriber_id(&self) -> Uuid {
+        self.subscriber_id
+    }
+}
+
+fn new_mac(hmac_secret: &Secret<String>) -> Hmac<Sha256> {
+    Hmac::<Sha256>::new_from_slice(hmac_secret.expose_secret().as_bytes())
+        .expect("HMAC can take a key of any size.")
+}
+
+impl AsRef<str> for UnsubscribeToken {
+    fn as_ref(&self) -> &str {
+        &self.token
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::UnsubscribeToken;
+    use claim::{assert_err, assert_ok};
+    use secrecy::Secret;
+    use uuid::Uuid;
+
+    fn secret() -> Secret<String> {
+        Secret::new("a-very-secret-key".to_string())
+    }
+
+    #[test]
+    fn a_generated_token_is_parsed_back_to_the_same_subscriber() {
+        let subscriber_id = Uuid::new_v4();
+        let token = UnsubscribeToken::generate(subscriber_id, &secret());
+
+        let parsed = assert_ok!(UnsubscribeToken::parse(
+            token.as_ref().to_string(),
+            &secret()
+        ));
+        assert_eq!(parsed.subscriber_id(), subscriber_id);
+    }
+
+    #[test]
+    fn a_token_signed_with_another_secret_is_rejected() {
+        let other_secret = Secret::new("another-secret-key".to_string());
+        let token = UnsubscribeToken::generate(Uuid::new_v4(), &other_secret);
+
+        assert_err!(UnsubscribeToken::parse(
+            token.as_ref().to_string(),
+            &secret()
+        ));
+    }
+
+    #[test]
+    fn a_token_for_another_subscriber_is_rejected() {
+        let token = UnsubscribeToken::generate(Uuid::new_v4(), &secret());
+        let (_, signature) = token.as_ref().split_once('.').unwrap();
+        let forged = format!("{}.{}", Uuid::new_v4().to_simple(), signature);
+
+        assert_err!(UnsubscribeToken::parse(forged, &secret()));
+    }
+
+    #[test]
+    fn malformed_tokens_are_rejected() {
+        for token in [
+            "",
+            "not-a-token",
+            "not-a-uuid.abcd",
+            &Uuid::new_v4().to_string(),
+        ] {
+            assert_err!(UnsubscribeToken::parse(token.to_string(), &secret()));
+        }
+    }
+}
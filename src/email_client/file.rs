@@ -0,0 +1,79 @@
+use lettre::{AsyncFileTransport, AsyncTransport, Tokio1Executor};
+use std::path::PathBuf;
+
+use super::{to_mime_message, Email, EmailError, EmailTransport};
+
+/// Writes every email as an `.eml` file in a directory instead of sending it,
+/// to look at the emails of a local deployment without an email provider.
+#[derive(Debug)]
+pub struct FileTransport {
+    outbox: AsyncFileTransport<Tokio1Executor>,
+}
+
+impl FileTransport {
+    pub fn new(directory: impl Into<PathBuf>) -> Result<Self, std::io::Error> {
+        let directory = directory.into();
+        std::fs::create_dir_all(&directory)?;
+
+        Ok(Self {
+            outbox: AsyncFileTransport::new(directory),
+        })
+    }
+}
+
+#[async_trait::async_trait]
+impl EmailTransport for FileTransport {
+    async fn send(&self, email: &Email<'_>) -> Result<(), EmailError> {
+        let (envelope, message) = to_mime_message(email)?;
+
+        self.outbox
+            .send_raw(&envelope, &message)
+            .await
+            .map_err(|e| EmailError::Unavailable(e.into()))?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domains::SubscriberEmail;
+    use crate::email_client::EmailClient;
+    use uuid::Uuid;
+
+    fn email(address: &str) -> SubscriberEmail {
+        SubscriberEmail::parse(address.to_string()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn send_email_writes_an_eml_file_to_the_directory() {
+        let directory = std::env::temp_dir().join(Uuid::new_v4().to_string());
+        let transport = FileTransport::new(&directory).unwrap();
+        let email_client = EmailClient::new(email("sender@example.com"), Box::new(transport));
+
+        email_client
+            .send_email(
+                &email("recipient@example.com"),
+                "Subject line",
+                "<p>HTML body</p>",
+                "Text body",
+            )
+            .await
+            .unwrap();
+
+        let files = std::fs::read_dir(&directory)
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .collect::<Vec<_>>();
+        assert_eq!(files.len(), 1);
+        assert_eq!(files[0].extension().unwrap(), "eml");
+
+        let message = std::fs::read_to_string(&files[0]).unwrap();
+        assert!(message.contains("To: recipient@example.com"));
+        assert!(message.contains("Subject: Subject line"));
+        assert!(message.contains("Text body"));
+
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+}
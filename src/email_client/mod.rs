@@ -0,0 +1,289 @@
+mod file;
+mod postmark;
+mod rate_limit;
+mod smtp;
+
+use std::time::Duration;
+
+use crate::domains::SubscriberEmail;
+
+pub use file::FileTransport;
+pub use postmark::PostmarkTransport;
+pub use rate_limit::RateLimiter;
+pub use smtp::SmtpTransport;
+
+/// The largest batch of emails Postmark accepts in a single request.
+pub const MAX_BATCH_SIZE: usize = 500;
+
+/// Something that can deliver an email: the Postmark API, an SMTP server...
+#[async_trait::async_trait]
+pub trait EmailTransport: std::fmt::Debug + Send + Sync {
+    async fn send(&self, email: &Email<'_>) -> Result<(), EmailError>;
+
+    /// Send up to `MAX_BATCH_SIZE` emails, returning the outcome of each of them in order.
+    /// Fails as a whole only if none of them could be sent.
+    async fn send_batch(
+        &self,
+        emails: &[Email<'_>],
+    ) -> Result<Vec<Result<(), EmailError>>, EmailError> {
+        let mut outcomes = Vec::with_capacity(emails.len());
+        for email in emails {
+            outcomes.push(self.send(email).await);
+        }
+        Ok(outcomes)
+    }
+}
+
+pub struct Email<'a> {
+    pub from: &'a SubscriberEmail,
+    pub to: &'a SubscriberEmail,
+    pub subject: &'a str,
+    pub html_body: &'a str,
+    pub text_body: &'a str,
+    pub headers: &'a [EmailHeader],
+}
+
+/// One of the emails of a batch, all of them are sent from our sender address.
+pub struct BatchEmail<'a> {
+    pub recipient: &'a SubscriberEmail,
+    pub subject: &'a str,
+    pub html_content: &'a str,
+    pub text_content: &'a str,
+    pub headers: &'a [EmailHeader],
+}
+
+/// A custom header to add to an email, on top of the ones set by the transport.
+#[derive(Debug, Clone, serde::Serialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct EmailHeader {
+    pub name: String,
+    pub value: String,
+}
+
+impl EmailHeader {
+    pub fn new(name: impl Into<String>, value: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            value: value.into(),
+        }
+    }
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum EmailError {
+    /// The email itself was refused, sending it again won't make any difference.
+    #[error("The email was rejected")]
+    Rejected(#[source] anyhow::Error),
+    /// The transport couldn't take the email right now (timeouts, server errors...).
+    #[error("Failed to send the email")]
+    Unavailable(#[source] anyhow::Error),
+    /// The provider asked us to slow down, the email will go through later.
+    #[error("The email provider asked us to retry after {retry_after:?}")]
+    RateLimited { retry_after: Duration },
+}
+
+impl EmailError {
+    pub fn is_retryable(&self) -> bool {
+        matches!(
+            self,
+            EmailError::Unavailable(_) | EmailError::RateLimited { .. }
+        )
+    }
+}
+
+/// Sends emails from our sender address, through whichever transport is configured.
+/// Everyone sharing a client (e.g. the delivery workers of a process) shares its rate limit,
+/// other processes have their own.
+#[derive(Debug)]
+pub struct EmailClient {
+    sender: SubscriberEmail,
+    transport: Box<dyn EmailTransport>,
+    rate_limiter: RateLimiter,
+}
+
+impl EmailClient {
+    /// The client isn't rate limited, see `with_rate_limiter`.
+    pub fn new(sender: SubscriberEmail, transport: Box<dyn EmailTransport>) -> Self {
+        Self {
+            sender,
+            transport,
+            rate_limiter: RateLimiter::default(),
+        }
+    }
+
+    pub fn with_rate_limiter(mut self, rate_limiter: RateLimiter) -> Self {
+        self.rate_limiter = rate_limiter;
+        self
+    }
+
+    pub fn sender(&self) -> &SubscriberEmail {
+        &self.sender
+    }
+
+    pub async fn send_email(
+        &self,
+        recipient: &SubscriberEmail,
+        subject: &str,
+        html_content: &str,
+        text_content: &str,
+    ) -> Result<(), EmailError> {
+        self.send_email_with_headers(recipient, subject, html_content, text_content, &[])
+            .await
+    }
+
+    pub async fn send_email_with_headers(
+        &self,
+        recipient: &SubscriberEmail,
+        subject: &str,
+        html_content: &str,
+        text_content: &str,
+        headers: &[EmailHeader],
+    ) -> Result<(), EmailError> {
+        self.rate_limiter.acquire(1).await;
+        let outcome = self
+            .transport
+            .send(&Email {
+                from: &self.sender,
+                to: recipient,
+                subject,
+                html_body: html_content,
+                text_body: text_content,
+                headers,
+            })
+            .await;
+        self.pause_if_rate_limited(outcome)
+    }
+
+    /// Waits for the rate limit to let the whole batch through.
+    pub async fn send_batch(
+        &self,
+        emails: &[BatchEmail<'_>],
+    ) -> Result<Vec<Result<(), EmailError>>, EmailError> {
+        self.rate_limiter
+            .acquire(emails.len().min(MAX_BATCH_SIZE))
+            .await;
+        self.send_batch_within_limit(emails).await
+    }
+
+    /// Take up to `max_messages` from the rate limit without waiting, to send them
+    /// with `send_reserved_batch`. Returns how long to wait if none can be sent right now.
+    pub fn try_reserve(&self, max_messages: usize) -> Result<Reservation<'_>, Duration> {
+        let n_messages = self.rate_limiter.try_acquire_up_to(max_messages)?;
+        Ok(Reservation {
+            rate_limiter: &self.rate_limiter,
+            n_messages,
+        })
+    }
+
+    /// Send a batch of at most `reservation.n_messages()` emails, the rest of the
+    /// reservation is given back.
+    pub async fn send_reserved_batch(
+        &self,
+        mut reservation: Reservation<'_>,
+        emails: &[BatchEmail<'_>],
+    ) -> Result<Vec<Result<(), EmailError>>, EmailError> {
+        if emails.len() > reservation.n_messages {
+            return Err(EmailError::Rejected(anyhow::anyhow!(
+                "Only {} emails have been reserved, got {}.",
+                reservation.n_messages,
+                emails.len()
+            )));
+        }
+        reservation.n_messages -= emails.len();
+        drop(reservation);
+
+        self.send_batch_within_limit(emails).await
+    }
+
+    async fn send_batch_within_limit(
+        &self,
+        emails: &[BatchEmail<'_>],
+    ) -> Result<Vec<Result<(), EmailError>>, EmailError> {
+        if emails.len() > MAX_BATCH_SIZE {
+            return Err(EmailError::Rejected(anyhow::anyhow!(
+                "Can't send more than {} emails in a batch, got {}.",
+                MAX_BATCH_SIZE,
+                emails.len()
+            )));
+        }
+
+        let emails = emails
+            .iter()
+            .map(|email| Email {
+                from: &self.sender,
+                to: email.recipient,
+                subject: email.subject,
+                html_body: email.html_content,
+                text_body: email.text_content,
+                headers: email.headers,
+            })
+            .collect::<Vec<_>>();
+        let outcome = self.transport.send_batch(&emails).await;
+        self.pause_if_rate_limited(outcome)
+    }
+
+    fn pause_if_rate_limited<T>(&self, outcome: Result<T, EmailError>) -> Result<T, EmailError> {
+        if let Err(EmailError::RateLimited { retry_after }) = &outcome {
+            self.rate_limiter.pause_for(*retry_after);
+        }
+        outcome
+    }
+}
+
+/// Messages taken from the rate limit of an `EmailClient` with `try_reserve`.
+/// Those that aren't sent with `send_reserved_batch` are given back when it's dropped,
+/// whichever way the caller bails out.
+#[derive(Debug)]
+pub struct Reservation<'a> {
+    rate_limiter: &'a RateLimiter,
+    n_messages: usize,
+}
+
+impl Reservation<'_> {
+    pub fn n_messages(&self) -> usize {
+        self.n_messages
+    }
+}
+
+impl Drop for Reservation<'_> {
+    fn drop(&mut self) {
+        self.rate_limiter.release(self.n_messages);
+    }
+}
+
+/// Format the email as a MIME message, for the transports that speak raw email.
+fn to_mime_message(email: &Email<'_>) -> Result<(lettre::address::Envelope, Vec<u8>), EmailError> {
+    use lettre::message::{Mailbox, MultiPart};
+
+    let parse_mailbox = |address: &SubscriberEmail| {
+        address
+            .as_ref()
+            .parse::<Mailbox>()
+            .map_err(|e| EmailError::Rejected(e.into()))
+    };
+
+    let message = lettre::Message::builder()
+        .from(parse_mailbox(email.from)?)
+        .to(parse_mailbox(email.to)?)
+        .subject(email.subject)
+        .multipart(MultiPart::alternative_plain_html(
+            email.text_body.to_owned(),
+            email.html_body.to_owned(),
+        ))
+        .map_err(|e| EmailError::Rejected(e.into()))?;
+
+    // lettre only lets us set headers it knows about, the custom ones go on top.
+    let mut formatted = Vec::new();
+    for header in email.headers {
+        if header.value.contains(['\r', '\n']) {
+            return Err(EmailError::Rejected(anyhow::anyhow!(
+                "The value of the {} header spans multiple lines.",
+                header.name
+            )));
+        }
+        formatted.extend_from_slice(format!("{}: {}\r\n", header.name, header.value).as_bytes());
+    }
+    formatted.extend_from_slice(&message.formatted());
+
+    Ok((message.envelope().clone(), formatted))
+}
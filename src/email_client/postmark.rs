@@ -0,0 +1,529 @@
+use reqwest::StatusCode;
+use secrecy::{ExposeSecret, Secret};
+use std::time;
+
+use super::{Email, EmailError, EmailHeader, EmailTransport};
+
+/// How long to hold off after a `429` without a `Retry-After` header.
+const DEFAULT_RETRY_AFTER: time::Duration = time::Duration::from_secs(60);
+
+/// Sends emails through Postmark's HTTP API.
+#[derive(Debug)]
+pub struct PostmarkTransport {
+    http_client: reqwest::Client,
+    base_url: String,
+    authorization_token: Secret<String>,
+}
+
+#[derive(serde::Serialize)]
+#[serde(rename_all = "PascalCase")]
+struct SendEmailRequestPayload<'a> {
+    from: &'a str,
+    to: &'a str,
+    subject: &'a str,
+    html_body: &'a str,
+    text_body: &'a str,
+    #[serde(skip_serializing_if = "<[_]>::is_empty")]
+    headers: &'a [EmailHeader],
+}
+
+/// Postmark answers a batch request with the outcome of each email, in order.
+#[derive(serde::Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct BatchResponseEntry {
+    error_code: i64,
+    message: String,
+}
+
+impl PostmarkTransport {
+    pub fn new(
+        base_url: String,
+        authorization_token: Secret<String>,
+        duration: time::Duration,
+    ) -> Self {
+        let reqwest_client = reqwest::Client::builder()
+            .timeout(duration)
+            .build()
+            .unwrap();
+
+        PostmarkTransport {
+            http_client: reqwest_client,
+            base_url,
+            authorization_token,
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl EmailTransport for PostmarkTransport {
+    async fn send(&self, email: &Email<'_>) -> Result<(), EmailError> {
+        self.post("email", &SendEmailRequestPayload::from(email))
+            .await?;
+
+        Ok(())
+    }
+
+    async fn send_batch(
+        &self,
+        emails: &[Email<'_>],
+    ) -> Result<Vec<Result<(), EmailError>>, EmailError> {
+        let req_body = emails
+            .iter()
+            .map(SendEmailRequestPayload::from)
+            .collect::<Vec<_>>();
+        let entries: Vec<BatchResponseEntry> = self
+            .post("email/batch", &req_body)
+            .await?
+            .json()
+            .await
+            .map_err(|e| EmailError::Unavailable(e.into()))?;
+
+        if entries.len() != emails.len() {
+            return Err(EmailError::Unavailable(anyhow::anyhow!(
+                "Postmark answered with {} results for a batch of {} emails.",
+                entries.len(),
+                emails.len()
+            )));
+        }
+
+        Ok(entries
+            .into_iter()
+            .map(|entry| match entry.error_code {
+                0 => Ok(()),
+                error_code => Err(EmailError::Rejected(anyhow::anyhow!(
+                    "Postmark error {}: {}",
+                    error_code,
+                    entry.message
+                ))),
+            })
+            .collect())
+    }
+}
+
+impl PostmarkTransport {
+    async fn post<Body: serde::Serialize + ?Sized>(
+        &self,
+        endpoint: &str,
+        req_body: &Body,
+    ) -> Result<reqwest::Response, EmailError> {
+        let to_email_error = |e: reqwest::Error| {
+            if is_retryable(&e) {
+                EmailError::Unavailable(e.into())
+            } else {
+                EmailError::Rejected(e.into())
+            }
+        };
+
+        let response = self
+            .http_client
+            .post(format!("{}/{}", self.base_url, endpoint))
+            .header(
+                "X-Postmark-Server-Token",
+                self.authorization_token.expose_secret(),
+            )
+            .json(req_body)
+            .send()
+            .await
+            .map_err(to_email_error)?;
+
+        if response.status() == StatusCode::TOO_MANY_REQUESTS {
+            return Err(EmailError::RateLimited {
+                retry_after: retry_after(&response).unwrap_or(DEFAULT_RETRY_AFTER),
+            });
+        }
+
+        response.error_for_status().map_err(to_email_error)
+    }
+}
+
+/// `Retry-After` is either a number of seconds or an HTTP date.
+fn retry_after(response: &reqwest::Response) -> Option<time::Duration> {
+    let value = response
+        .headers()
+        .get(reqwest::header::RETRY_AFTER)?
+        .to_str()
+        .ok()?
+        .trim();
+
+    match value.parse::<u64>() {
+        Ok(seconds) => Some(time::Duration::from_secs(seconds)),
+        Err(_) => {
+            let retry_at = chrono::DateTime::parse_from_rfc2822(value).ok()?;
+            (retry_at.with_timezone(&chrono::Utc) - chrono::Utc::now())
+                .to_std()
+                .ok()
+        }
+    }
+}
+
+impl<'a> From<&'a Email<'a>> for SendEmailRequestPayload<'a> {
+    fn from(email: &'a Email<'a>) -> Self {
+        Self {
+            from: email.from.as_ref(),
+            to: email.to.as_ref(),
+            subject: email.subject,
+            html_body: email.html_body,
+            text_body: email.text_body,
+            headers: email.headers,
+        }
+    }
+}
+
+/// Client errors mean that Postmark rejected the message itself,
+/// sending it again won't make any difference. Rate limiting is handled separately.
+fn is_retryable(e: &reqwest::Error) -> bool {
+    match e.status() {
+        Some(status) => !status.is_client_error(),
+        None => true,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domains::SubscriberEmail;
+    use crate::email_client::{BatchEmail, EmailClient, RateLimiter};
+    use claim::{assert_err, assert_ok};
+    use fake::faker::internet::en::SafeEmail;
+    use fake::faker::lorem::en::{Paragraph, Sentence};
+    use fake::{Fake, Faker};
+    use wiremock::{
+        matchers::{any, header, header_exists, method, path},
+        Mock, MockServer, Request, ResponseTemplate,
+    };
+
+    struct SendEmailBodyMatcher;
+
+    impl wiremock::Match for SendEmailBodyMatcher {
+        fn matches(&self, request: &Request) -> bool {
+            let result: Result<serde_json::Value, _> = serde_json::from_slice(&request.body);
+
+            if let Ok(body) = result {
+                body.get("From").is_some()
+                    && body.get("To").is_some()
+                    && body.get("Subject").is_some()
+                    && body.get("HtmlBody").is_some()
+                    && body.get("TextBody").is_some()
+            } else {
+                false
+            }
+        }
+    }
+
+    fn subject() -> String {
+        Sentence(1..2).fake::<String>()
+    }
+
+    fn content() -> String {
+        Paragraph(1..10).fake::<String>()
+    }
+
+    fn email() -> SubscriberEmail {
+        SubscriberEmail::parse(SafeEmail().fake()).unwrap()
+    }
+
+    fn email_client(base_url: String) -> EmailClient {
+        let transport = PostmarkTransport::new(
+            base_url,
+            Secret::new(Faker.fake()),
+            time::Duration::from_millis(200),
+        );
+        EmailClient::new(email(), Box::new(transport))
+    }
+
+    #[tokio::test]
+    async fn send_email_actually_sends_the_expected_request() {
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+
+        Mock::given(header_exists("X-Postmark-Server-Token"))
+            .and(header("Content-Type", "application/json"))
+            .and(method("POST"))
+            .and(path("/email"))
+            .and(SendEmailBodyMatcher)
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let outcome = email_client
+            .send_email(&email(), &subject(), &content(), &content())
+            .await;
+
+        assert_ok!(outcome)
+    }
+
+    #[tokio::test]
+    async fn send_email_does_not_send_headers_if_there_are_none() {
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        email_client
+            .send_email(&email(), &subject(), &content(), &content())
+            .await
+            .unwrap();
+
+        let request = &mock_server.received_requests().await.unwrap()[0];
+        let body: serde_json::Value = serde_json::from_slice(&request.body).unwrap();
+        assert!(body.get("Headers").is_none());
+    }
+
+    #[tokio::test]
+    async fn send_email_with_headers_sends_the_custom_headers() {
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let headers = [EmailHeader::new("X-Campaign", "weekly")];
+        let outcome = email_client
+            .send_email_with_headers(&email(), &subject(), &content(), &content(), &headers)
+            .await;
+        assert_ok!(outcome);
+
+        let request = &mock_server.received_requests().await.unwrap()[0];
+        let body: serde_json::Value = serde_json::from_slice(&request.body).unwrap();
+        assert_eq!(
+            body["Headers"],
+            serde_json::json!([{ "Name": "X-Campaign", "Value": "weekly" }])
+        );
+    }
+
+    #[tokio::test]
+    async fn send_email_fails_if_the_server_returns_500() {
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(500))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let outcome = email_client
+            .send_email(&email(), &subject(), &content(), &content())
+            .await;
+
+        assert_err!(outcome);
+    }
+
+    #[tokio::test]
+    async fn client_errors_are_not_retryable_except_for_rate_limiting() {
+        let test_cases = vec![(422, false), (429, true), (500, true)];
+
+        for (status, retryable) in test_cases {
+            let mock_server = MockServer::start().await;
+            let email_client = email_client(mock_server.uri());
+
+            Mock::given(any())
+                .respond_with(ResponseTemplate::new(status))
+                .mount(&mock_server)
+                .await;
+
+            let error = email_client
+                .send_email(&email(), &subject(), &content(), &content())
+                .await
+                .unwrap_err();
+
+            assert_eq!(
+                error.is_retryable(),
+                retryable,
+                "Unexpected classification of a {} response.",
+                status
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn rate_limited_responses_carry_the_retry_after_delay() {
+        let test_cases = vec![
+            (Some("120"), time::Duration::from_secs(120)),
+            (None, DEFAULT_RETRY_AFTER),
+        ];
+
+        for (header_value, expected_delay) in test_cases {
+            let mock_server = MockServer::start().await;
+            let email_client = email_client(mock_server.uri());
+
+            let mut response = ResponseTemplate::new(429);
+            if let Some(value) = header_value {
+                response = response.insert_header("Retry-After", value);
+            }
+            Mock::given(any())
+                .respond_with(response)
+                .mount(&mock_server)
+                .await;
+
+            let error = email_client
+                .send_email(&email(), &subject(), &content(), &content())
+                .await
+                .unwrap_err();
+
+            match error {
+                EmailError::RateLimited { retry_after } => assert_eq!(retry_after, expected_delay),
+                other => panic!("Expected the email to be rate limited, got {:?}", other),
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn a_rate_limited_client_holds_off_the_following_emails() {
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(429).insert_header("Retry-After", "1"))
+            .up_to_n_times(1)
+            .mount(&mock_server)
+            .await;
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(200))
+            .mount(&mock_server)
+            .await;
+
+        let start = time::Instant::now();
+        assert_err!(
+            email_client
+                .send_email(&email(), &subject(), &content(), &content())
+                .await
+        );
+        assert_ok!(
+            email_client
+                .send_email(&email(), &subject(), &content(), &content())
+                .await
+        );
+
+        assert!(start.elapsed() >= time::Duration::from_secs(1));
+    }
+
+    #[tokio::test]
+    async fn send_batch_returns_the_outcome_of_each_email() {
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+
+        Mock::given(header_exists("X-Postmark-Server-Token"))
+            .and(method("POST"))
+            .and(path("/email/batch"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!([
+                { "ErrorCode": 0, "Message": "OK" },
+                { "ErrorCode": 300, "Message": "Invalid email request" },
+            ])))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let (first, second) = (email(), email());
+        let (subject, content) = (subject(), content());
+        let emails = [&first, &second].map(|recipient| BatchEmail {
+            recipient,
+            subject: &subject,
+            html_content: &content,
+            text_content: &content,
+            headers: &[],
+        });
+        let outcomes = email_client.send_batch(&emails).await.unwrap();
+
+        assert_eq!(outcomes.len(), 2);
+        assert_ok!(&outcomes[0]);
+        let error = outcomes[1].as_ref().unwrap_err();
+        assert!(!error.is_retryable());
+
+        let request = &mock_server.received_requests().await.unwrap()[0];
+        let body: Vec<serde_json::Value> = serde_json::from_slice(&request.body).unwrap();
+        assert_eq!(body[0]["To"], first.as_ref());
+        assert_eq!(body[1]["To"], second.as_ref());
+    }
+
+    #[tokio::test]
+    async fn reserved_messages_that_are_not_sent_are_given_back() {
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri()).with_rate_limiter(RateLimiter::new(
+            &crate::configuration::RateLimitSettings {
+                messages_per_second: None,
+                messages_per_hour: Some(3),
+            },
+        ));
+
+        Mock::given(path("/email/batch"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!([
+                { "ErrorCode": 0, "Message": "OK" },
+            ])))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        // Bailing out without sending anything
+        let reservation = email_client.try_reserve(3).unwrap();
+        assert_eq!(reservation.n_messages(), 3);
+        drop(reservation);
+
+        let reservation = email_client.try_reserve(3).unwrap();
+        assert_eq!(reservation.n_messages(), 3);
+        let (recipient, subject, content) = (email(), subject(), content());
+        let emails = [BatchEmail {
+            recipient: &recipient,
+            subject: &subject,
+            html_content: &content,
+            text_content: &content,
+            headers: &[],
+        }];
+        assert_ok!(email_client.send_reserved_batch(reservation, &emails).await);
+
+        assert_eq!(email_client.try_reserve(3).unwrap().n_messages(), 2);
+    }
+
+    #[tokio::test]
+    async fn send_batch_fails_as_a_whole_if_the_server_returns_500() {
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(500))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let (recipient, subject, content) = (email(), subject(), content());
+        let emails = [BatchEmail {
+            recipient: &recipient,
+            subject: &subject,
+            html_content: &content,
+            text_content: &content,
+            headers: &[],
+        }];
+        let outcome = email_client.send_batch(&emails).await;
+
+        assert!(outcome.unwrap_err().is_retryable());
+    }
+
+    #[tokio::test]
+    async fn send_email_times_out_if_the_server_takes_too_long() {
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+
+        let server_response =
+            ResponseTemplate::new(200).set_delay(std::time::Duration::from_secs(180));
+
+        Mock::given(any())
+            .respond_with(server_response)
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let outcome = email_client
+            .send_email(&email(), &subject(), &content(), &content())
+            .await;
+
+        assert_err!(outcome);
+    }
+}
@@ -0,0 +1,287 @@
+use std::sync::Mutex;
+use std::time::Duration;
+
+use tokio::time::Instant;
+
+use crate::configuration::RateLimitSettings;
+
+/// Keeps the senders sharing an `EmailClient` under the provider's rate limits:
+/// a token bucket per limit, and a pause when the provider asks us to slow down.
+///
+/// The buckets live in memory: each process (the API, every `worker` process)
+/// gets the whole allowance, the limits have to be divided between them.
+#[derive(Debug, Default)]
+pub struct RateLimiter {
+    state: Mutex<State>,
+}
+
+#[derive(Debug, Default)]
+struct State {
+    buckets: Vec<TokenBucket>,
+    paused_until: Option<Instant>,
+}
+
+#[derive(Debug)]
+struct TokenBucket {
+    capacity: f64,
+    tokens: f64,
+    tokens_per_second: f64,
+    refilled_at: Instant,
+}
+
+impl TokenBucket {
+    /// Starts full, allowing a burst of `n_messages` right away.
+    fn new(n_messages: u32, period: Duration) -> Self {
+        let capacity = f64::from(n_messages.max(1));
+        Self {
+            capacity,
+            tokens: capacity,
+            tokens_per_second: capacity / period.as_secs_f64(),
+            refilled_at: Instant::now(),
+        }
+    }
+
+    fn refill(&mut self, now: Instant) {
+        let elapsed = now.duration_since(self.refilled_at).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.tokens_per_second).min(self.capacity);
+        self.refilled_at = now;
+    }
+
+    /// More messages than the bucket holds go out once it's full,
+    /// overdrawing it: the following ones wait for the debt to be paid back.
+    fn time_until_available(&self, n_messages: f64) -> Duration {
+        let missing = n_messages.min(self.capacity) - self.tokens;
+        if missing <= 0.0 {
+            Duration::ZERO
+        } else {
+            Duration::from_secs_f64(missing / self.tokens_per_second)
+        }
+    }
+}
+
+impl RateLimiter {
+    pub fn new(settings: &RateLimitSettings) -> Self {
+        let limits = [
+            (settings.messages_per_second, Duration::from_secs(1)),
+            (settings.messages_per_hour, Duration::from_secs(60 * 60)),
+        ];
+        let buckets = limits
+            .into_iter()
+            .filter_map(|(n_messages, period)| Some(TokenBucket::new(n_messages?, period)))
+            .collect();
+
+        Self {
+            state: Mutex::new(State {
+                buckets,
+                paused_until: None,
+            }),
+        }
+    }
+
+    /// Wait until `n_messages` can be sent without going over any of the limits.
+    pub async fn acquire(&self, n_messages: usize) {
+        let n_messages = n_messages as f64;
+        loop {
+            let wait = {
+                let mut state = self.state.lock().unwrap();
+                let now = Instant::now();
+                let pause = state
+                    .paused_until
+                    .map(|paused_until| paused_until.saturating_duration_since(now))
+                    .unwrap_or_default();
+                let wait = state
+                    .buckets
+                    .iter_mut()
+                    .map(|bucket| {
+                        bucket.refill(now);
+                        bucket.time_until_available(n_messages)
+                    })
+                    .fold(pause, Duration::max);
+
+                if wait.is_zero() {
+                    for bucket in &mut state.buckets {
+                        bucket.tokens -= n_messages;
+                    }
+                    return;
+                }
+                wait
+            };
+            tokio::time::sleep(wait).await;
+        }
+    }
+
+    /// Take as many of `max_messages` as can be sent right away, at least one,
+    /// or nothing and how long to wait until one can be sent.
+    /// Doesn't wait, for callers that shouldn't hold on to resources meanwhile.
+    pub fn try_acquire_up_to(&self, max_messages: usize) -> Result<usize, Duration> {
+        let mut state = self.state.lock().unwrap();
+        let now = Instant::now();
+        let pause = state
+            .paused_until
+            .map(|paused_until| paused_until.saturating_duration_since(now))
+            .unwrap_or_default();
+        if !pause.is_zero() {
+            return Err(pause);
+        }
+
+        let mut n_available = max_messages as f64;
+        let mut wait = Duration::ZERO;
+        for bucket in &mut state.buckets {
+            bucket.refill(now);
+            n_available = n_available.min(bucket.tokens.floor());
+            wait = wait.max(bucket.time_until_available(1.0));
+        }
+        if n_available < 1.0 {
+            return Err(wait);
+        }
+
+        for bucket in &mut state.buckets {
+            bucket.tokens -= n_available;
+        }
+        Ok(n_available as usize)
+    }
+
+    /// Give back messages that have been acquired but won't be sent.
+    pub fn release(&self, n_messages: usize) {
+        let mut state = self.state.lock().unwrap();
+        for bucket in &mut state.buckets {
+            bucket.tokens = (bucket.tokens + n_messages as f64).min(bucket.capacity);
+        }
+    }
+
+    /// Hold every sender off for `duration`, e.g. when the provider answers with a `429`.
+    pub fn pause_for(&self, duration: Duration) {
+        let mut state = self.state.lock().unwrap();
+        let paused_until = Instant::now() + duration;
+        state.paused_until = state.paused_until.max(Some(paused_until));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rate_limiter(
+        messages_per_second: Option<u32>,
+        messages_per_hour: Option<u32>,
+    ) -> RateLimiter {
+        RateLimiter::new(&RateLimitSettings {
+            messages_per_second,
+            messages_per_hour,
+        })
+    }
+
+    async fn time_to_acquire(rate_limiter: &RateLimiter, n_messages: usize) -> Duration {
+        let start = Instant::now();
+        rate_limiter.acquire(n_messages).await;
+        start.elapsed()
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn an_unlimited_rate_limiter_never_waits() {
+        let rate_limiter = rate_limiter(None, None);
+
+        for _ in 0..100 {
+            assert_eq!(time_to_acquire(&rate_limiter, 500).await, Duration::ZERO);
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn messages_within_the_burst_go_out_right_away() {
+        let rate_limiter = rate_limiter(Some(10), None);
+
+        assert_eq!(time_to_acquire(&rate_limiter, 4).await, Duration::ZERO);
+        assert_eq!(time_to_acquire(&rate_limiter, 6).await, Duration::ZERO);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn acquire_waits_for_the_bucket_to_refill() {
+        let rate_limiter = rate_limiter(Some(10), None);
+        rate_limiter.acquire(10).await;
+
+        let waited = time_to_acquire(&rate_limiter, 5).await;
+
+        assert!(waited >= Duration::from_millis(500));
+        assert!(waited < Duration::from_millis(600));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn a_batch_bigger_than_the_bucket_is_paid_back_afterwards() {
+        let rate_limiter = rate_limiter(Some(10), None);
+
+        assert_eq!(time_to_acquire(&rate_limiter, 30).await, Duration::ZERO);
+        let waited = time_to_acquire(&rate_limiter, 1).await;
+
+        assert!(waited >= Duration::from_millis(2100));
+        assert!(waited < Duration::from_millis(2200));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn the_hourly_limit_applies_on_top_of_the_per_second_one() {
+        let rate_limiter = rate_limiter(Some(10), Some(20));
+        rate_limiter.acquire(10).await;
+        rate_limiter.acquire(10).await;
+
+        let waited = time_to_acquire(&rate_limiter, 1).await;
+
+        // 20 messages an hour: one every 3 minutes,
+        // minus the second spent waiting for the per-second bucket
+        assert!(waited >= Duration::from_secs(178));
+        assert!(waited < Duration::from_secs(180));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn try_acquire_takes_what_is_available_without_waiting() {
+        let rate_limiter = rate_limiter(Some(10), Some(25));
+
+        assert_eq!(rate_limiter.try_acquire_up_to(4), Ok(4));
+        assert_eq!(rate_limiter.try_acquire_up_to(100), Ok(6));
+        let wait = rate_limiter.try_acquire_up_to(100).unwrap_err();
+        assert!(wait > Duration::from_millis(99) && wait <= Duration::from_millis(100));
+
+        tokio::time::advance(Duration::from_secs(1)).await;
+        // The hourly limit has 15 messages left
+        assert_eq!(rate_limiter.try_acquire_up_to(100), Ok(10));
+        tokio::time::advance(Duration::from_secs(1)).await;
+        assert_eq!(rate_limiter.try_acquire_up_to(100), Ok(5));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn released_messages_can_be_acquired_again() {
+        let rate_limiter = rate_limiter(Some(10), None);
+        assert_eq!(rate_limiter.try_acquire_up_to(10), Ok(10));
+
+        rate_limiter.release(3);
+
+        assert_eq!(rate_limiter.try_acquire_up_to(10), Ok(3));
+        // Releasing doesn't fill the bucket past its capacity
+        rate_limiter.release(100);
+        assert_eq!(rate_limiter.try_acquire_up_to(100), Ok(10));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn try_acquire_respects_pauses() {
+        let rate_limiter = rate_limiter(None, None);
+        assert_eq!(rate_limiter.try_acquire_up_to(500), Ok(500));
+
+        rate_limiter.pause_for(Duration::from_secs(30));
+
+        assert_eq!(
+            rate_limiter.try_acquire_up_to(1),
+            Err(Duration::from_secs(30))
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn a_pause_holds_every_sender_off() {
+        let rate_limiter = rate_limiter(None, None);
+
+        rate_limiter.pause_for(Duration::from_secs(30));
+        // A shorter pause doesn't cut the current one short
+        rate_limiter.pause_for(Duration::from_secs(5));
+
+        let waited = time_to_acquire(&rate_limiter, 1).await;
+        assert!(waited >= Duration::from_secs(30));
+        assert!(waited < Duration::from_secs(31));
+    }
+}
@@ -0,0 +1,224 @@
+use lettre::transport::smtp::{
+    authentication::Credentials,
+    client::{Tls, TlsParameters},
+    AsyncSmtpTransport,
+};
+use lettre::{AsyncTransport, Tokio1Executor};
+use secrecy::ExposeSecret;
+use std::time;
+
+use super::{to_mime_message, Email, EmailError, EmailTransport};
+use crate::configuration::SmtpSettings;
+
+/// Sends emails to an SMTP server.
+#[derive(Debug)]
+pub struct SmtpTransport {
+    mailer: AsyncSmtpTransport<Tokio1Executor>,
+}
+
+impl SmtpTransport {
+    pub fn new(
+        settings: &SmtpSettings,
+        timeout: time::Duration,
+    ) -> Result<Self, lettre::transport::smtp::Error> {
+        // Servers that don't support STARTTLS (e.g. local test servers)
+        // are only allowed if TLS isn't required.
+        let tls_parameters = TlsParameters::new(settings.host.clone())?;
+        let tls = if settings.require_tls {
+            Tls::Required(tls_parameters)
+        } else {
+            Tls::Opportunistic(tls_parameters)
+        };
+
+        let mut builder = AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous(&settings.host)
+            .port(settings.port)
+            .tls(tls)
+            .timeout(Some(timeout));
+        if let (Some(username), Some(password)) = (&settings.username, &settings.password) {
+            builder = builder.credentials(Credentials::new(
+                username.clone(),
+                password.expose_secret().clone(),
+            ));
+        }
+
+        Ok(Self {
+            mailer: builder.build(),
+        })
+    }
+}
+
+#[async_trait::async_trait]
+impl EmailTransport for SmtpTransport {
+    async fn send(&self, email: &Email<'_>) -> Result<(), EmailError> {
+        let (envelope, message) = to_mime_message(email)?;
+
+        self.mailer
+            .send_raw(&envelope, &message)
+            .await
+            .map_err(|e| {
+                // 5xx replies: the server won't ever accept this email.
+                if e.is_permanent() {
+                    EmailError::Rejected(e.into())
+                } else {
+                    EmailError::Unavailable(e.into())
+                }
+            })?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domains::SubscriberEmail;
+    use crate::email_client::{EmailClient, EmailHeader};
+    use claim::{assert_err, assert_ok};
+    use std::sync::{Arc, Mutex};
+    use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+    use tokio::net::TcpListener;
+
+    /// A bare-bones SMTP server, accepting every message unless
+    /// `rcpt_reply` says otherwise, and keeping the DATA it received.
+    struct FakeSmtpServer {
+        port: u16,
+        messages: Arc<Mutex<Vec<String>>>,
+    }
+
+    impl FakeSmtpServer {
+        async fn start(rcpt_reply: &'static str) -> Self {
+            let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let port = listener.local_addr().unwrap().port();
+            let messages = Arc::new(Mutex::new(Vec::new()));
+
+            let received = messages.clone();
+            tokio::spawn(async move {
+                loop {
+                    let (stream, _) = listener.accept().await.unwrap();
+                    let received = received.clone();
+                    tokio::spawn(async move {
+                        let (reader, mut writer) = stream.into_split();
+                        let mut lines = BufReader::new(reader).lines();
+                        writer.write_all(b"220 localhost ESMTP\r\n").await.unwrap();
+
+                        while let Ok(Some(line)) = lines.next_line().await {
+                            let command = line.to_uppercase();
+                            let reply = if command.starts_with("EHLO") {
+                                "250 localhost\r\n"
+                            } else if command.starts_with("RCPT") {
+                                rcpt_reply
+                            } else if command.starts_with("DATA") {
+                                writer.write_all(b"354 Go ahead\r\n").await.unwrap();
+                                let mut data = String::new();
+                                while let Ok(Some(line)) = lines.next_line().await {
+                                    if line == "." {
+                                        break;
+                                    }
+                                    data.push_str(&line);
+                                    data.push('\n');
+                                }
+                                received.lock().unwrap().push(data);
+                                "250 Queued\r\n"
+                            } else if command.starts_with("QUIT") {
+                                writer.write_all(b"221 Bye\r\n").await.unwrap();
+                                break;
+                            } else {
+                                "250 OK\r\n"
+                            };
+                            writer.write_all(reply.as_bytes()).await.unwrap();
+                        }
+                    });
+                }
+            });
+
+            Self { port, messages }
+        }
+
+        fn messages(&self) -> Vec<String> {
+            self.messages.lock().unwrap().clone()
+        }
+    }
+
+    fn email(address: &str) -> SubscriberEmail {
+        SubscriberEmail::parse(address.to_string()).unwrap()
+    }
+
+    fn email_client(port: u16) -> EmailClient {
+        let settings = SmtpSettings {
+            host: "127.0.0.1".into(),
+            port,
+            username: None,
+            password: None,
+            require_tls: false,
+        };
+        let transport = SmtpTransport::new(&settings, time::Duration::from_secs(2)).unwrap();
+        EmailClient::new(email("sender@example.com"), Box::new(transport))
+    }
+
+    #[tokio::test]
+    async fn send_email_delivers_a_multipart_message_to_the_smtp_server() {
+        let server = FakeSmtpServer::start("250 OK\r\n").await;
+        let email_client = email_client(server.port);
+
+        let outcome = email_client
+            .send_email_with_headers(
+                &email("recipient@example.com"),
+                "Subject line",
+                "<p>HTML body</p>",
+                "Text body",
+                &[EmailHeader::new("X-Campaign", "weekly")],
+            )
+            .await;
+        assert_ok!(outcome);
+
+        let messages = server.messages();
+        assert_eq!(messages.len(), 1);
+        let message = &messages[0];
+        assert!(message.starts_with("X-Campaign: weekly\n"));
+        assert!(message.contains("From: sender@example.com"));
+        assert!(message.contains("To: recipient@example.com"));
+        assert!(message.contains("Subject: Subject line"));
+        assert!(message.contains("Content-Type: multipart/alternative"));
+        assert!(message.contains("Text body"));
+        assert!(message.contains("<p>HTML body</p>"));
+    }
+
+    #[tokio::test]
+    async fn permanent_smtp_errors_are_not_retryable() {
+        let server = FakeSmtpServer::start("550 No such user\r\n").await;
+        let email_client = email_client(server.port);
+
+        let error = email_client
+            .send_email(&email("recipient@example.com"), "Subject", "HTML", "Text")
+            .await
+            .unwrap_err();
+
+        assert!(!error.is_retryable());
+    }
+
+    #[tokio::test]
+    async fn transient_smtp_errors_are_retryable() {
+        let server = FakeSmtpServer::start("451 Try again later\r\n").await;
+        let email_client = email_client(server.port);
+
+        let error = email_client
+            .send_email(&email("recipient@example.com"), "Subject", "HTML", "Text")
+            .await
+            .unwrap_err();
+
+        assert!(error.is_retryable());
+    }
+
+    #[tokio::test]
+    async fn send_email_fails_if_the_smtp_server_is_unreachable() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        drop(listener);
+
+        let outcome = email_client(port)
+            .send_email(&email("recipient@example.com"), "Subject", "HTML", "Text")
+            .await;
+
+        assert_err!(outcome);
+    }
+}
@@ -1,113 +1,358 @@
+use std::collections::{hash_map::Entry, HashMap};
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc,
+};
 use std::time::Duration;
 
-use sqlx::{PgPool, Postgres, Transaction};
-use tracing::{field::display, Span};
+use chrono::{DateTime, Utc};
+use secrecy::Secret;
+use sqlx::{
+    postgres::{PgListener, PgPoolOptions},
+    PgPool, Postgres, Transaction,
+};
+use tokio::sync::Notify;
+use tracing::{Instrument, Span};
 use uuid::Uuid;
 
 use crate::{
-    configuration::Settings, domains::SubscriberEmail, email_client::EmailClient,
-    startup::get_connection_pool,
+    configuration::{DBSettings, IssueDeliverySettings, Settings},
+    domains::{SubscriberEmail, UnsubscribeToken},
+    email_client::{BatchEmail, EmailClient, EmailError, EmailHeader, MAX_BATCH_SIZE},
+    shutdown::Shutdown,
+    startup::get_connection_pool_of_size,
 };
 
+/// Postgres channel notified whenever deliveries become pending,
+/// see [`notify_delivery_workers`].
+pub const ISSUE_DELIVERY_CHANNEL: &str = "issue_delivery_queue";
+
 pub enum ExecutionOutcome {
     TaskCompleted,
     EmptyQueue,
+    /// Nothing has been claimed, the rate limit lets the next email out after `retry_after`.
+    RateLimited {
+        retry_after: Duration,
+    },
 }
 
-#[tracing::instrument(
-    skip_all,
-    fields(
-        newsletter_issue_id=tracing::field::Empty,
-        subscriber_email=tracing::field::Empty,
-    ),
-    err
-)]
+#[tracing::instrument(skip_all, fields(n_tasks = tracing::field::Empty), err)]
 pub async fn try_execute_task(
     email_client: &EmailClient,
     db_pool: &PgPool,
+    settings: &IssueDeliverySettings,
+    base_url: &str,
+    hmac_secret: &Secret<String>,
 ) -> Result<ExecutionOutcome, anyhow::Error> {
-    let task = dequeue_task(db_pool).await?;
-    if task.is_none() {
-        return Ok(ExecutionOutcome::EmptyQueue);
-    }
+    let batch_size = settings.batch_size.clamp(1, MAX_BATCH_SIZE);
+    // Only claim as many tasks as the rate limit lets out right away: waiting for it
+    // with the tasks claimed would hold their locks and a connection meanwhile.
+    // Whatever isn't sent is given back when `reservation` is dropped, errors included.
+    let reservation = match email_client.try_reserve(batch_size) {
+        Ok(reservation) => reservation,
+        Err(retry_after) => return Ok(ExecutionOutcome::RateLimited { retry_after }),
+    };
+    let (mut transaction, tasks) = match dequeue_tasks(db_pool, reservation.n_messages()).await? {
+        Some(batch) => batch,
+        None => return Ok(ExecutionOutcome::EmptyQueue),
+    };
+    Span::current().record("n_tasks", &tasks.len());
 
-    let (transaction, issue_id, subscriber_email) = task.unwrap();
-
-    Span::current()
-        .record("newsletter_issue_id", &display(issue_id))
-        .record("subscriber_email", &display(&subscriber_email));
-
-    match SubscriberEmail::parse(subscriber_email.clone()) {
-        Ok(subscriber_email) => {
-            let issue = get_issue(issue_id, db_pool).await?;
-            if let Err(e) = email_client
-                .send_email(
-                    &subscriber_email,
-                    &issue.title,
-                    &issue.html_content,
-                    &issue.text_content,
-                )
-                .await
-            {
+    let mut issues = HashMap::new();
+    let mut deliveries = Vec::with_capacity(tasks.len());
+    for task in &tasks {
+        let recipient = match SubscriberEmail::parse(task.subscriber_email.clone()) {
+            Ok(recipient) => recipient,
+            Err(error) => {
                 tracing::error!(
-                    error.cause_chain = ?e,
-                    error.message = %e,
-                    "Failed to deliver issue to a confirmed subscriber. \
-                        Skipping."
+                    error.cause_chain = ?error,
+                    subscriber_email = %task.subscriber_email,
+                    "Skipping a confirmed subscriber. \
+                    Their stored email address is invalid."
+                );
+                move_task_to_dead_letter(task, task.n_attempts, &error, &mut transaction).await?;
+                continue;
+            }
+        };
+
+        let subscriber_id = match task.subscriber_id {
+            Some(subscriber_id) => subscriber_id,
+            None => {
+                tracing::error!(
+                    subscriber_email = %task.subscriber_email,
+                    "Skipping a subscriber that doesn't exist anymore. \
+                    We can't send them an unsubscribe link."
+                );
+                move_task_to_dead_letter(
+                    task,
+                    task.n_attempts,
+                    "The subscriber doesn't exist anymore.",
+                    &mut transaction,
                 )
+                .await?;
+                continue;
             }
+        };
+
+        if let Entry::Vacant(entry) = issues.entry(task.newsletter_issue_id) {
+            entry.insert(get_issue(task.newsletter_issue_id, &mut transaction).await?);
         }
-        Err(error) => {
-            tracing::error!(
-                error.cause_chain = ?error,
-                "Skipping a confirmed subscriber. \
-                Their stored email address is invalid."
-            )
-        }
+        let issue = &issues[&task.newsletter_issue_id];
+
+        let unsubscribe_token = UnsubscribeToken::generate(subscriber_id, hmac_secret);
+        let unsubscribe_link = format!(
+            "{}/subscriptions/unsubscribe?token={}",
+            base_url,
+            unsubscribe_token.as_ref()
+        );
+        let one_click_unsubscribe_link = format!(
+            "{}/subscriptions/unsubscribe/one-click?token={}",
+            base_url,
+            unsubscribe_token.as_ref()
+        );
+        deliveries.push(Delivery {
+            task,
+            recipient,
+            html_content: with_html_unsubscribe_link(&issue.html_content, &unsubscribe_link),
+            text_content: with_text_unsubscribe_link(&issue.text_content, &unsubscribe_link),
+            headers: list_unsubscribe_headers(email_client.sender(), &one_click_unsubscribe_link),
+        });
     }
 
-    delete_task(issue_id, &subscriber_email, transaction).await?;
+    if !deliveries.is_empty() {
+        let emails = deliveries
+            .iter()
+            .map(|delivery| BatchEmail {
+                recipient: &delivery.recipient,
+                subject: &issues[&delivery.task.newsletter_issue_id].title,
+                html_content: &delivery.html_content,
+                text_content: &delivery.text_content,
+                headers: &delivery.headers,
+            })
+            .collect::<Vec<_>>();
+
+        match email_client.send_reserved_batch(reservation, &emails).await {
+            Ok(outcomes) => {
+                for (delivery, outcome) in deliveries.iter().zip(outcomes) {
+                    match outcome {
+                        Ok(()) => {
+                            record_delivery_outcome(
+                                delivery.task,
+                                DeliveryOutcome::Delivered,
+                                &mut transaction,
+                            )
+                            .await?;
+                            delete_task(delivery.task, &mut transaction).await?;
+                        }
+                        Err(EmailError::RateLimited { retry_after }) => {
+                            postpone_task(delivery.task, retry_after, &mut transaction).await?;
+                        }
+                        Err(e) => {
+                            let is_retryable = e.is_retryable();
+                            let error_message = format!("{:#}", anyhow::Error::from(e));
+                            handle_failed_delivery(
+                                delivery.task,
+                                is_retryable,
+                                &error_message,
+                                settings,
+                                &mut transaction,
+                            )
+                            .await?;
+                        }
+                    }
+                }
+            }
+            Err(EmailError::RateLimited { retry_after }) => {
+                tracing::warn!(
+                    "The email provider is rate limiting us. \
+                    Postponing a batch of {} emails by {:?}.",
+                    deliveries.len(),
+                    retry_after
+                );
+                for delivery in &deliveries {
+                    postpone_task(delivery.task, retry_after, &mut transaction).await?;
+                }
+            }
+            Err(e) => {
+                tracing::warn!(
+                    error.cause_chain = ?e,
+                    error.message = %e,
+                    "Failed to send a batch of {} emails.",
+                    deliveries.len()
+                );
+                let is_retryable = e.is_retryable();
+                let error_message = format!("{:#}", anyhow::Error::from(e));
+                for delivery in &deliveries {
+                    handle_failed_delivery(
+                        delivery.task,
+                        is_retryable,
+                        &error_message,
+                        settings,
+                        &mut transaction,
+                    )
+                    .await?;
+                }
+            }
+        }
+    }
 
+    transaction.commit().await?;
     Ok(ExecutionOutcome::TaskCompleted)
 }
 
+/// The personalised email of a subscriber, waiting to be sent as part of a batch.
+struct Delivery<'a> {
+    task: &'a Task,
+    recipient: SubscriberEmail,
+    html_content: String,
+    text_content: String,
+    headers: [EmailHeader; 2],
+}
+
+/// Retry the delivery later if the error might go away,
+/// give up on it if it can't or it has been attempted too many times already.
+#[tracing::instrument(
+    skip_all,
+    fields(
+        newsletter_issue_id=%task.newsletter_issue_id,
+        subscriber_email=%task.subscriber_email,
+        n_attempts=task.n_attempts,
+    )
+)]
+async fn handle_failed_delivery(
+    task: &Task,
+    is_retryable: bool,
+    error_message: &str,
+    settings: &IssueDeliverySettings,
+    transaction: &mut PgTransaction,
+) -> Result<(), anyhow::Error> {
+    let n_attempts = task.n_attempts + 1;
+    if is_retryable && n_attempts < settings.max_attempts {
+        tracing::warn!(
+            error.message = %error_message,
+            "Failed to deliver issue to a confirmed subscriber. \
+                Retrying later."
+        );
+        let execute_after =
+            Utc::now() + chrono::Duration::from_std(settings.backoff(task.n_attempts))?;
+        reschedule_task(task, n_attempts, execute_after, transaction).await?;
+    } else {
+        tracing::error!(
+            error.message = %error_message,
+            "Failed to deliver issue to a confirmed subscriber \
+                after {} attempts. Giving up.",
+            n_attempts
+        );
+        move_task_to_dead_letter(task, n_attempts, error_message, transaction).await?;
+    }
+
+    Ok(())
+}
+
+/// The provider asked us to slow down: try again once it's ready,
+/// without counting it as a failed attempt.
+#[tracing::instrument(skip_all)]
+async fn postpone_task(
+    task: &Task,
+    retry_after: Duration,
+    transaction: &mut PgTransaction,
+) -> Result<(), anyhow::Error> {
+    let execute_after = Utc::now() + chrono::Duration::from_std(retry_after)?;
+    reschedule_task(task, task.n_attempts, execute_after, transaction).await
+}
+
+/// Goes right before the closing `</body>` tag if the issue has one.
+fn with_html_unsubscribe_link(html_content: &str, unsubscribe_link: &str) -> String {
+    let footer = format!(
+        r#"<p><a href="{}">Unsubscribe</a> from this newsletter.</p>"#,
+        unsubscribe_link
+    );
+    match html_content.rfind("</body>") {
+        Some(index) => format!(
+            "{}{}{}",
+            &html_content[..index],
+            footer,
+            &html_content[index..]
+        ),
+        None => format!("{}\n{}", html_content, footer),
+    }
+}
+
+fn with_text_unsubscribe_link(text_content: &str, unsubscribe_link: &str) -> String {
+    format!(
+        "{}\n\nTo unsubscribe from this newsletter, visit {}",
+        text_content, unsubscribe_link
+    )
+}
+
+/// Let mailbox providers show their own unsubscribe button (RFC 2369),
+/// unsubscribing with a single POST request to the one-click link (RFC 8058).
+fn list_unsubscribe_headers(
+    sender: &SubscriberEmail,
+    one_click_unsubscribe_link: &str,
+) -> [EmailHeader; 2] {
+    [
+        EmailHeader::new(
+            "List-Unsubscribe",
+            format!(
+                "<mailto:{}?subject=unsubscribe>, <{}>",
+                sender, one_click_unsubscribe_link
+            ),
+        ),
+        EmailHeader::new("List-Unsubscribe-Post", "List-Unsubscribe=One-Click"),
+    ]
+}
+
 type PgTransaction = Transaction<'static, Postgres>;
 
+struct Task {
+    newsletter_issue_id: Uuid,
+    subscriber_email: String,
+    subscriber_id: Option<Uuid>,
+    n_attempts: i16,
+}
+
+/// Claim up to `batch_size` tasks that are due, skipping the ones
+/// that other workers are already busy with.
 #[tracing::instrument(skip_all)]
-async fn dequeue_task(
+async fn dequeue_tasks(
     db_pool: &PgPool,
-) -> Result<Option<(PgTransaction, Uuid, String)>, anyhow::Error> {
+    batch_size: usize,
+) -> Result<Option<(PgTransaction, Vec<Task>)>, anyhow::Error> {
     let mut transaction = db_pool.begin().await?;
 
-    let row = sqlx::query!(
+    let tasks = sqlx::query_as!(
+        Task,
         r#"
-        SELECT newsletter_issue_id, subscriber_email
-        FROM issue_delivery_queue
-        FOR UPDATE
+        SELECT
+            q.newsletter_issue_id,
+            q.subscriber_email,
+            s.id AS "subscriber_id?",
+            q.n_attempts
+        FROM issue_delivery_queue q
+        JOIN newsletter_issues i ON i.id = q.newsletter_issue_id
+        LEFT JOIN subscriptions s ON s.email = q.subscriber_email
+        WHERE
+            q.execute_after <= now() AND
+            i.published_at <= now()
+        FOR UPDATE OF q
         SKIP LOCKED
-        LIMIT 1
-    "#
+        LIMIT $1
+    "#,
+        i64::try_from(batch_size)?
     )
-    .fetch_optional(&mut transaction)
+    .fetch_all(&mut transaction)
     .await?;
 
-    if let Some(row) = row {
-        Ok(Some((
-            transaction,
-            row.newsletter_issue_id,
-            row.subscriber_email,
-        )))
-    } else {
-        Ok(None)
+    if tasks.is_empty() {
+        return Ok(None);
     }
+
+    Ok(Some((transaction, tasks)))
 }
 
 #[tracing::instrument(skip_all)]
-async fn delete_task(
-    issue_id: Uuid,
-    subscriber_email: &str,
-    mut transaction: PgTransaction,
-) -> Result<(), anyhow::Error> {
+async fn delete_task(task: &Task, transaction: &mut PgTransaction) -> Result<(), anyhow::Error> {
     sqlx::query!(
         r#"
             DELETE FROM issue_delivery_queue
@@ -115,13 +360,117 @@ async fn delete_task(
                 newsletter_issue_id = $1 AND
                 subscriber_email = $2
         "#,
-        issue_id,
-        subscriber_email
+        task.newsletter_issue_id,
+        task.subscriber_email
     )
-    .execute(&mut transaction)
+    .execute(transaction)
+    .await?;
+
+    Ok(())
+}
+
+#[tracing::instrument(skip_all)]
+async fn reschedule_task(
+    task: &Task,
+    n_attempts: i16,
+    execute_after: DateTime<Utc>,
+    transaction: &mut PgTransaction,
+) -> Result<(), anyhow::Error> {
+    sqlx::query!(
+        r#"
+            UPDATE issue_delivery_queue
+            SET
+                n_attempts = $3,
+                execute_after = $4
+            WHERE
+                newsletter_issue_id = $1 AND
+                subscriber_email = $2
+        "#,
+        task.newsletter_issue_id,
+        task.subscriber_email,
+        n_attempts,
+        execute_after
+    )
+    .execute(&mut *transaction)
+    .await?;
+
+    // Idle workers might be sleeping past the new execution time.
+    notify_delivery_workers(transaction).await?;
+
+    Ok(())
+}
+
+#[tracing::instrument(skip_all)]
+async fn move_task_to_dead_letter(
+    task: &Task,
+    n_attempts: i16,
+    error_message: &str,
+    transaction: &mut PgTransaction,
+) -> Result<(), anyhow::Error> {
+    sqlx::query!(
+        r#"
+            INSERT INTO issue_delivery_failures (
+                newsletter_issue_id,
+                subscriber_email,
+                error_message,
+                n_attempts,
+                failed_at
+            ) VALUES ($1, $2, $3, $4, now())
+            ON CONFLICT (newsletter_issue_id, subscriber_email) DO UPDATE
+            SET
+                error_message = EXCLUDED.error_message,
+                n_attempts = EXCLUDED.n_attempts,
+                failed_at = EXCLUDED.failed_at
+        "#,
+        task.newsletter_issue_id,
+        task.subscriber_email,
+        error_message,
+        n_attempts
+    )
+    .execute(&mut *transaction)
+    .await?;
+
+    record_delivery_outcome(task, DeliveryOutcome::Failed, transaction).await?;
+    delete_task(task, transaction).await
+}
+
+enum DeliveryOutcome {
+    Delivered,
+    Failed,
+}
+
+/// Update the delivery progress of the task's issue,
+/// marking it as completed once every recipient has been handled.
+#[tracing::instrument(skip_all)]
+async fn record_delivery_outcome(
+    task: &Task,
+    outcome: DeliveryOutcome,
+    transaction: &mut PgTransaction,
+) -> Result<(), anyhow::Error> {
+    let (n_delivered, n_failed) = match outcome {
+        DeliveryOutcome::Delivered => (1, 0),
+        DeliveryOutcome::Failed => (0, 1),
+    };
+
+    sqlx::query!(
+        r#"
+            UPDATE newsletter_issues
+            SET
+                n_delivered = n_delivered + $2,
+                n_failed = n_failed + $3,
+                completed_at = CASE
+                    WHEN n_delivered + $2 + n_failed + $3 >= n_total_recipients THEN now()
+                    ELSE completed_at
+                END
+            WHERE id = $1
+        "#,
+        task.newsletter_issue_id,
+        n_delivered,
+        n_failed
+    )
+    .execute(transaction)
     .await?;
 
-    transaction.commit().await?;
     Ok(())
 }
 
@@ -131,8 +480,13 @@ struct NewsletterIssue {
     html_content: String,
 }
 
+/// Through the transaction of the batch: taking a second connection from the pool
+/// while holding one could starve the workers.
 #[tracing::instrument(skip_all)]
-async fn get_issue(issue_id: Uuid, db_pool: &PgPool) -> Result<NewsletterIssue, sqlx::Error> {
+async fn get_issue(
+    issue_id: Uuid,
+    transaction: &mut PgTransaction,
+) -> Result<NewsletterIssue, sqlx::Error> {
     let issue = sqlx::query_as!(
         NewsletterIssue,
         r#"
@@ -143,29 +497,237 @@ async fn get_issue(issue_id: Uuid, db_pool: &PgPool) -> Result<NewsletterIssue,
     "#,
         issue_id
     )
-    .fetch_one(db_pool)
+    .fetch_one(transaction)
     .await?;
 
     Ok(issue)
 }
 
-async fn worker_loop(email_client: EmailClient, db_pool: PgPool) -> Result<(), anyhow::Error> {
+/// Wake the idle delivery workers up once the transaction commits,
+/// to be called whenever deliveries are enqueued or rescheduled.
+///
+/// Postgres folds identical notifications sent by a transaction into one,
+/// calling this for every delivery doesn't flood the workers.
+#[tracing::instrument(skip_all)]
+pub async fn notify_delivery_workers(
+    transaction: &mut Transaction<'_, Postgres>,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!("SELECT pg_notify($1, '')", ISSUE_DELIVERY_CHANNEL)
+        .execute(transaction)
+        .await?;
+
+    Ok(())
+}
+
+/// Shared by the workers of a process, woken up by a single
+/// listener on [`ISSUE_DELIVERY_CHANNEL`].
+#[derive(Default)]
+struct WakeUpSignal {
+    notify: Notify,
+    is_listening: AtomicBool,
+}
+
+impl WakeUpSignal {
+    fn wake_up_workers(&self) {
+        self.notify.notify_waiters();
+    }
+
+    fn is_listening(&self) -> bool {
+        self.is_listening.load(Ordering::SeqCst)
+    }
+
+    fn set_listening(&self, is_listening: bool) {
+        self.is_listening.store(is_listening, Ordering::SeqCst);
+        // Workers re-evaluate how they should wait for new deliveries.
+        self.wake_up_workers();
+    }
+}
+
+/// Forward the notifications of [`ISSUE_DELIVERY_CHANNEL`] to the workers,
+/// reconnecting for as long as it takes if the connection drops.
+///
+/// The listener gets a connection of its own,
+/// it would otherwise hold one of the workers' forever.
+async fn listen_for_pending_deliveries(
+    database: DBSettings,
+    signal: Arc<WakeUpSignal>,
+    retry_interval: Duration,
+) {
+    let listener_pool = PgPoolOptions::new()
+        .max_connections(1)
+        .connect_lazy_with(database.with_db());
+
     loop {
-        match try_execute_task(&email_client, &db_pool).await {
-            Ok(ExecutionOutcome::EmptyQueue) => {
-                tokio::time::sleep(Duration::from_secs(10)).await;
+        let mut listener = match PgListener::connect_with(&listener_pool).await {
+            Ok(listener) => listener,
+            Err(e) => {
+                tracing::warn!(
+                    error.cause_chain = ?e,
+                    "Failed to connect the delivery queue listener. \
+                    Falling back to polling the queue."
+                );
+                tokio::time::sleep(retry_interval).await;
+                continue;
             }
-            Err(_) => {
-                tokio::time::sleep(Duration::from_secs(1)).await;
+        };
+        if let Err(e) = listener.listen(ISSUE_DELIVERY_CHANNEL).await {
+            tracing::warn!(
+                error.cause_chain = ?e,
+                "Failed to listen for pending deliveries. \
+                Falling back to polling the queue."
+            );
+            tokio::time::sleep(retry_interval).await;
+            continue;
+        }
+        tracing::info!("Listening for pending deliveries.");
+        // Deliveries enqueued while nobody was listening are picked up too.
+        signal.set_listening(true);
+
+        // `try_recv` yields `None` when the connection is lost,
+        // start over with a fresh listener then.
+        while let Ok(Some(_)) = listener.try_recv().await {
+            signal.wake_up_workers();
+        }
+        tracing::warn!(
+            "Lost the connection of the delivery queue listener. \
+            Falling back to polling the queue."
+        );
+        signal.set_listening(false);
+    }
+}
+
+/// How long until the earliest delivery that isn't due yet,
+/// `None` if there isn't any.
+#[tracing::instrument(skip_all)]
+async fn time_until_next_delivery(db_pool: &PgPool) -> Result<Option<Duration>, anyhow::Error> {
+    let row = sqlx::query!(
+        r#"
+        SELECT MIN(GREATEST(q.execute_after, i.published_at)) AS next_delivery_at
+        FROM issue_delivery_queue q
+        JOIN newsletter_issues i ON i.id = q.newsletter_issue_id
+        WHERE GREATEST(q.execute_after, i.published_at) > now()
+    "#
+    )
+    .fetch_one(db_pool)
+    .await?;
+
+    Ok(row
+        .next_delivery_at
+        .map(|next_delivery_at| (next_delivery_at - Utc::now()).to_std().unwrap_or_default()))
+}
+
+/// Process the queue until `shutdown` is triggered.
+/// A batch that has been claimed is always seen through: cancelling it between
+/// sending the emails and committing would deliver them twice.
+async fn worker_loop(
+    email_client: Arc<EmailClient>,
+    db_pool: PgPool,
+    settings: IssueDeliverySettings,
+    base_url: String,
+    hmac_secret: Secret<String>,
+    signal: Arc<WakeUpSignal>,
+    shutdown: Shutdown,
+) -> Result<(), anyhow::Error> {
+    while !shutdown.is_triggered() {
+        // Created before looking at the queue,
+        // so that we don't miss a notification sent in the meantime.
+        let wake_up = signal.notify.notified();
+
+        let outcome =
+            try_execute_task(&email_client, &db_pool, &settings, &base_url, &hmac_secret).await;
+        let wait = async {
+            match outcome {
+                Ok(ExecutionOutcome::EmptyQueue) if signal.is_listening() => {
+                    // Scheduled issues and retries don't send a notification when they become due.
+                    match time_until_next_delivery(&db_pool).await {
+                        Ok(Some(delay)) => {
+                            let _ = tokio::time::timeout(delay, wake_up).await;
+                        }
+                        Ok(None) => wake_up.await,
+                        Err(_) => tokio::time::sleep(settings.error_poll_interval()).await,
+                    }
+                }
+                Ok(ExecutionOutcome::EmptyQueue) => {
+                    tokio::time::sleep(settings.empty_queue_poll_interval()).await;
+                }
+                Ok(ExecutionOutcome::RateLimited { retry_after }) => {
+                    tokio::time::sleep(retry_after).await;
+                }
+                Err(_) => {
+                    tokio::time::sleep(settings.error_poll_interval()).await;
+                }
+                Ok(ExecutionOutcome::TaskCompleted) => {}
             }
-            Ok(ExecutionOutcome::TaskCompleted) => {}
+        };
+        tokio::select! {
+            _ = wait => {}
+            _ = shutdown.wait() => {}
         }
     }
+
+    Ok(())
 }
 
-pub async fn run_worker_until_stopped(configuration: Settings) -> Result<(), anyhow::Error> {
-    let connection_pool = get_connection_pool(&configuration.database);
-    let email_client = configuration.email_client.client();
+/// Run `n_workers` workers sharing the same connection pool, which has a connection
+/// for each of them and one more for the queries made between batches.
+/// `FOR UPDATE SKIP LOCKED` in `dequeue_tasks` keeps them from claiming the same tasks.
+///
+/// Idle workers wait for a notification on [`ISSUE_DELIVERY_CHANNEL`],
+/// they only poll the queue while the listener is disconnected.
+///
+/// Once `shutdown` is triggered the workers stop claiming tasks,
+/// those with a batch in flight get up to the drain timeout to finish it.
+pub async fn run_worker_until_stopped(
+    configuration: Settings,
+    shutdown: Shutdown,
+) -> Result<(), anyhow::Error> {
+    let settings = configuration.issue_delivery;
+    let n_workers = settings.n_workers.max(1);
+    let connection_pool =
+        get_connection_pool_of_size(&configuration.database, n_workers as u32 + 1);
+    let email_client = Arc::new(configuration.email_client.client());
+    let drain_timeout = configuration.application.drain_timeout();
+
+    let signal = Arc::new(WakeUpSignal::default());
+    tokio::spawn(
+        listen_for_pending_deliveries(
+            configuration.database,
+            signal.clone(),
+            settings.empty_queue_poll_interval(),
+        )
+        .instrument(tracing::info_span!("Delivery queue listener")),
+    );
+
+    let workers = (0..n_workers)
+        .map(|worker_id| {
+            tokio::spawn(
+                worker_loop(
+                    email_client.clone(),
+                    connection_pool.clone(),
+                    settings.clone(),
+                    configuration.application.base_url.clone(),
+                    configuration.application.hmac_secret.clone(),
+                    signal.clone(),
+                    shutdown.clone(),
+                )
+                .instrument(tracing::info_span!("Delivery worker", worker_id)),
+            )
+        })
+        .collect::<Vec<_>>();
+
+    // Workers stop on their own if something went really wrong,
+    // bring the whole pool down as soon as one of them does.
+    let (outcome, _, other_workers) = futures::future::select_all(workers).await;
+    shutdown.trigger();
+    if tokio::time::timeout(drain_timeout, futures::future::join_all(other_workers))
+        .await
+        .is_err()
+    {
+        tracing::warn!(
+            "Delivery workers didn't finish their batch within the drain timeout. \
+            Its deliveries are back in the queue and might be sent twice."
+        );
+    }
 
-    worker_loop(email_client, connection_pool).await
+    outcome?
 }
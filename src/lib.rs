@@ -1,4 +1,5 @@
 use std::net::TcpListener;
+use std::time::Duration;
 
 use actix_session::storage::RedisSessionStore;
 use actix_session::SessionMiddleware;
@@ -8,13 +9,15 @@ use actix_web::{web, App, HttpServer};
 use actix_web_flash_messages::storage::CookieMessageStore;
 use actix_web_flash_messages::FlashMessagesFramework;
 use actix_web_lab::middleware::from_fn;
-use authentication::reject_anonymous_users;
+use authentication::{reject_anonymous_users, require_editor, require_owner};
+use configuration::{LoginThrottlingSettings, SubscriptionSettings};
 use email_client::EmailClient;
 use secrecy::{ExposeSecret, Secret};
 use sqlx::PgPool;
-use startup::ApplicationBaseUrl;
+use startup::{ApplicationBaseUrl, HmacSecret};
 use tracing_actix_web::TracingLogger;
 
+pub mod audit_log;
 pub mod authentication;
 pub mod configuration;
 pub mod domains;
@@ -22,21 +25,31 @@ pub mod email_client;
 pub mod issue_delivery_worker;
 pub mod routes;
 pub mod session_state;
+pub mod shutdown;
 pub mod startup;
+pub mod subscription_purge;
 pub mod telemetry;
 pub mod utils;
 
+#[allow(clippy::too_many_arguments)]
 pub async fn run(
     listener: TcpListener,
     db_pool: PgPool,
     email_client: EmailClient,
     base_url: String,
     hmac_secret: Secret<String>,
+    public_archive: bool,
     redis_uri: Secret<String>,
+    drain_timeout: Duration,
+    login_throttling: LoginThrottlingSettings,
+    subscription_settings: SubscriptionSettings,
 ) -> Result<Server, anyhow::Error> {
     let db_pool = web::Data::new(db_pool);
     let email_client = web::Data::new(email_client);
     let base_url = web::Data::new(ApplicationBaseUrl(base_url));
+    let unsubscribe_secret = web::Data::new(HmacSecret(hmac_secret.clone()));
+    let login_throttling = web::Data::new(login_throttling);
+    let subscription_settings = web::Data::new(subscription_settings);
 
     let hmac_secret = Key::from(hmac_secret.expose_secret().as_bytes());
     let message_store = CookieMessageStore::builder(hmac_secret.clone()).build();
@@ -54,18 +67,128 @@ pub async fn run(
             .route("/health-check", web::get().to(routes::health_check))
             .route("/subscriptions", web::post().to(routes::subscribe))
             .route("/subscriptions/confirm", web::get().to(routes::confirm))
+            .route(
+                "/subscriptions/unsubscribe",
+                web::get().to(routes::unsubscribe_form),
+            )
+            .route(
+                "/subscriptions/unsubscribe",
+                web::post().to(routes::unsubscribe),
+            )
+            .route(
+                "/subscriptions/unsubscribe/one-click",
+                web::post().to(routes::unsubscribe_one_click),
+            )
             .route("/", web::get().to(routes::home))
             .route("/login", web::get().to(routes::login_form))
             .route("/login", web::post().to(routes::login))
+            .route("/login/2fa", web::get().to(routes::two_factor_form))
+            .route("/login/2fa", web::post().to(routes::verify_two_factor))
+            .route("/login/forgot", web::get().to(routes::forgot_password_form))
+            .route("/login/forgot", web::post().to(routes::forgot_password))
+            .route("/login/reset", web::get().to(routes::reset_password_form))
+            .route(
+                "/login/reset",
+                web::post().to(routes::reset_forgotten_password),
+            )
+            .configure(|cfg| {
+                if public_archive {
+                    cfg.route("/archive", web::get().to(routes::archive))
+                        .route("/archive/{issue_id}", web::get().to(routes::archived_issue));
+                }
+            })
             .service(
                 web::scope("/admin")
                     .wrap(from_fn(reject_anonymous_users))
                     .route("/dashboard", web::get().to(routes::admin_dashboard))
+                    .service(
+                        web::resource("/newsletters")
+                            .wrap(from_fn(require_editor))
+                            .route(web::get().to(routes::publish_newsletter_form))
+                            .route(web::post().to(routes::publish_newsletter)),
+                    )
+                    .route(
+                        "/newsletters/history",
+                        web::get().to(routes::newsletter_history),
+                    )
+                    .service(
+                        web::scope("/newsletters/drafts")
+                            .wrap(from_fn(require_editor))
+                            .route("", web::get().to(routes::list_drafts))
+                            .route("", web::post().to(routes::create_draft))
+                            .route("/{draft_id}", web::get().to(routes::edit_draft_form))
+                            .route("/{draft_id}", web::post().to(routes::update_draft))
+                            .route("/{draft_id}/delete", web::post().to(routes::delete_draft))
+                            .route(
+                                "/{draft_id}/preview/html",
+                                web::get().to(routes::preview_draft_html),
+                            )
+                            .route(
+                                "/{draft_id}/preview/text",
+                                web::get().to(routes::preview_draft_text),
+                            )
+                            .route("/{draft_id}/test", web::post().to(routes::send_test_draft))
+                            .route("/{draft_id}/publish", web::post().to(routes::publish_draft)),
+                    )
+                    .route(
+                        "/newsletters/{issue_id}",
+                        web::get().to(routes::issue_delivery_status),
+                    )
+                    .route(
+                        "/newsletters/{issue_id}/status",
+                        web::get().to(routes::issue_delivery_status_json),
+                    )
+                    .service(
+                        web::resource("/newsletters/{issue_id}/cancel")
+                            .wrap(from_fn(require_editor))
+                            .route(web::post().to(routes::cancel_scheduled_issue)),
+                    )
+                    .service(
+                        web::resource("/newsletters/{issue_id}/reschedule")
+                            .wrap(from_fn(require_editor))
+                            .route(web::post().to(routes::reschedule_issue)),
+                    )
+                    .route(
+                        "/newsletters/{issue_id}/failures",
+                        web::get().to(routes::issue_delivery_failures),
+                    )
+                    .service(
+                        web::resource("/newsletters/{issue_id}/failures/requeue")
+                            .wrap(from_fn(require_editor))
+                            .route(web::post().to(routes::requeue_failed_delivery)),
+                    )
+                    .service(
+                        web::scope("/users")
+                            .wrap(from_fn(require_owner))
+                            .route("", web::get().to(routes::users_page))
+                            .route("", web::post().to(routes::add_user))
+                            .route("/{user_id}/role", web::post().to(routes::change_user_role))
+                            .route(
+                                "/{user_id}/email",
+                                web::post().to(routes::change_user_email),
+                            )
+                            .route("/{user_id}/disable", web::post().to(routes::disable_user))
+                            .route("/{user_id}/enable", web::post().to(routes::enable_user))
+                            .route("/{user_id}/delete", web::post().to(routes::remove_user)),
+                    )
+                    .service(
+                        web::scope("/audit")
+                            .wrap(from_fn(require_owner))
+                            .route("", web::get().to(routes::audit_log_page))
+                            .route("/export", web::get().to(routes::export_audit_log)),
+                    )
+                    .route("/2fa", web::get().to(routes::two_factor_settings))
+                    .route("/2fa", web::post().to(routes::enable_two_factor))
+                    .route("/2fa/disable", web::post().to(routes::disable_two_factor))
+                    .route("/sessions", web::get().to(routes::sessions_page))
+                    .route(
+                        "/sessions/revoke-others",
+                        web::post().to(routes::revoke_other_sessions),
+                    )
                     .route(
-                        "/newsletters",
-                        web::get().to(routes::publish_newsletter_form),
+                        "/sessions/{session_id}/revoke",
+                        web::post().to(routes::revoke_session),
                     )
-                    .route("/newsletters", web::post().to(routes::publish_newsletter))
                     .route("/password", web::get().to(routes::change_password_form))
                     .route("/password", web::post().to(routes::change_password))
                     .route("/logout", web::post().to(routes::logout)),
@@ -73,8 +196,14 @@ pub async fn run(
             .app_data(db_pool.clone())
             .app_data(email_client.clone())
             .app_data(base_url.clone())
+            .app_data(unsubscribe_secret.clone())
+            .app_data(login_throttling.clone())
+            .app_data(subscription_settings.clone())
     })
     .listen(listener)?
+    // Stopping is driven by `Application::run_until_stopped`, see `shutdown`.
+    .disable_signals()
+    .shutdown_timeout(drain_timeout.as_secs())
     .run();
 
     Ok(server)
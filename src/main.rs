@@ -1,28 +1,223 @@
+use clap::{Parser, Subcommand};
+use secrecy::Secret;
+use sqlx::PgPool;
 use std::fmt::{Debug, Display};
-use tokio::task::JoinError;
+use tokio::task::{JoinError, JoinHandle};
+use zero2prod::authentication::{
+    create_user, delete_user, find_user_id, list_users, reset_password, set_email, Role,
+};
 use zero2prod::configuration::get_configuration;
+use zero2prod::domains::SubscriberEmail;
 use zero2prod::issue_delivery_worker::run_worker_until_stopped;
-use zero2prod::startup::Application;
+use zero2prod::shutdown::{wait_for_termination_signal, Shutdown};
+use zero2prod::startup::{get_connection_pool, migrate_database, Application};
+use zero2prod::subscription_purge::run_purge_until_stopped;
 use zero2prod::telemetry::{get_subscriber, init_subscriber};
 
+/// The API and the delivery workers can run in separate processes,
+/// to scale them independently.
+#[derive(Parser, Debug)]
+#[clap(about)]
+struct Cli {
+    #[clap(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand, Debug, PartialEq, Eq)]
+enum Command {
+    /// Serve the API and deliver newsletter issues (the default)
+    All,
+    /// Serve the API only
+    Serve,
+    /// Deliver newsletter issues and purge stale pending subscriptions only
+    Worker,
+    /// Apply the pending database migrations and exit
+    Migrate,
+    /// Manage the accounts that can log into the admin dashboard
+    Users {
+        #[clap(subcommand)]
+        command: UsersCommand,
+    },
+}
+
+#[derive(Subcommand, Debug, PartialEq, Eq)]
+enum UsersCommand {
+    /// Create a user, prompting for their password
+    Add {
+        username: String,
+        /// owner, editor or viewer
+        #[clap(long, default_value = "editor", value_parser = Role::parse)]
+        role: Role,
+        /// Read the password from the first line of stdin instead of prompting for it
+        #[clap(long)]
+        password_stdin: bool,
+    },
+    /// List every user
+    List,
+    /// Set a new password for a user, prompting for it
+    ResetPassword {
+        username: String,
+        /// Read the password from the first line of stdin instead of prompting for it
+        #[clap(long)]
+        password_stdin: bool,
+    },
+    /// Set the email address password reset links are sent to, or remove it
+    SetEmail {
+        username: String,
+        email: Option<String>,
+    },
+    /// Delete a user
+    Delete { username: String },
+}
+
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
+    let command = Cli::parse().command.unwrap_or(Command::All);
+    let configuration = get_configuration().expect("Failed to get configurations");
+
+    if let Command::Users { command } = command {
+        // Keep stdout for the output of the command.
+        let subscriber = get_subscriber("zero2prod".into(), "warn".into(), std::io::stderr);
+        init_subscriber(subscriber);
+        let connection_pool = get_connection_pool(&configuration.database);
+        return manage_users(command, &connection_pool).await;
+    }
+
     let subscriber = get_subscriber("zero2prod".into(), "info".into(), std::io::stdout);
     init_subscriber(subscriber);
 
-    let configuration = get_configuration().expect("Failed to get configurations");
-    let application = Application::build(configuration.clone()).await?;
-    let application_task = tokio::spawn(application.run_until_stopped());
-    let worker_task = tokio::spawn(run_worker_until_stopped(configuration));
+    if command == Command::Migrate {
+        let connection_pool = get_connection_pool(&configuration.database);
+        migrate_database(&connection_pool).await?;
+        tracing::info!("The database has been migrated.");
+        return Ok(());
+    }
 
-    tokio::select! {
-        o = application_task => report_exit("API", o),
-        o = worker_task => report_exit("Background worker", o)
+    let shutdown = Shutdown::new();
+    tokio::spawn({
+        let shutdown = shutdown.clone();
+        async move {
+            wait_for_termination_signal().await;
+            tracing::info!("Received a termination signal, shutting down.");
+            shutdown.trigger();
+        }
+    });
+
+    let application_task = match command {
+        Command::All | Command::Serve => {
+            let application = Application::build(configuration.clone()).await?;
+            Some(tokio::spawn(
+                application.run_until_stopped(shutdown.clone()),
+            ))
+        }
+        _ => None,
+    };
+    let (worker_task, purge_task) = match command {
+        Command::All | Command::Worker => (
+            Some(tokio::spawn(run_worker_until_stopped(
+                configuration.clone(),
+                shutdown.clone(),
+            ))),
+            Some(tokio::spawn(run_purge_until_stopped(
+                configuration,
+                shutdown.clone(),
+            ))),
+        ),
+        _ => (None, None),
+    };
+
+    tokio::join!(
+        run_to_completion("API", application_task, &shutdown),
+        run_to_completion("Background worker", worker_task, &shutdown),
+        run_to_completion("Subscription purge", purge_task, &shutdown)
+    );
+
+    Ok(())
+}
+
+async fn manage_users(command: UsersCommand, db_pool: &PgPool) -> anyhow::Result<()> {
+    match command {
+        UsersCommand::Add {
+            username,
+            role,
+            password_stdin,
+        } => {
+            let password = read_password(password_stdin)?;
+            let user_id = create_user(&username, password, role, db_pool).await?;
+            println!("Created {} ({}) as {}.", username.trim(), user_id, role);
+        }
+        UsersCommand::List => {
+            for user in list_users(db_pool).await? {
+                let status = if user.disabled { "disabled" } else { "enabled" };
+                println!(
+                    "{}\t{}\t{}\t{}\t{}",
+                    user.id,
+                    user.username,
+                    user.role,
+                    status,
+                    user.email.as_deref().unwrap_or("-")
+                );
+            }
+        }
+        UsersCommand::ResetPassword {
+            username,
+            password_stdin,
+        } => {
+            let password = read_password(password_stdin)?;
+            let user_id = find_user_id(&username, db_pool).await?;
+            reset_password(user_id, password, db_pool).await?;
+            println!("The password of {} has been reset.", username);
+        }
+        UsersCommand::SetEmail { username, email } => {
+            let email = email
+                .map(SubscriberEmail::parse)
+                .transpose()
+                .map_err(anyhow::Error::msg)?;
+            let user_id = find_user_id(&username, db_pool).await?;
+            set_email(user_id, email.as_ref(), db_pool).await?;
+            match email {
+                Some(email) => println!("The email address of {} is now {}.", username, email),
+                None => println!("{} has no email address anymore.", username),
+            }
+        }
+        UsersCommand::Delete { username } => {
+            let user_id = find_user_id(&username, db_pool).await?;
+            delete_user(user_id, db_pool).await?;
+            println!("Deleted {}.", username);
+        }
     }
 
     Ok(())
 }
 
+fn read_password(from_stdin: bool) -> anyhow::Result<Secret<String>> {
+    let password = if from_stdin {
+        let mut line = String::new();
+        std::io::stdin().read_line(&mut line)?;
+        line.trim_end_matches(&['\r', '\n'][..]).to_string()
+    } else {
+        let password = rpassword::prompt_password("Password: ")?;
+        if password != rpassword::prompt_password("Confirm the password: ")? {
+            anyhow::bail!("The passwords don't match.");
+        }
+        password
+    };
+
+    Ok(Secret::new(password))
+}
+
+/// Whichever task stops first brings the others down gracefully.
+async fn run_to_completion<E: Debug + Display>(
+    task_name: &str,
+    task: Option<JoinHandle<Result<(), E>>>,
+    shutdown: &Shutdown,
+) {
+    if let Some(task) = task {
+        report_exit(task_name, task.await);
+        shutdown.trigger();
+    }
+}
+
 fn report_exit(task_name: &str, outcome: Result<Result<(), impl Debug + Display>, JoinError>) {
     match outcome {
         Ok(Ok(())) => {
@@ -46,3 +241,99 @@ fn report_exit(task_name: &str, outcome: Result<Result<(), impl Debug + Display>
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{Cli, Command, UsersCommand};
+    use clap::Parser;
+    use zero2prod::authentication::Role;
+
+    fn parse(args: &[&str]) -> Option<Command> {
+        Cli::try_parse_from(std::iter::once("zero2prod").chain(args.iter().copied()))
+            .unwrap()
+            .command
+    }
+
+    #[test]
+    fn no_subcommand_runs_everything() {
+        assert_eq!(parse(&[]), None);
+    }
+
+    #[test]
+    fn each_subcommand_is_parsed() {
+        assert_eq!(parse(&["all"]), Some(Command::All));
+        assert_eq!(parse(&["serve"]), Some(Command::Serve));
+        assert_eq!(parse(&["worker"]), Some(Command::Worker));
+        assert_eq!(parse(&["migrate"]), Some(Command::Migrate));
+    }
+
+    #[test]
+    fn users_subcommands_are_parsed() {
+        assert_eq!(
+            parse(&["users", "add", "ursula", "--password-stdin"]),
+            Some(Command::Users {
+                command: UsersCommand::Add {
+                    username: "ursula".into(),
+                    role: Role::Editor,
+                    password_stdin: true
+                }
+            })
+        );
+        assert_eq!(
+            parse(&["users", "add", "ursula", "--role", "viewer"]),
+            Some(Command::Users {
+                command: UsersCommand::Add {
+                    username: "ursula".into(),
+                    role: Role::Viewer,
+                    password_stdin: false
+                }
+            })
+        );
+        assert_eq!(
+            parse(&["users", "reset-password", "ursula"]),
+            Some(Command::Users {
+                command: UsersCommand::ResetPassword {
+                    username: "ursula".into(),
+                    password_stdin: false
+                }
+            })
+        );
+        assert_eq!(
+            parse(&["users", "set-email", "ursula", "ursula@example.com"]),
+            Some(Command::Users {
+                command: UsersCommand::SetEmail {
+                    username: "ursula".into(),
+                    email: Some("ursula@example.com".into())
+                }
+            })
+        );
+        assert_eq!(
+            parse(&["users", "set-email", "ursula"]),
+            Some(Command::Users {
+                command: UsersCommand::SetEmail {
+                    username: "ursula".into(),
+                    email: None
+                }
+            })
+        );
+        assert_eq!(
+            parse(&["users", "list"]),
+            Some(Command::Users {
+                command: UsersCommand::List
+            })
+        );
+    }
+
+    #[test]
+    fn unknown_subcommands_are_rejected() {
+        assert!(Cli::try_parse_from(["zero2prod", "frobnicate"]).is_err());
+    }
+
+    #[test]
+    fn unknown_roles_are_rejected() {
+        assert!(
+            Cli::try_parse_from(["zero2prod", "users", "add", "ursula", "--role", "admin"])
+                .is_err()
+        );
+    }
+}
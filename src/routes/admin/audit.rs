@@ -0,0 +1,302 @@
+use actix_web::{
+    http::header::{self, ContentType},
+    web, HttpResponse,
+};
+use chrono::NaiveDate;
+use htmlescape::encode_minimal;
+use sqlx::PgPool;
+use std::fmt::Write;
+
+use crate::{
+    audit_log::{count_audit_events, list_audit_events, AuditAction, AuditLogFilter},
+    utils::{e400, e500},
+};
+
+const EVENTS_PER_PAGE: i64 = 50;
+
+/// Empty fields, as sent by the filter form, are ignored.
+#[derive(serde::Deserialize)]
+pub struct AuditLogQuery {
+    pub actor: Option<String>,
+    pub action: Option<String>,
+    pub since: Option<String>,
+    pub until: Option<String>,
+    pub page: Option<i64>,
+}
+
+impl AuditLogQuery {
+    fn filter(&self) -> Result<AuditLogFilter, String> {
+        let parse_date = |date: &str| {
+            NaiveDate::parse_from_str(date, "%Y-%m-%d")
+                .map_err(|_| format!("{} is not a valid date, use YYYY-MM-DD.", date))
+        };
+
+        let until = non_empty(&self.until).map(parse_date).transpose()?;
+        // The events of `until` end at the start of the next day, which has to exist.
+        if let Some(until) = until {
+            if until.succ_opt().is_none() {
+                return Err(format!("{} is too far in the future.", until));
+            }
+        }
+
+        Ok(AuditLogFilter {
+            actor: non_empty(&self.actor).map(str::to_string),
+            action: non_empty(&self.action)
+                .map(AuditAction::parse)
+                .transpose()?,
+            since: non_empty(&self.since).map(parse_date).transpose()?,
+            until,
+        })
+    }
+
+    /// The filters as a query string, to carry them over to other pages and the export.
+    fn filter_query_string(&self) -> String {
+        [
+            ("actor", &self.actor),
+            ("action", &self.action),
+            ("since", &self.since),
+            ("until", &self.until),
+        ]
+        .into_iter()
+        .filter_map(|(key, value)| non_empty(value).map(|value| (key, value)))
+        .map(|(key, value)| format!("{}={}", key, urlencoding::encode(value)))
+        .collect::<Vec<_>>()
+        .join("&")
+    }
+}
+
+fn non_empty(value: &Option<String>) -> Option<&str> {
+    value
+        .as_deref()
+        .map(str::trim)
+        .filter(|value| !value.is_empty())
+}
+
+pub async fn audit_log_page(
+    query: web::Query<AuditLogQuery>,
+    db_pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let filter = query.filter().map_err(e400)?;
+    let page = query.page.unwrap_or(1);
+    if page < 1 {
+        return Err(e400("Page numbers start at 1."));
+    }
+    let offset = (page - 1)
+        .checked_mul(EVENTS_PER_PAGE)
+        .ok_or_else(|| e400("There is no such page."))?;
+
+    let n_events = count_audit_events(&filter, &db_pool).await.map_err(e500)?;
+    let n_pages = ((n_events + EVENTS_PER_PAGE - 1) / EVENTS_PER_PAGE).max(1);
+    let events = list_audit_events(&filter, Some(EVENTS_PER_PAGE), offset, &db_pool)
+        .await
+        .map_err(e500)?;
+
+    let mut rows_html = String::new();
+    for event in &events {
+        writeln!(
+            rows_html,
+            r#"<tr>
+                <td>{occurred_at}</td>
+                <td>{actor}</td>
+                <td>{action}</td>
+                <td>{target}</td>
+                <td>{client_ip}</td>
+            </tr>"#,
+            occurred_at = event.occurred_at.format("%Y-%m-%d %H:%M:%S UTC"),
+            actor = encode_minimal(&event.actor_username),
+            action = encode_minimal(&event.action),
+            target = encode_minimal(event.target.as_deref().unwrap_or_default()),
+            client_ip = encode_minimal(&event.client_ip),
+        )
+        .unwrap();
+    }
+
+    let filter_query_string = query.filter_query_string();
+    let page_link = |page: i64| {
+        let mut link = format!("/admin/audit?page={}", page);
+        if !filter_query_string.is_empty() {
+            write!(link, "&{}", filter_query_string).unwrap();
+        }
+        encode_minimal(&link)
+    };
+    let mut pagination_html = String::new();
+    if page > 1 {
+        write!(
+            pagination_html,
+            r#"<a href="{}">&lt; Newer</a> "#,
+            page_link(page - 1)
+        )
+        .unwrap();
+    }
+    write!(pagination_html, "Page {} of {}", page, n_pages).unwrap();
+    if page < n_pages {
+        write!(
+            pagination_html,
+            r#" <a href="{}">Older &gt;</a>"#,
+            page_link(page + 1)
+        )
+        .unwrap();
+    }
+
+    let mut action_options = String::from(r#"<option value="">Any action</option>"#);
+    for action in AuditAction::ALL {
+        write!(
+            action_options,
+            r#"<option value="{action}"{selected}>{action}</option>"#,
+            selected = if filter.action == Some(action) {
+                " selected"
+            } else {
+                ""
+            },
+        )
+        .unwrap();
+    }
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"
+    <html>
+      <head>
+        <meta http-equiv="content-type" content="text/html; charset=utf-8">
+        <title>Audit log</title>
+      </head>
+      <body>
+        <h1>Audit log</h1>
+        <form action="/admin/audit" method="GET">
+          <input type="text" placeholder="Username" name="actor" value="{actor}" />
+          <select name="action">{action_options}</select>
+          <label>From <input type="date" name="since" value="{since}" /></label>
+          <label>To <input type="date" name="until" value="{until}" /></label>
+          <input type="submit" value="Filter" />
+        </form>
+        <p><a href="{export_link}">Download as CSV</a></p>
+        <table>
+          <tr>
+            <th>When</th>
+            <th>Who</th>
+            <th>Action</th>
+            <th>Target</th>
+            <th>IP address</th>
+          </tr>
+          {rows_html}
+        </table>
+        <p>{pagination_html}</p>
+        <a href="/admin/dashboard">&lt; - Back</a>
+      </body>
+    </html>"#,
+            actor = encode_minimal(non_empty(&query.actor).unwrap_or_default()),
+            since = filter.since.map(|d| d.to_string()).unwrap_or_default(),
+            until = filter.until.map(|d| d.to_string()).unwrap_or_default(),
+            export_link = encode_minimal(&format!("/admin/audit/export?{}", filter_query_string)),
+        )))
+}
+
+/// Every event matching the filters, not just a page of them.
+pub async fn export_audit_log(
+    query: web::Query<AuditLogQuery>,
+    db_pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let filter = query.filter().map_err(e400)?;
+    let events = list_audit_events(&filter, None, 0, &db_pool)
+        .await
+        .map_err(e500)?;
+
+    let mut csv = String::from("occurred_at,actor,action,target,client_ip\r\n");
+    for event in &events {
+        let fields = [
+            event.occurred_at.to_rfc3339(),
+            csv_field(&event.actor_username),
+            csv_field(&event.action),
+            csv_field(event.target.as_deref().unwrap_or_default()),
+            csv_field(&event.client_ip),
+        ];
+        write!(csv, "{}\r\n", fields.join(",")).unwrap();
+    }
+
+    Ok(HttpResponse::Ok()
+        .content_type("text/csv; charset=utf-8")
+        .insert_header((
+            header::CONTENT_DISPOSITION,
+            r#"attachment; filename="audit-log.csv""#,
+        ))
+        .body(csv))
+}
+
+/// Quotes the field when it has to be (RFC 4180), and keeps spreadsheets from
+/// evaluating it as a formula.
+fn csv_field(value: &str) -> String {
+    let value = if value.starts_with(['=', '+', '-', '@']) {
+        format!("'{}", value)
+    } else {
+        value.to_string()
+    };
+
+    if value.contains([',', '"', '\r', '\n']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{csv_field, AuditLogQuery};
+    use crate::audit_log::AuditAction;
+
+    fn query(action: &str, since: &str) -> AuditLogQuery {
+        AuditLogQuery {
+            actor: Some(" ".into()),
+            action: Some(action.into()),
+            since: Some(since.into()),
+            until: None,
+            page: None,
+        }
+    }
+
+    #[test]
+    fn plain_fields_are_left_alone() {
+        assert_eq!(csv_field("log_in"), "log_in");
+    }
+
+    #[test]
+    fn fields_with_separators_or_quotes_are_quoted() {
+        assert_eq!(csv_field("a,b"), r#""a,b""#);
+        assert_eq!(csv_field(r#"say "hi""#), r#""say ""hi""""#);
+        assert_eq!(csv_field("two\nlines"), "\"two\nlines\"");
+    }
+
+    #[test]
+    fn formulas_are_defused() {
+        assert_eq!(csv_field("=1+1"), "'=1+1");
+        assert_eq!(csv_field("@SUM(A1)"), "'@SUM(A1)");
+    }
+
+    #[test]
+    fn empty_filters_are_ignored() {
+        let filter = query("", "").filter().unwrap();
+
+        assert!(filter.actor.is_none());
+        assert!(filter.action.is_none());
+        assert!(filter.since.is_none());
+        assert_eq!(query("", "").filter_query_string(), "");
+    }
+
+    #[test]
+    fn filters_are_parsed() {
+        let filter = query("log_in", "2022-07-26").filter().unwrap();
+
+        assert_eq!(filter.action, Some(AuditAction::LogIn));
+        assert_eq!(filter.since.unwrap().to_string(), "2022-07-26");
+        assert_eq!(
+            query("log_in", "2022-07-26").filter_query_string(),
+            "action=log_in&since=2022-07-26"
+        );
+    }
+
+    #[test]
+    fn invalid_filters_are_rejected() {
+        assert!(query("launch_missiles", "").filter().is_err());
+        assert!(query("", "26/07/2022").filter().is_err());
+    }
+}
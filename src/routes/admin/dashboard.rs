@@ -1,17 +1,39 @@
 use actix_web::{web, HttpResponse};
 use anyhow::Context;
+use htmlescape::encode_minimal;
 use sqlx::PgPool;
 use uuid::Uuid;
 
-use crate::{authentication::UserId, utils::e500};
+use crate::{
+    authentication::{Role, UserId},
+    utils::e500,
+};
 
 pub async fn admin_dashboard(
     user_id: web::ReqData<UserId>,
+    role: web::ReqData<Role>,
     db_pool: web::Data<PgPool>,
 ) -> Result<HttpResponse, actix_web::Error> {
     let user_id = user_id.into_inner();
+    let role = role.into_inner();
     let username = get_username(*user_id, &db_pool).await.map_err(e500)?;
 
+    let mut actions_html = String::new();
+    if role >= Role::Editor {
+        actions_html.push_str(
+            r#"<li><a href="/admin/newsletters">Send a newsletter issue</a></li>
+                    <li><a href="/admin/newsletters/drafts">Newsletter drafts</a></li>"#,
+        );
+    }
+    actions_html
+        .push_str(r#"<li><a href="/admin/newsletters/history">Past newsletter issues</a></li>"#);
+    if role >= Role::Owner {
+        actions_html.push_str(
+            r#"<li><a href="/admin/users">Manage users</a></li>
+                    <li><a href="/admin/audit">Audit log</a></li>"#,
+        );
+    }
+
     Ok(HttpResponse::Ok().body(format!(
         r#"
         <!DOCTYPE html>
@@ -21,11 +43,13 @@ pub async fn admin_dashboard(
                 <title>Admin Dashboard</title>
             </head>
             <body>
-                <p>Welcome {}</p>
+                <p>Welcome {} ({})</p>
                 <ol>
                     Available Actions:
-                    <li><a href="/admin/newsletters">Send a newsletter issue</a></li>
+                    {}
                     <li><a href="/admin/password">Change password</a></li>
+                    <li><a href="/admin/2fa">Two-factor authentication</a></li>
+                    <li><a href="/admin/sessions">Sessions</a></li>
                     <li>
                     <form name="logout_form" action="/admin/logout" method="POST">
                     <input type="submit" value="Logout"/>
@@ -35,7 +59,9 @@ pub async fn admin_dashboard(
             </body>
         <html>
     "#,
-        username
+        encode_minimal(&username),
+        role,
+        actions_html
     )))
 }
 
@@ -1,9 +1,29 @@
-use actix_web::HttpResponse;
+use actix_web::{web, HttpResponse};
 use actix_web_flash_messages::FlashMessage;
+use sqlx::PgPool;
 
-use crate::{session_state::TypedSession, utils::see_other};
+use crate::{
+    audit_log::{AuditAction, Auditor},
+    authentication::{revoke_user_session, UserId},
+    session_state::TypedSession,
+    utils::{e500, see_other},
+};
 
-pub async fn logout(session: TypedSession) -> Result<HttpResponse, actix_web::Error> {
+pub async fn logout(
+    session: TypedSession,
+    user_id: web::ReqData<UserId>,
+    db_pool: web::Data<PgPool>,
+    auditor: Auditor,
+) -> Result<HttpResponse, actix_web::Error> {
+    auditor
+        .record(AuditAction::LogOut, None)
+        .await
+        .map_err(e500)?;
+    if let Some(session_id) = session.get_session_id().map_err(e500)? {
+        revoke_user_session(*user_id.into_inner(), session_id, &db_pool)
+            .await
+            .map_err(e500)?;
+    }
     session.log_out();
     FlashMessage::info("You've logged out successfully.").send();
     Ok(see_other("/login"))
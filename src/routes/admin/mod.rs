@@ -1,9 +1,17 @@
+mod audit;
 mod dashboard;
 mod logout;
 mod newsletters;
 mod password;
+mod sessions;
+mod two_factor;
+mod users;
 
-pub use dashboard::admin_dashboard;
+pub use audit::{audit_log_page, export_audit_log};
+pub use dashboard::{admin_dashboard, get_username};
 pub use logout::logout;
 pub use newsletters::*;
 pub use password::*;
+pub use sessions::*;
+pub use two_factor::*;
+pub use users::*;
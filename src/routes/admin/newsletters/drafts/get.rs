@@ -0,0 +1,166 @@
+use actix_web::{http::header::ContentType, web, HttpResponse};
+use actix_web_flash_messages::IncomingFlashMessages;
+use anyhow::Context;
+use htmlescape::encode_minimal;
+use sqlx::PgPool;
+use std::fmt::Write;
+use uuid::Uuid;
+
+use super::{get_draft, Draft};
+use crate::utils::{e404, e500};
+
+pub async fn list_drafts(
+    flash_messages: IncomingFlashMessages,
+    db_pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let mut message_html = String::new();
+    for m in flash_messages.iter() {
+        writeln!(message_html, "<p><i>{}</i></p>", m.content()).unwrap();
+    }
+
+    let mut drafts_html = String::new();
+    for draft in get_drafts(&db_pool).await.map_err(e500)? {
+        writeln!(
+            drafts_html,
+            r#"<li><a href="/admin/newsletters/drafts/{}">{}</a></li>"#,
+            draft.id,
+            encode_minimal(&draft.title),
+        )
+        .unwrap();
+    }
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"
+    <html>
+      <head>
+        <meta http-equiv="content-type" content="text/html; charset=utf-8">
+        <title>Newsletter Drafts</title>
+      </head>
+      <body>
+        {message_html}
+        <h1>Drafts</h1>
+        <ul>
+          {drafts_html}
+        </ul>
+        <h2>New draft</h2>
+        <form action="/admin/newsletters/drafts" method="POST">
+          <input type="text" name="title" placeholder="Title"/>
+          <textarea name="text_content" placeholder="Text Content"></textarea>
+          <textarea name="html_content" placeholder="HTML Content"></textarea>
+          <input type="submit" value="Save draft"/>
+        </form>
+        <a href="/admin/dashboard">&lt; - Back</a>
+      </body>
+    </html>"#
+        )))
+}
+
+pub async fn edit_draft_form(
+    draft_id: web::Path<Uuid>,
+    flash_messages: IncomingFlashMessages,
+    db_pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let draft = get_draft(draft_id.into_inner(), &db_pool)
+        .await
+        .map_err(e500)?
+        .ok_or_else(|| e404("Draft not found."))?;
+
+    let mut message_html = String::new();
+    for m in flash_messages.iter() {
+        writeln!(message_html, "<p><i>{}</i></p>", m.content()).unwrap();
+    }
+
+    let idempotency_key = Uuid::new_v4();
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"
+    <html>
+      <head>
+        <meta http-equiv="content-type" content="text/html; charset=utf-8">
+        <title>Edit Draft</title>
+      </head>
+      <body>
+        {message_html}
+        <form action="/admin/newsletters/drafts/{id}" method="POST">
+          <input type="text" name="title" placeholder="Title" value="{title}"/>
+          <textarea name="text_content" placeholder="Text Content">{text_content}</textarea>
+          <textarea name="html_content" placeholder="HTML Content">{html_content}</textarea>
+          <input type="submit" value="Save draft"/>
+        </form>
+        <p>
+          Preview as <a href="/admin/newsletters/drafts/{id}/preview/html">HTML</a>
+          or <a href="/admin/newsletters/drafts/{id}/preview/text">plain text</a>
+        </p>
+        <form action="/admin/newsletters/drafts/{id}/test" method="POST">
+          <input type="email" name="email" placeholder="Send a test copy to"/>
+          <input type="submit" value="Send test"/>
+        </form>
+        <form action="/admin/newsletters/drafts/{id}/publish" method="POST">
+          <input type="hidden" name="idempotency_key" value="{idempotency_key}"/>
+          <label>Send at (UTC, leave empty to send now)
+            <input type="datetime-local" name="send_at"/>
+          </label>
+          <input type="submit" value="Publish"/>
+        </form>
+        <form action="/admin/newsletters/drafts/{id}/delete" method="POST">
+          <input type="submit" value="Delete draft"/>
+        </form>
+        <a href="/admin/newsletters/drafts">&lt; - Back</a>
+      </body>
+    </html>"#,
+            id = draft.id,
+            title = encode_minimal(&draft.title),
+            text_content = encode_minimal(&draft.text_content),
+            html_content = encode_minimal(&draft.html_content),
+        )))
+}
+
+pub async fn preview_draft_html(
+    draft_id: web::Path<Uuid>,
+    db_pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let draft = get_draft(draft_id.into_inner(), &db_pool)
+        .await
+        .map_err(e500)?
+        .ok_or_else(|| e404("Draft not found."))?;
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(draft.html_content))
+}
+
+pub async fn preview_draft_text(
+    draft_id: web::Path<Uuid>,
+    db_pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let draft = get_draft(draft_id.into_inner(), &db_pool)
+        .await
+        .map_err(e500)?
+        .ok_or_else(|| e404("Draft not found."))?;
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::plaintext())
+        .body(draft.text_content))
+}
+
+#[tracing::instrument(name = "Get newsletter issue drafts", skip(db_pool))]
+async fn get_drafts(db_pool: &PgPool) -> Result<Vec<Draft>, anyhow::Error> {
+    let drafts = sqlx::query_as!(
+        Draft,
+        r#"
+        SELECT id, title, text_content, html_content
+        FROM newsletter_issues
+        WHERE status = 'draft'
+        ORDER BY title
+    "#
+    )
+    .fetch_all(db_pool)
+    .await
+    .context("Failed to fetch newsletter issue drafts.")?;
+
+    Ok(drafts)
+}
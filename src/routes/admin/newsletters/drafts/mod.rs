@@ -0,0 +1,36 @@
+mod get;
+mod post;
+
+pub use get::*;
+pub use post::*;
+
+use anyhow::Context;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+pub struct Draft {
+    pub id: Uuid,
+    pub title: String,
+    pub text_content: String,
+    pub html_content: String,
+}
+
+#[tracing::instrument(name = "Get newsletter issue draft", skip(db_pool))]
+async fn get_draft(draft_id: Uuid, db_pool: &PgPool) -> Result<Option<Draft>, anyhow::Error> {
+    let draft = sqlx::query_as!(
+        Draft,
+        r#"
+        SELECT id, title, text_content, html_content
+        FROM newsletter_issues
+        WHERE
+            id = $1 AND
+            status = 'draft'
+    "#,
+        draft_id
+    )
+    .fetch_optional(db_pool)
+    .await
+    .context("Failed to fetch newsletter issue draft.")?;
+
+    Ok(draft)
+}
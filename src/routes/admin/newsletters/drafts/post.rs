@@ -0,0 +1,305 @@
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use sqlx::{PgPool, Postgres, Transaction};
+use uuid::Uuid;
+
+use super::get_draft;
+use crate::{
+    audit_log::{AuditAction, Auditor},
+    authentication::UserId,
+    domains::{save_response, try_processing, IdempotencyKey, NextAction, SendAt, SubscriberEmail},
+    email_client::EmailClient,
+    routes::admin::newsletters::post::{
+        enqueue_issue_delivery, publication_time, set_issue_total_recipients, success_message,
+    },
+    utils::{e400, e404, e500, see_other},
+};
+
+#[derive(serde::Deserialize)]
+pub struct DraftFormData {
+    pub title: String,
+    pub text_content: String,
+    pub html_content: String,
+}
+
+#[derive(serde::Deserialize)]
+pub struct TestEmailFormData {
+    pub email: String,
+}
+
+#[derive(serde::Deserialize)]
+pub struct PublishDraftFormData {
+    pub idempotency_key: String,
+    pub send_at: Option<String>,
+}
+
+#[tracing::instrument(
+    name = "Create a newsletter issue draft",
+    skip(form_data, db_pool, auditor)
+)]
+pub async fn create_draft(
+    form_data: web::Form<DraftFormData>,
+    db_pool: web::Data<PgPool>,
+    auditor: Auditor,
+) -> Result<HttpResponse, actix_web::Error> {
+    let draft_id = Uuid::new_v4();
+    sqlx::query!(
+        r#"
+        INSERT INTO newsletter_issues (
+            id,
+            title,
+            text_content,
+            html_content,
+            status
+        ) VALUES ($1, $2, $3, $4, 'draft')
+    "#,
+        draft_id,
+        form_data.title,
+        form_data.text_content,
+        form_data.html_content
+    )
+    .execute(db_pool.get_ref())
+    .await
+    .context("Failed to store newsletter issue draft.")
+    .map_err(e500)?;
+    auditor
+        .record(
+            AuditAction::CreateDraft,
+            Some(&format!("draft:{}", draft_id)),
+        )
+        .await
+        .map_err(e500)?;
+
+    FlashMessage::info("The draft has been saved.").send();
+    Ok(see_other(&format!(
+        "/admin/newsletters/drafts/{}",
+        draft_id
+    )))
+}
+
+#[tracing::instrument(
+    name = "Update a newsletter issue draft",
+    skip(form_data, db_pool, auditor)
+)]
+pub async fn update_draft(
+    draft_id: web::Path<Uuid>,
+    form_data: web::Form<DraftFormData>,
+    db_pool: web::Data<PgPool>,
+    auditor: Auditor,
+) -> Result<HttpResponse, actix_web::Error> {
+    let draft_id = draft_id.into_inner();
+    let n_updated_rows = sqlx::query!(
+        r#"
+        UPDATE newsletter_issues
+        SET
+            title = $2,
+            text_content = $3,
+            html_content = $4
+        WHERE
+            id = $1 AND
+            status = 'draft'
+    "#,
+        draft_id,
+        form_data.title,
+        form_data.text_content,
+        form_data.html_content
+    )
+    .execute(db_pool.get_ref())
+    .await
+    .context("Failed to update newsletter issue draft.")
+    .map_err(e500)?
+    .rows_affected();
+
+    if n_updated_rows == 0 {
+        return Err(e404("Draft not found."));
+    }
+    auditor
+        .record(
+            AuditAction::UpdateDraft,
+            Some(&format!("draft:{}", draft_id)),
+        )
+        .await
+        .map_err(e500)?;
+
+    FlashMessage::info("The draft has been saved.").send();
+    Ok(see_other(&format!(
+        "/admin/newsletters/drafts/{}",
+        draft_id
+    )))
+}
+
+#[tracing::instrument(name = "Delete a newsletter issue draft", skip(db_pool, auditor))]
+pub async fn delete_draft(
+    draft_id: web::Path<Uuid>,
+    db_pool: web::Data<PgPool>,
+    auditor: Auditor,
+) -> Result<HttpResponse, actix_web::Error> {
+    let draft_id = draft_id.into_inner();
+    let n_deleted_rows = sqlx::query!(
+        "DELETE FROM newsletter_issues WHERE id = $1 AND status = 'draft'",
+        draft_id
+    )
+    .execute(db_pool.get_ref())
+    .await
+    .context("Failed to delete newsletter issue draft.")
+    .map_err(e500)?
+    .rows_affected();
+
+    if n_deleted_rows == 0 {
+        return Err(e404("Draft not found."));
+    }
+    auditor
+        .record(
+            AuditAction::DeleteDraft,
+            Some(&format!("draft:{}", draft_id)),
+        )
+        .await
+        .map_err(e500)?;
+
+    FlashMessage::info("The draft has been deleted.").send();
+    Ok(see_other("/admin/newsletters/drafts"))
+}
+
+#[tracing::instrument(
+    name = "Send a test copy of a newsletter issue draft",
+    skip(form_data, db_pool, email_client, auditor),
+    fields(recipient = %form_data.email)
+)]
+pub async fn send_test_draft(
+    draft_id: web::Path<Uuid>,
+    form_data: web::Form<TestEmailFormData>,
+    db_pool: web::Data<PgPool>,
+    email_client: web::Data<EmailClient>,
+    auditor: Auditor,
+) -> Result<HttpResponse, actix_web::Error> {
+    let draft = get_draft(draft_id.into_inner(), &db_pool)
+        .await
+        .map_err(e500)?
+        .ok_or_else(|| e404("Draft not found."))?;
+    let draft_page = format!("/admin/newsletters/drafts/{}", draft.id);
+
+    let recipient = match SubscriberEmail::parse(form_data.0.email) {
+        Ok(recipient) => recipient,
+        Err(e) => {
+            FlashMessage::error(e).send();
+            return Ok(see_other(&draft_page));
+        }
+    };
+
+    email_client
+        .send_email(
+            &recipient,
+            &format!("[Test] {}", draft.title),
+            &draft.html_content,
+            &draft.text_content,
+        )
+        .await
+        .context("Failed to send a test copy of the draft.")
+        .map_err(e500)?;
+    auditor
+        .record(
+            AuditAction::SendTestDraft,
+            Some(&format!("draft:{} to {}", draft.id, recipient)),
+        )
+        .await
+        .map_err(e500)?;
+
+    FlashMessage::info(format!("A test copy has been sent to {}.", recipient)).send();
+    Ok(see_other(&draft_page))
+}
+
+#[tracing::instrument(
+    name = "Publish a newsletter issue draft",
+    skip(form_data, db_pool, auditor),
+    fields(user_id=%*user_id)
+)]
+pub async fn publish_draft(
+    draft_id: web::Path<Uuid>,
+    form_data: web::Form<PublishDraftFormData>,
+    db_pool: web::Data<PgPool>,
+    user_id: web::ReqData<UserId>,
+    auditor: Auditor,
+) -> Result<HttpResponse, actix_web::Error> {
+    let user_id = user_id.into_inner();
+    let draft_id = draft_id.into_inner();
+    let PublishDraftFormData {
+        idempotency_key,
+        send_at,
+    } = form_data.0;
+
+    let idempotency_key: IdempotencyKey = idempotency_key.try_into().map_err(e400)?;
+    let send_at = SendAt::parse_optional(send_at.as_deref()).map_err(e400)?;
+    let mut transaction = match try_processing(*user_id, &idempotency_key, &db_pool)
+        .await
+        .map_err(e500)?
+    {
+        NextAction::StartProcessing(transaction) => transaction,
+        NextAction::ReturnSavedResponse(saved_response) => {
+            success_message(send_at).send();
+            return Ok(saved_response);
+        }
+    };
+
+    let was_published =
+        mark_draft_as_published(draft_id, publication_time(send_at), &mut transaction)
+            .await
+            .context("Failed to publish newsletter issue draft.")
+            .map_err(e500)?;
+    if !was_published {
+        return Err(e404("Draft not found."));
+    }
+
+    let n_recipients = enqueue_issue_delivery(draft_id, &mut transaction)
+        .await
+        .context("Failed enqueueing issue delivery task.")
+        .map_err(e500)?;
+
+    set_issue_total_recipients(draft_id, n_recipients, &mut transaction)
+        .await
+        .context("Failed to store the number of recipients of the newsletter issue.")
+        .map_err(e500)?;
+    auditor
+        .record_in(
+            &mut transaction,
+            AuditAction::PublishDraft,
+            Some(&format!("issue:{}", draft_id)),
+        )
+        .await
+        .map_err(e500)?;
+
+    let response = see_other("/admin/newsletters");
+    let response = save_response(*user_id, &idempotency_key, response, transaction)
+        .await
+        .map_err(e500)?;
+
+    success_message(send_at).send();
+    Ok(response)
+}
+
+#[tracing::instrument(skip_all)]
+async fn mark_draft_as_published(
+    draft_id: Uuid,
+    published_at: DateTime<Utc>,
+    transaction: &mut Transaction<'_, Postgres>,
+) -> Result<bool, sqlx::Error> {
+    let n_updated_rows = sqlx::query!(
+        r#"
+        UPDATE newsletter_issues
+        SET
+            status = 'published',
+            published_at = $2
+        WHERE
+            id = $1 AND
+            status = 'draft'
+    "#,
+        draft_id,
+        published_at
+    )
+    .execute(transaction)
+    .await?
+    .rows_affected();
+
+    Ok(n_updated_rows > 0)
+}
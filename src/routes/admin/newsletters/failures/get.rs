@@ -0,0 +1,129 @@
+use actix_web::{http::header::ContentType, web, HttpResponse};
+use actix_web_flash_messages::IncomingFlashMessages;
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use htmlescape::encode_minimal;
+use sqlx::PgPool;
+use std::fmt::Write;
+use uuid::Uuid;
+
+use crate::utils::{e404, e500};
+
+struct FailedDelivery {
+    subscriber_email: String,
+    error_message: String,
+    n_attempts: i16,
+    failed_at: DateTime<Utc>,
+}
+
+pub async fn issue_delivery_failures(
+    issue_id: web::Path<Uuid>,
+    db_pool: web::Data<PgPool>,
+    flash_messages: IncomingFlashMessages,
+) -> Result<HttpResponse, actix_web::Error> {
+    let issue_id = issue_id.into_inner();
+    let issue_title = get_issue_title(issue_id, &db_pool)
+        .await
+        .map_err(e500)?
+        .ok_or_else(|| e404("Newsletter issue not found."))?;
+    let failures = get_failed_deliveries(issue_id, &db_pool)
+        .await
+        .map_err(e500)?;
+
+    let mut message_html = String::new();
+    for m in flash_messages.iter() {
+        writeln!(message_html, "<p><i>{}</i></p>", m.content()).unwrap();
+    }
+
+    let mut rows_html = String::new();
+    for failure in &failures {
+        writeln!(
+            rows_html,
+            r#"<tr>
+                <td>{email}</td>
+                <td>{error}</td>
+                <td>{n_attempts}</td>
+                <td>{failed_at}</td>
+                <td>
+                    <form action="/admin/newsletters/{issue_id}/failures/requeue" method="POST">
+                        <input type="hidden" name="subscriber_email" value="{email}"/>
+                        <input type="submit" value="Requeue"/>
+                    </form>
+                </td>
+            </tr>"#,
+            email = encode_minimal(&failure.subscriber_email),
+            error = encode_minimal(&failure.error_message),
+            n_attempts = failure.n_attempts,
+            failed_at = failure.failed_at.to_rfc3339(),
+        )
+        .unwrap();
+    }
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"
+    <html>
+      <head>
+        <meta http-equiv="content-type" content="text/html; charset=utf-8">
+        <title>Failed Deliveries</title>
+      </head>
+      <body>
+        {message_html}
+        <h1>Failed deliveries of "{title}"</h1>
+        <p>{n_failures} failed deliveries.</p>
+        <table>
+          <tr>
+            <th>Subscriber</th>
+            <th>Error</th>
+            <th>Attempts</th>
+            <th>Failed at</th>
+            <th></th>
+          </tr>
+          {rows_html}
+        </table>
+        <a href="/admin/dashboard">&lt; - Back</a>
+      </body>
+    </html>"#,
+            title = encode_minimal(&issue_title),
+            n_failures = failures.len(),
+        )))
+}
+
+#[tracing::instrument(name = "Get newsletter issue title", skip(db_pool))]
+async fn get_issue_title(
+    issue_id: Uuid,
+    db_pool: &PgPool,
+) -> Result<Option<String>, anyhow::Error> {
+    let row = sqlx::query!(
+        "SELECT title FROM newsletter_issues WHERE id = $1",
+        issue_id
+    )
+    .fetch_optional(db_pool)
+    .await
+    .context("Failed to fetch newsletter issue.")?;
+
+    Ok(row.map(|r| r.title))
+}
+
+#[tracing::instrument(name = "Get failed deliveries of a newsletter issue", skip(db_pool))]
+async fn get_failed_deliveries(
+    issue_id: Uuid,
+    db_pool: &PgPool,
+) -> Result<Vec<FailedDelivery>, anyhow::Error> {
+    let failures = sqlx::query_as!(
+        FailedDelivery,
+        r#"
+        SELECT subscriber_email, error_message, n_attempts, failed_at
+        FROM issue_delivery_failures
+        WHERE newsletter_issue_id = $1
+        ORDER BY failed_at DESC
+    "#,
+        issue_id
+    )
+    .fetch_all(db_pool)
+    .await
+    .context("Failed to fetch failed deliveries.")?;
+
+    Ok(failures)
+}
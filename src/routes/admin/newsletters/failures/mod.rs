@@ -0,0 +1,5 @@
+mod get;
+mod post;
+
+pub use get::issue_delivery_failures;
+pub use post::requeue_failed_delivery;
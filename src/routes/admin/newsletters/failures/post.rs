@@ -0,0 +1,178 @@
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use anyhow::Context;
+use sqlx::{PgPool, Postgres, Transaction};
+use uuid::Uuid;
+
+use crate::{
+    audit_log::{AuditAction, Auditor},
+    issue_delivery_worker::notify_delivery_workers,
+    utils::{e500, see_other},
+};
+
+#[derive(serde::Deserialize)]
+pub struct FormData {
+    pub subscriber_email: String,
+}
+
+#[tracing::instrument(
+    name = "Requeue a failed delivery",
+    skip(form_data, db_pool, auditor),
+    fields(subscriber_email = %form_data.subscriber_email)
+)]
+pub async fn requeue_failed_delivery(
+    issue_id: web::Path<Uuid>,
+    form_data: web::Form<FormData>,
+    db_pool: web::Data<PgPool>,
+    auditor: Auditor,
+) -> Result<HttpResponse, actix_web::Error> {
+    let issue_id = issue_id.into_inner();
+    let failures_page = format!("/admin/newsletters/{}/failures", issue_id);
+
+    let mut transaction = db_pool
+        .begin()
+        .await
+        .context("Failed to acquire connection from DB pool.")
+        .map_err(e500)?;
+
+    if has_unsubscribed(&form_data.subscriber_email, &mut transaction)
+        .await
+        .context("Failed to fetch the subscription status.")
+        .map_err(e500)?
+    {
+        FlashMessage::error(format!(
+            "{} has unsubscribed, the delivery can't be requeued.",
+            form_data.subscriber_email
+        ))
+        .send();
+        return Ok(see_other(&failures_page));
+    }
+
+    let was_removed =
+        remove_failed_delivery(issue_id, &form_data.subscriber_email, &mut transaction)
+            .await
+            .context("Failed to remove the failed delivery.")
+            .map_err(e500)?;
+    if !was_removed {
+        FlashMessage::error("The failed delivery does not exist.").send();
+        return Ok(see_other(&failures_page));
+    }
+
+    enqueue_delivery(issue_id, &form_data.subscriber_email, &mut transaction)
+        .await
+        .context("Failed to requeue the delivery.")
+        .map_err(e500)?;
+
+    reopen_issue_delivery(issue_id, &mut transaction)
+        .await
+        .context("Failed to update the delivery progress of the newsletter issue.")
+        .map_err(e500)?;
+    auditor
+        .record_in(
+            &mut transaction,
+            AuditAction::RequeueFailedDelivery,
+            Some(&format!(
+                "issue:{} to {}",
+                issue_id, form_data.subscriber_email
+            )),
+        )
+        .await
+        .map_err(e500)?;
+
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction for requeueing a delivery.")
+        .map_err(e500)?;
+
+    FlashMessage::info(format!(
+        "The delivery to {} has been requeued.",
+        form_data.subscriber_email
+    ))
+    .send();
+    Ok(see_other(&failures_page))
+}
+
+#[tracing::instrument(skip_all)]
+async fn has_unsubscribed(
+    subscriber_email: &str,
+    transaction: &mut Transaction<'_, Postgres>,
+) -> Result<bool, sqlx::Error> {
+    let row = sqlx::query!(
+        "SELECT status FROM subscriptions WHERE email = $1",
+        subscriber_email
+    )
+    .fetch_optional(transaction)
+    .await?;
+
+    Ok(matches!(row, Some(r) if r.status == "unsubscribed"))
+}
+
+#[tracing::instrument(skip_all)]
+async fn remove_failed_delivery(
+    issue_id: Uuid,
+    subscriber_email: &str,
+    transaction: &mut Transaction<'_, Postgres>,
+) -> Result<bool, sqlx::Error> {
+    let n_deleted_rows = sqlx::query!(
+        r#"
+        DELETE FROM issue_delivery_failures
+        WHERE
+            newsletter_issue_id = $1 AND
+            subscriber_email = $2
+    "#,
+        issue_id,
+        subscriber_email
+    )
+    .execute(transaction)
+    .await?
+    .rows_affected();
+
+    Ok(n_deleted_rows > 0)
+}
+
+#[tracing::instrument(skip_all)]
+async fn enqueue_delivery(
+    issue_id: Uuid,
+    subscriber_email: &str,
+    transaction: &mut Transaction<'_, Postgres>,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        INSERT INTO issue_delivery_queue (
+            newsletter_issue_id,
+            subscriber_email
+        ) VALUES ($1, $2)
+        ON CONFLICT DO NOTHING
+    "#,
+        issue_id,
+        subscriber_email
+    )
+    .execute(&mut *transaction)
+    .await?;
+
+    notify_delivery_workers(transaction).await
+}
+
+/// The requeued delivery is pending again, so it no longer counts as failed
+/// and the issue isn't completed until the worker is done with it.
+#[tracing::instrument(skip_all)]
+async fn reopen_issue_delivery(
+    issue_id: Uuid,
+    transaction: &mut Transaction<'_, Postgres>,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        UPDATE newsletter_issues
+        SET
+            n_failed = n_failed - 1,
+            completed_at = NULL
+        WHERE id = $1
+    "#,
+        issue_id
+    )
+    .execute(transaction)
+    .await?;
+
+    Ok(())
+}
@@ -1,17 +1,44 @@
-use actix_web::{http::header::ContentType, HttpResponse};
+use actix_web::{http::header::ContentType, web, HttpResponse};
 use actix_web_flash_messages::IncomingFlashMessages;
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use htmlescape::encode_minimal;
+use sqlx::PgPool;
 use std::fmt::Write;
 use uuid::Uuid;
 
-pub async fn publish_newsletter_form(flash_messages: IncomingFlashMessages) -> HttpResponse {
+use crate::utils::e500;
+
+struct ScheduledIssue {
+    id: Uuid,
+    title: String,
+    published_at: DateTime<Utc>,
+}
+
+pub async fn publish_newsletter_form(
+    flash_messages: IncomingFlashMessages,
+    db_pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
     let mut error_html = String::new();
     for m in flash_messages.iter() {
         writeln!(error_html, "<p><i>{}</i></p>", m.content()).unwrap();
     }
 
+    let mut scheduled_html = String::new();
+    for issue in get_scheduled_issues(&db_pool).await.map_err(e500)? {
+        writeln!(
+            scheduled_html,
+            r#"<li><a href="/admin/newsletters/{}">{}</a> goes out at {}</li>"#,
+            issue.id,
+            encode_minimal(&issue.title),
+            issue.published_at.format("%Y-%m-%d %H:%M UTC"),
+        )
+        .unwrap();
+    }
+
     let idempotency_key = Uuid::new_v4();
 
-    HttpResponse::Ok()
+    Ok(HttpResponse::Ok()
         .content_type(ContentType::html())
         .body(format!(
             r#"
@@ -24,10 +51,37 @@ pub async fn publish_newsletter_form(flash_messages: IncomingFlashMessages) -> H
                 <input type="text" name="title" placeholder="Title"/>
                 <textarea name="text_content" placeholder="Text Content"></textarea>
                 <textarea name="html_content" placeholder="HTML Content"></textarea>
+                <label>Send at (UTC, leave empty to send now)
+                    <input type="datetime-local" name="send_at"/>
+                </label>
                 <input type="submit" value="Publish"/>
             </form>
+            <h2>Scheduled issues</h2>
+            <ul>
+                {}
+            </ul>
         </body>
     </html>"#,
-            error_html
-        ))
+            error_html, scheduled_html
+        )))
+}
+
+#[tracing::instrument(name = "Get scheduled newsletter issues", skip(db_pool))]
+async fn get_scheduled_issues(db_pool: &PgPool) -> Result<Vec<ScheduledIssue>, anyhow::Error> {
+    let issues = sqlx::query_as!(
+        ScheduledIssue,
+        r#"
+        SELECT id, title, published_at AS "published_at!"
+        FROM newsletter_issues
+        WHERE
+            status = 'published' AND
+            published_at > now()
+        ORDER BY published_at
+    "#
+    )
+    .fetch_all(db_pool)
+    .await
+    .context("Failed to fetch scheduled newsletter issues.")?;
+
+    Ok(issues)
 }
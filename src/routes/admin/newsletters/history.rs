@@ -0,0 +1,156 @@
+use actix_web::{http::header::ContentType, web, HttpResponse};
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use htmlescape::encode_minimal;
+use sqlx::PgPool;
+use std::fmt::Write;
+use uuid::Uuid;
+
+use crate::utils::{e400, e500};
+
+const ISSUES_PER_PAGE: i64 = 20;
+
+#[derive(serde::Deserialize)]
+pub struct HistoryQuery {
+    pub page: Option<i64>,
+}
+
+struct PublishedIssue {
+    id: Uuid,
+    title: String,
+    published_at: DateTime<Utc>,
+    n_delivered: i32,
+    n_failed: i32,
+    n_pending: i32,
+}
+
+pub async fn newsletter_history(
+    query: web::Query<HistoryQuery>,
+    db_pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let page = query.page.unwrap_or(1);
+    if page < 1 {
+        return Err(e400("Page numbers start at 1."));
+    }
+    let offset = (page - 1)
+        .checked_mul(ISSUES_PER_PAGE)
+        .ok_or_else(|| e400("There is no such page."))?;
+
+    let n_issues = count_published_issues(&db_pool).await.map_err(e500)?;
+    let n_pages = ((n_issues + ISSUES_PER_PAGE - 1) / ISSUES_PER_PAGE).max(1);
+    let issues = get_published_issues(offset, &db_pool).await.map_err(e500)?;
+
+    let mut rows_html = String::new();
+    for issue in &issues {
+        writeln!(
+            rows_html,
+            r#"<tr>
+                <td><a href="/admin/newsletters/{id}">{title}</a></td>
+                <td>{published_at}</td>
+                <td>{delivered}</td>
+                <td>{failed}</td>
+                <td>{pending}</td>
+            </tr>"#,
+            id = issue.id,
+            title = encode_minimal(&issue.title),
+            published_at = issue.published_at.format("%Y-%m-%d %H:%M UTC"),
+            delivered = issue.n_delivered,
+            failed = issue.n_failed,
+            pending = issue.n_pending,
+        )
+        .unwrap();
+    }
+
+    let mut pagination_html = String::new();
+    if page > 1 {
+        write!(
+            pagination_html,
+            r#"<a href="/admin/newsletters/history?page={}">&lt; Newer</a> "#,
+            page - 1
+        )
+        .unwrap();
+    }
+    write!(pagination_html, "Page {} of {}", page, n_pages).unwrap();
+    if page < n_pages {
+        write!(
+            pagination_html,
+            r#" <a href="/admin/newsletters/history?page={}">Older &gt;</a>"#,
+            page + 1
+        )
+        .unwrap();
+    }
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"
+    <html>
+      <head>
+        <meta http-equiv="content-type" content="text/html; charset=utf-8">
+        <title>Past Newsletter Issues</title>
+      </head>
+      <body>
+        <h1>Past newsletter issues</h1>
+        <table>
+          <tr>
+            <th>Title</th>
+            <th>Published at</th>
+            <th>Delivered</th>
+            <th>Failed</th>
+            <th>Pending</th>
+          </tr>
+          {rows_html}
+        </table>
+        <p>{pagination_html}</p>
+        <a href="/admin/dashboard">&lt; - Back</a>
+      </body>
+    </html>"#
+        )))
+}
+
+#[tracing::instrument(name = "Count published newsletter issues", skip(db_pool))]
+async fn count_published_issues(db_pool: &PgPool) -> Result<i64, anyhow::Error> {
+    let row = sqlx::query!(
+        r#"
+        SELECT COUNT(*) AS "count!"
+        FROM newsletter_issues
+        WHERE status = 'published'
+    "#
+    )
+    .fetch_one(db_pool)
+    .await
+    .context("Failed to count published newsletter issues.")?;
+
+    Ok(row.count)
+}
+
+#[tracing::instrument(name = "Get a page of published newsletter issues", skip(db_pool))]
+async fn get_published_issues(
+    offset: i64,
+    db_pool: &PgPool,
+) -> Result<Vec<PublishedIssue>, anyhow::Error> {
+    let issues = sqlx::query_as!(
+        PublishedIssue,
+        r#"
+        SELECT
+            id,
+            title,
+            published_at AS "published_at!",
+            n_delivered,
+            n_failed,
+            n_total_recipients - n_delivered - n_failed AS "n_pending!"
+        FROM newsletter_issues
+        WHERE status = 'published'
+        ORDER BY published_at DESC
+        LIMIT $1
+        OFFSET $2
+    "#,
+        ISSUES_PER_PAGE,
+        offset
+    )
+    .fetch_all(db_pool)
+    .await
+    .context("Failed to fetch published newsletter issues.")?;
+
+    Ok(issues)
+}
@@ -1,5 +1,15 @@
+mod drafts;
+mod failures;
 mod get;
+mod history;
 mod post;
+mod schedule;
+mod status;
 
+pub use drafts::*;
+pub use failures::*;
 pub use get::publish_newsletter_form;
+pub use history::newsletter_history;
 pub use post::publish_newsletter;
+pub use schedule::*;
+pub use status::*;
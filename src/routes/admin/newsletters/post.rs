@@ -1,75 +1,44 @@
-use actix_web::{http, http::header, http::header::HeaderValue, web, HttpResponse, ResponseError};
+use actix_web::{web, HttpResponse};
 use actix_web_flash_messages::FlashMessage;
 use anyhow::Context;
+use chrono::{DateTime, Utc};
 use sqlx::{PgPool, Postgres, Transaction};
 use uuid::Uuid;
 
 use crate::{
+    audit_log::{AuditAction, Auditor},
     authentication::UserId,
-    domains::{save_response, try_processing, IdempotencyKey, NextAction},
-    routes::error_chain_fmt,
+    domains::{save_response, try_processing, IdempotencyKey, NextAction, SendAt},
+    issue_delivery_worker::notify_delivery_workers,
     utils::{e400, e500, see_other},
 };
 
-#[derive(thiserror::Error)]
-pub enum PublishError {
-    #[error("Authentication failed.")]
-    _AuthError(#[source] anyhow::Error),
-    #[error(transparent)]
-    UnexpectedError(#[from] anyhow::Error),
-}
-
-impl std::fmt::Debug for PublishError {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        error_chain_fmt(self, f)
-    }
-}
-
-impl ResponseError for PublishError {
-    fn error_response(&self) -> HttpResponse<actix_web::body::BoxBody> {
-        match self {
-            PublishError::UnexpectedError(_) => {
-                HttpResponse::new(http::StatusCode::INTERNAL_SERVER_ERROR)
-            }
-            PublishError::_AuthError(_) => {
-                let mut response = HttpResponse::new(http::StatusCode::UNAUTHORIZED);
-
-                let header_value_authenticate =
-                    HeaderValue::from_str(r#"Basic realm="publish""#).unwrap();
-
-                response
-                    .headers_mut()
-                    .insert(header::WWW_AUTHENTICATE, header_value_authenticate);
-
-                response
-            }
-        }
-    }
-}
-
 #[derive(serde::Deserialize)]
 pub struct FormData {
     pub title: String,
     pub text_content: String,
     pub html_content: String,
     pub idempotency_key: String,
+    pub send_at: Option<String>,
 }
 
 struct NewsletterIssue {
     title: String,
     text_content: String,
     html_content: String,
+    published_at: DateTime<Utc>,
 }
 
 #[tracing::instrument(
     "Publishing newsletter",
-    skip(form_data, db_pool),
+    skip(form_data, db_pool, auditor),
     fields(user_id=%*user_id)
 )]
 pub async fn publish_newsletter(
     form_data: web::Form<FormData>,
     db_pool: web::Data<PgPool>,
     user_id: web::ReqData<UserId>,
+    auditor: Auditor,
 ) -> actix_web::Result<HttpResponse, actix_web::Error> {
     let user_id = user_id.into_inner();
     let FormData {
@@ -77,16 +46,18 @@ pub async fn publish_newsletter(
         text_content,
         html_content,
         idempotency_key,
+        send_at,
     } = form_data.0;
 
     let idempotency_key: IdempotencyKey = idempotency_key.try_into().map_err(e400)?;
+    let send_at = SendAt::parse_optional(send_at.as_deref()).map_err(e400)?;
     let mut transaction = match try_processing(*user_id, &idempotency_key, &db_pool)
         .await
         .map_err(e500)?
     {
         NextAction::StartProcessing(transaction) => transaction,
         NextAction::ReturnSavedResponse(saved_response) => {
-            success_message().send();
+            success_message(send_at).send();
             return Ok(saved_response);
         }
     };
@@ -96,6 +67,7 @@ pub async fn publish_newsletter(
             title,
             text_content,
             html_content,
+            published_at: publication_time(send_at),
         },
         &mut transaction,
     )
@@ -103,17 +75,31 @@ pub async fn publish_newsletter(
     .context("Failed to store newsletter issue details.")
     .map_err(e500)?;
 
-    enqueue_issue_delivery(issue_id, &mut transaction)
+    let n_recipients = enqueue_issue_delivery(issue_id, &mut transaction)
         .await
         .context("Failed enqueueing issue delivery task.")
         .map_err(e500)?;
 
+    set_issue_total_recipients(issue_id, n_recipients, &mut transaction)
+        .await
+        .context("Failed to store the number of recipients of the newsletter issue.")
+        .map_err(e500)?;
+    // Retries of the same request return the saved response, the event isn't recorded twice
+    auditor
+        .record_in(
+            &mut transaction,
+            AuditAction::PublishNewsletter,
+            Some(&format!("issue:{}", issue_id)),
+        )
+        .await
+        .map_err(e500)?;
+
     let response = see_other("/admin/newsletters");
     let response = save_response(*user_id, &idempotency_key, response, transaction)
         .await
         .map_err(e500)?;
 
-    success_message().send();
+    success_message(send_at).send();
     Ok(response)
 }
 
@@ -131,13 +117,15 @@ async fn insert_newsletter_issue(
             title,
             text_content,
             html_content,
-            published_at
-        ) VALUES ($1, $2, $3, $4, now())
+            published_at,
+            status
+        ) VALUES ($1, $2, $3, $4, $5, 'published')
     "#,
         newsletter_issue_id,
         newsletter_issue.title,
         newsletter_issue.text_content,
-        newsletter_issue.html_content
+        newsletter_issue.html_content,
+        newsletter_issue.published_at
     )
     .execute(transaction)
     .await?;
@@ -146,11 +134,11 @@ async fn insert_newsletter_issue(
 }
 
 #[tracing::instrument(skip_all)]
-async fn enqueue_issue_delivery(
+pub(super) async fn enqueue_issue_delivery(
     newsletter_issue_id: Uuid,
     transaction: &mut Transaction<'_, Postgres>,
-) -> Result<(), sqlx::Error> {
-    sqlx::query!(
+) -> Result<i32, anyhow::Error> {
+    let n_enqueued = sqlx::query!(
         r#"
         INSERT INTO issue_delivery_queue (
             newsletter_issue_id,
@@ -161,12 +149,56 @@ async fn enqueue_issue_delivery(
     "#,
         newsletter_issue_id
     )
+    .execute(&mut *transaction)
+    .await?
+    .rows_affected();
+
+    if n_enqueued > 0 {
+        notify_delivery_workers(transaction).await?;
+    }
+
+    Ok(n_enqueued.try_into()?)
+}
+
+#[tracing::instrument(skip_all)]
+pub(super) async fn set_issue_total_recipients(
+    newsletter_issue_id: Uuid,
+    n_total_recipients: i32,
+    transaction: &mut Transaction<'_, Postgres>,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        UPDATE newsletter_issues
+        SET
+            n_total_recipients = $2,
+            completed_at = CASE WHEN $2 = 0 THEN now() END
+        WHERE id = $1
+    "#,
+        newsletter_issue_id,
+        n_total_recipients
+    )
     .execute(transaction)
     .await?;
 
     Ok(())
 }
 
-fn success_message() -> FlashMessage {
-    FlashMessage::info("The newsletter issue has been accepted, emails will go out shortly.")
+/// Issues scheduled in the past go out right away.
+pub(super) fn publication_time(send_at: Option<SendAt>) -> DateTime<Utc> {
+    match send_at {
+        Some(send_at) if send_at.is_in_the_future() => send_at.into(),
+        _ => Utc::now(),
+    }
+}
+
+pub(super) fn success_message(send_at: Option<SendAt>) -> FlashMessage {
+    match send_at {
+        Some(send_at) if send_at.is_in_the_future() => FlashMessage::info(format!(
+            "The newsletter issue has been scheduled, emails will go out at {}.",
+            send_at
+        )),
+        _ => FlashMessage::info(
+            "The newsletter issue has been accepted, emails will go out shortly.",
+        ),
+    }
 }
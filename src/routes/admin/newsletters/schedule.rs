@@ -0,0 +1,183 @@
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use sqlx::{PgPool, Postgres, Transaction};
+use uuid::Uuid;
+
+use crate::{
+    audit_log::{AuditAction, Auditor},
+    domains::SendAt,
+    issue_delivery_worker::notify_delivery_workers,
+    routes::admin::newsletters::post::{publication_time, success_message},
+    utils::{e400, e500, see_other},
+};
+
+#[derive(serde::Deserialize)]
+pub struct RescheduleFormData {
+    pub send_at: Option<String>,
+}
+
+#[tracing::instrument(name = "Cancel a scheduled newsletter issue", skip(db_pool, auditor))]
+pub async fn cancel_scheduled_issue(
+    issue_id: web::Path<Uuid>,
+    db_pool: web::Data<PgPool>,
+    auditor: Auditor,
+) -> Result<HttpResponse, actix_web::Error> {
+    let issue_id = issue_id.into_inner();
+
+    let mut transaction = db_pool
+        .begin()
+        .await
+        .context("Failed to acquire connection from DB pool.")
+        .map_err(e500)?;
+
+    if !lock_scheduled_issue(issue_id, &mut transaction)
+        .await
+        .map_err(e500)?
+    {
+        FlashMessage::error("Only issues that haven't gone out yet can be cancelled.").send();
+        return Ok(see_other(&format!("/admin/newsletters/{}", issue_id)));
+    }
+
+    delete_issue(issue_id, &mut transaction)
+        .await
+        .context("Failed to delete the scheduled newsletter issue.")
+        .map_err(e500)?;
+    auditor
+        .record_in(
+            &mut transaction,
+            AuditAction::CancelIssue,
+            Some(&format!("issue:{}", issue_id)),
+        )
+        .await
+        .map_err(e500)?;
+
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction for cancelling a newsletter issue.")
+        .map_err(e500)?;
+
+    FlashMessage::info("The scheduled newsletter issue has been cancelled.").send();
+    Ok(see_other("/admin/newsletters"))
+}
+
+#[tracing::instrument(
+    name = "Reschedule a newsletter issue",
+    skip(form_data, db_pool, auditor),
+    fields(send_at = ?form_data.send_at)
+)]
+pub async fn reschedule_issue(
+    issue_id: web::Path<Uuid>,
+    form_data: web::Form<RescheduleFormData>,
+    db_pool: web::Data<PgPool>,
+    auditor: Auditor,
+) -> Result<HttpResponse, actix_web::Error> {
+    let issue_id = issue_id.into_inner();
+    let send_at = SendAt::parse_optional(form_data.send_at.as_deref()).map_err(e400)?;
+    let status_page = format!("/admin/newsletters/{}", issue_id);
+
+    let mut transaction = db_pool
+        .begin()
+        .await
+        .context("Failed to acquire connection from DB pool.")
+        .map_err(e500)?;
+
+    if !lock_scheduled_issue(issue_id, &mut transaction)
+        .await
+        .map_err(e500)?
+    {
+        FlashMessage::error("Only issues that haven't gone out yet can be rescheduled.").send();
+        return Ok(see_other(&status_page));
+    }
+
+    let published_at = publication_time(send_at);
+    set_publication_time(issue_id, published_at, &mut transaction)
+        .await
+        .context("Failed to reschedule the newsletter issue.")
+        .map_err(e500)?;
+    auditor
+        .record_in(
+            &mut transaction,
+            AuditAction::RescheduleIssue,
+            Some(&format!(
+                "issue:{} at {}",
+                issue_id,
+                published_at.format("%Y-%m-%d %H:%M UTC")
+            )),
+        )
+        .await
+        .map_err(e500)?;
+
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction for rescheduling a newsletter issue.")
+        .map_err(e500)?;
+
+    success_message(send_at).send();
+    Ok(see_other(&status_page))
+}
+
+/// Lock the issue for the rest of the transaction,
+/// returning `false` if it doesn't exist or has already started going out.
+#[tracing::instrument(skip_all)]
+async fn lock_scheduled_issue(
+    issue_id: Uuid,
+    transaction: &mut Transaction<'_, Postgres>,
+) -> Result<bool, anyhow::Error> {
+    let row = sqlx::query!(
+        r#"
+        SELECT id
+        FROM newsletter_issues
+        WHERE
+            id = $1 AND
+            published_at > now()
+        FOR UPDATE
+    "#,
+        issue_id
+    )
+    .fetch_optional(transaction)
+    .await
+    .context("Failed to fetch the scheduled newsletter issue.")?;
+
+    Ok(row.is_some())
+}
+
+#[tracing::instrument(skip_all)]
+async fn delete_issue(
+    issue_id: Uuid,
+    transaction: &mut Transaction<'_, Postgres>,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        "DELETE FROM issue_delivery_queue WHERE newsletter_issue_id = $1",
+        issue_id
+    )
+    .execute(&mut *transaction)
+    .await?;
+
+    sqlx::query!("DELETE FROM newsletter_issues WHERE id = $1", issue_id)
+        .execute(transaction)
+        .await?;
+
+    Ok(())
+}
+
+#[tracing::instrument(skip_all)]
+async fn set_publication_time(
+    issue_id: Uuid,
+    published_at: DateTime<Utc>,
+    transaction: &mut Transaction<'_, Postgres>,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        "UPDATE newsletter_issues SET published_at = $2 WHERE id = $1",
+        issue_id,
+        published_at
+    )
+    .execute(&mut *transaction)
+    .await?;
+
+    // Idle workers might be waiting for the former publication time.
+    notify_delivery_workers(transaction).await
+}
@@ -0,0 +1,131 @@
+use actix_web::{http::header::ContentType, web, HttpResponse};
+use actix_web_flash_messages::IncomingFlashMessages;
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use htmlescape::encode_minimal;
+use sqlx::PgPool;
+use std::fmt::Write;
+use uuid::Uuid;
+
+use crate::utils::{e404, e500};
+
+#[derive(serde::Serialize)]
+pub struct IssueDeliveryStatus {
+    pub issue_id: Uuid,
+    pub title: String,
+    pub published_at: DateTime<Utc>,
+    pub total_recipients: i32,
+    pub delivered: i32,
+    pub failed: i32,
+    pub pending: i32,
+    pub completed_at: Option<DateTime<Utc>>,
+}
+
+pub async fn issue_delivery_status(
+    issue_id: web::Path<Uuid>,
+    db_pool: web::Data<PgPool>,
+    flash_messages: IncomingFlashMessages,
+) -> Result<HttpResponse, actix_web::Error> {
+    let status = get_issue_delivery_status(issue_id.into_inner(), &db_pool)
+        .await
+        .map_err(e500)?
+        .ok_or_else(|| e404("Newsletter issue not found."))?;
+
+    let mut message_html = String::new();
+    for m in flash_messages.iter() {
+        writeln!(message_html, "<p><i>{}</i></p>", m.content()).unwrap();
+    }
+
+    let completion = match status.completed_at {
+        Some(completed_at) => format!("Completed at {}", completed_at.to_rfc3339()),
+        None if status.published_at > Utc::now() => format!(
+            r#"Scheduled, emails will go out at {published_at}
+        <form action="/admin/newsletters/{issue_id}/reschedule" method="POST">
+          <input type="datetime-local" name="send_at"/>
+          <input type="submit" value="Reschedule"/>
+        </form>
+        <form action="/admin/newsletters/{issue_id}/cancel" method="POST">
+          <input type="submit" value="Cancel"/>
+        </form>"#,
+            published_at = status.published_at.format("%Y-%m-%d %H:%M UTC"),
+            issue_id = status.issue_id,
+        ),
+        None => "In progress".to_string(),
+    };
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"
+    <html>
+      <head>
+        <meta http-equiv="content-type" content="text/html; charset=utf-8">
+        <title>Newsletter Issue Status</title>
+      </head>
+      <body>
+        {message_html}
+        <h1>{title}</h1>
+        <p>Published at {published_at}</p>
+        <p>{completion}</p>
+        <ul>
+          <li>Total recipients: {total}</li>
+          <li>Delivered: {delivered}</li>
+          <li>Failed: {failed}</li>
+          <li>Pending: {pending}</li>
+        </ul>
+        <a href="/admin/newsletters/{issue_id}/failures">See failed deliveries</a>
+        <a href="/admin/dashboard">&lt; - Back</a>
+      </body>
+    </html>"#,
+            title = encode_minimal(&status.title),
+            published_at = status.published_at.to_rfc3339(),
+            total = status.total_recipients,
+            delivered = status.delivered,
+            failed = status.failed,
+            pending = status.pending,
+            issue_id = status.issue_id,
+        )))
+}
+
+pub async fn issue_delivery_status_json(
+    issue_id: web::Path<Uuid>,
+    db_pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let status = get_issue_delivery_status(issue_id.into_inner(), &db_pool)
+        .await
+        .map_err(e500)?
+        .ok_or_else(|| e404("Newsletter issue not found."))?;
+
+    Ok(HttpResponse::Ok().json(status))
+}
+
+#[tracing::instrument(name = "Get delivery status of a newsletter issue", skip(db_pool))]
+async fn get_issue_delivery_status(
+    issue_id: Uuid,
+    db_pool: &PgPool,
+) -> Result<Option<IssueDeliveryStatus>, anyhow::Error> {
+    let status = sqlx::query_as!(
+        IssueDeliveryStatus,
+        r#"
+        SELECT
+            id AS issue_id,
+            title,
+            published_at AS "published_at!",
+            n_total_recipients AS total_recipients,
+            n_delivered AS delivered,
+            n_failed AS failed,
+            n_total_recipients - n_delivered - n_failed AS "pending!",
+            completed_at
+        FROM newsletter_issues
+        WHERE
+            id = $1 AND
+            status = 'published'
+    "#,
+        issue_id
+    )
+    .fetch_optional(db_pool)
+    .await
+    .context("Failed to fetch the delivery status of the newsletter issue.")?;
+
+    Ok(status)
+}
@@ -4,8 +4,12 @@ use secrecy::{ExposeSecret, Secret};
 use sqlx::PgPool;
 
 use crate::{
-    authentication::{self, validate_credentials, AuthError, UserCredentials, UserId},
+    audit_log::{AuditAction, Auditor},
+    authentication::{
+        self, revoke_other_user_sessions, validate_credentials, AuthError, UserCredentials, UserId,
+    },
     routes::admin::dashboard::get_username,
+    session_state::TypedSession,
     utils::{e500, see_other},
 };
 
@@ -20,6 +24,8 @@ pub async fn change_password(
     form_data: web::Form<FormData>,
     db_pool: web::Data<PgPool>,
     user_id: web::ReqData<UserId>,
+    session: TypedSession,
+    auditor: Auditor,
 ) -> Result<HttpResponse, actix_web::Error> {
     let user_id = user_id.into_inner();
 
@@ -28,8 +34,7 @@ pub async fn change_password(
         return Ok(see_other("/admin/password"));
     }
 
-    let password_length = form_data.new_password.expose_secret().chars().count();
-    if !(12..=128).contains(&password_length) {
+    if !authentication::is_valid_password_length(&form_data.new_password) {
         FlashMessage::error("New password must be between 12 and 128 characters long.").send();
         return Ok(see_other("/admin/password"));
     }
@@ -54,7 +59,20 @@ pub async fn change_password(
     authentication::change_password(*user_id, form_data.0.new_password, &db_pool)
         .await
         .map_err(e500)?;
+    // Whoever knew the previous password must not stay logged in with it
+    let session_id = session
+        .get_session_id()
+        .map_err(e500)?
+        .ok_or_else(|| e500("The session id is missing."))?;
+    revoke_other_user_sessions(*user_id, session_id, &db_pool)
+        .await
+        .map_err(e500)?;
+    auditor
+        .record(AuditAction::ChangePassword, None)
+        .await
+        .map_err(e500)?;
 
     FlashMessage::info("Password changed successfully.").send();
+    FlashMessage::info("Your other sessions have been logged out.").send();
     Ok(see_other("/admin/password"))
 }
@@ -0,0 +1,88 @@
+use actix_web::{http::header::ContentType, web, HttpResponse};
+use actix_web_flash_messages::IncomingFlashMessages;
+use htmlescape::encode_minimal;
+use sqlx::PgPool;
+use std::fmt::Write;
+
+use crate::{
+    authentication::{list_user_sessions, UserId},
+    session_state::TypedSession,
+    utils::e500,
+};
+
+pub async fn sessions_page(
+    user_id: web::ReqData<UserId>,
+    session: TypedSession,
+    db_pool: web::Data<PgPool>,
+    flash_messages: IncomingFlashMessages,
+) -> Result<HttpResponse, actix_web::Error> {
+    let current_session_id = session.get_session_id().map_err(e500)?;
+    let sessions = list_user_sessions(*user_id.into_inner(), &db_pool)
+        .await
+        .map_err(e500)?;
+
+    let mut message_html = String::new();
+    for m in flash_messages.iter() {
+        writeln!(message_html, "<p><i>{}</i></p>", m.content()).unwrap();
+    }
+
+    let mut rows_html = String::new();
+    for user_session in &sessions {
+        let action_html = if Some(user_session.id) == current_session_id {
+            "This session".to_string()
+        } else {
+            format!(
+                r#"<form action="/admin/sessions/{}/revoke" method="POST">
+                        <input type="submit" value="Log out"/>
+                    </form>"#,
+                user_session.id
+            )
+        };
+        writeln!(
+            rows_html,
+            r#"<tr>
+                <td>{created_at}</td>
+                <td>{last_seen_at}</td>
+                <td>{client_ip}</td>
+                <td>{user_agent}</td>
+                <td>{action_html}</td>
+            </tr>"#,
+            created_at = user_session.created_at.format("%Y-%m-%d %H:%M UTC"),
+            last_seen_at = user_session.last_seen_at.format("%Y-%m-%d %H:%M UTC"),
+            client_ip = encode_minimal(&user_session.client_ip),
+            user_agent = encode_minimal(&user_session.user_agent),
+        )
+        .unwrap();
+    }
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"
+    <html>
+      <head>
+        <meta http-equiv="content-type" content="text/html; charset=utf-8">
+        <title>Sessions</title>
+      </head>
+      <body>
+        {message_html}
+        <h1>Sessions</h1>
+        <p>The devices you are logged in from.</p>
+        <table>
+          <tr>
+            <th>Logged in</th>
+            <th>Last seen</th>
+            <th>IP address</th>
+            <th>Browser</th>
+            <th></th>
+          </tr>
+          {rows_html}
+        </table>
+        <form action="/admin/sessions/revoke-others" method="POST">
+          <input type="submit" value="Log out of every other session" />
+        </form>
+        <a href="/admin/dashboard">&lt; - Back</a>
+      </body>
+    </html>"#
+        )))
+}
@@ -0,0 +1,5 @@
+mod get;
+mod post;
+
+pub use get::sessions_page;
+pub use post::{revoke_other_sessions, revoke_session};
@@ -0,0 +1,73 @@
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::{
+    audit_log::{AuditAction, Auditor},
+    authentication::{revoke_other_user_sessions, revoke_user_session, UserId},
+    session_state::TypedSession,
+    utils::{e500, see_other},
+};
+
+const SESSIONS_PAGE: &str = "/admin/sessions";
+
+/// Logging out of the current session is what `/admin/logout` is for.
+#[tracing::instrument(name = "Revoke a session", skip(user_id, session, db_pool, auditor))]
+pub async fn revoke_session(
+    session_id: web::Path<Uuid>,
+    user_id: web::ReqData<UserId>,
+    session: TypedSession,
+    db_pool: web::Data<PgPool>,
+    auditor: Auditor,
+) -> Result<HttpResponse, actix_web::Error> {
+    let session_id = session_id.into_inner();
+    if session.get_session_id().map_err(e500)? == Some(session_id) {
+        FlashMessage::error("Use the logout button to log out of this session.").send();
+        return Ok(see_other(SESSIONS_PAGE));
+    }
+
+    if revoke_user_session(*user_id.into_inner(), session_id, &db_pool)
+        .await
+        .map_err(e500)?
+    {
+        auditor
+            .record(
+                AuditAction::RevokeSession,
+                Some(&format!("session:{}", session_id)),
+            )
+            .await
+            .map_err(e500)?;
+        FlashMessage::info("The session has been logged out.").send();
+    } else {
+        FlashMessage::error("There is no such session.").send();
+    }
+    Ok(see_other(SESSIONS_PAGE))
+}
+
+#[tracing::instrument(
+    name = "Revoke the other sessions",
+    skip(user_id, session, db_pool, auditor)
+)]
+pub async fn revoke_other_sessions(
+    user_id: web::ReqData<UserId>,
+    session: TypedSession,
+    db_pool: web::Data<PgPool>,
+    auditor: Auditor,
+) -> Result<HttpResponse, actix_web::Error> {
+    let session_id = session
+        .get_session_id()
+        .map_err(e500)?
+        .ok_or_else(|| e500("The session id is missing."))?;
+
+    revoke_other_user_sessions(*user_id.into_inner(), session_id, &db_pool)
+        .await
+        .map_err(e500)?;
+    auditor
+        .record(AuditAction::RevokeOtherSessions, None)
+        .await
+        .map_err(e500)?;
+
+    FlashMessage::info("Every other session has been logged out.").send();
+    Ok(see_other(SESSIONS_PAGE))
+}
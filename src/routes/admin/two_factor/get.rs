@@ -0,0 +1,83 @@
+use actix_web::{http::header::ContentType, web, HttpResponse};
+use actix_web_flash_messages::IncomingFlashMessages;
+use htmlescape::encode_minimal;
+use sqlx::PgPool;
+use std::fmt::Write;
+
+use crate::{
+    authentication::{get_totp_secret, TotpSecret, UserId},
+    routes::admin::dashboard::get_username,
+    session_state::TypedSession,
+    utils::e500,
+};
+
+pub async fn two_factor_settings(
+    user_id: web::ReqData<UserId>,
+    db_pool: web::Data<PgPool>,
+    session: TypedSession,
+    flash_messages: IncomingFlashMessages,
+) -> Result<HttpResponse, actix_web::Error> {
+    let user_id = user_id.into_inner();
+
+    let mut message_html = String::new();
+    for m in flash_messages.iter() {
+        writeln!(message_html, "<p><i>{}</i></p>", m.content()).unwrap();
+    }
+
+    let is_enabled = get_totp_secret(*user_id, &db_pool)
+        .await
+        .map_err(e500)?
+        .is_some();
+    let settings_html = if is_enabled {
+        r#"<p>Two-factor authentication is on.</p>
+        <form action="/admin/2fa/disable" method="POST">
+          <input type="text" placeholder="Code or recovery code" name="code" autocomplete="one-time-code" />
+          <input type="submit" value="Turn off" />
+        </form>"#
+            .to_string()
+    } else {
+        // Reloading the page must not change the secret the user may have scanned already
+        let secret = match session.get_totp_enrollment_secret().map_err(e500)? {
+            Some(secret) => TotpSecret::parse(secret).map_err(e500)?,
+            None => {
+                let secret = TotpSecret::generate();
+                session
+                    .set_totp_enrollment_secret(secret.expose_base32())
+                    .map_err(e500)?;
+                secret
+            }
+        };
+        let username = get_username(*user_id, &db_pool).await.map_err(e500)?;
+
+        format!(
+            r#"<p>Two-factor authentication is off.</p>
+        <p>To turn it on, add this account to your authenticator app:</p>
+        <p><a href="{uri}">{uri}</a></p>
+        <p>Or type the secret in: <code>{secret}</code></p>
+        <form action="/admin/2fa" method="POST">
+          <input type="text" placeholder="Code from the app" name="code" autocomplete="one-time-code" />
+          <input type="submit" value="Turn on" />
+        </form>"#,
+            uri = encode_minimal(&secret.otpauth_uri(&username)),
+            secret = secret.expose_base32(),
+        )
+    };
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"
+    <html>
+      <head>
+        <meta http-equiv="content-type" content="text/html; charset=utf-8">
+        <title>Two-factor authentication</title>
+      </head>
+      <body>
+        {message_html}
+        <h1>Two-factor authentication</h1>
+        {settings_html}
+        <a href="/admin/dashboard">&lt; - Back</a>
+      </body>
+    </html>"#
+        )))
+}
@@ -0,0 +1,115 @@
+use actix_web::{http::header::ContentType, web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use sqlx::PgPool;
+use std::fmt::Write;
+
+use crate::{
+    audit_log::{AuditAction, Auditor},
+    authentication::{
+        generate_recovery_codes, remove_two_factor_settings, save_two_factor_settings,
+        verify_second_factor, TotpSecret, UserId,
+    },
+    session_state::TypedSession,
+    utils::{e500, see_other},
+};
+
+#[derive(serde::Deserialize)]
+pub struct CodeFormData {
+    pub code: String,
+}
+
+/// Turns two-factor authentication on once the user has entered a code from their app,
+/// then shows the recovery codes: this is the only time they are displayed.
+#[tracing::instrument(
+    name = "Enable two-factor authentication",
+    skip(form_data, db_pool, session, auditor)
+)]
+pub async fn enable_two_factor(
+    form_data: web::Form<CodeFormData>,
+    user_id: web::ReqData<UserId>,
+    db_pool: web::Data<PgPool>,
+    session: TypedSession,
+    auditor: Auditor,
+) -> Result<HttpResponse, actix_web::Error> {
+    let user_id = user_id.into_inner();
+
+    let secret = match session.get_totp_enrollment_secret().map_err(e500)? {
+        Some(secret) => TotpSecret::parse(secret).map_err(e500)?,
+        None => return Ok(see_other("/admin/2fa")),
+    };
+    let verified_step = match secret.verify(&form_data.code, chrono::Utc::now().timestamp()) {
+        Some(step) => step,
+        None => {
+            FlashMessage::error("Invalid code, check the clock of your device.").send();
+            return Ok(see_other("/admin/2fa"));
+        }
+    };
+
+    let recovery_codes = generate_recovery_codes();
+    save_two_factor_settings(*user_id, &secret, verified_step, &recovery_codes, &db_pool)
+        .await
+        .map_err(e500)?;
+    session.remove_totp_enrollment_secret();
+    auditor
+        .record(AuditAction::EnableTwoFactor, None)
+        .await
+        .map_err(e500)?;
+
+    let mut codes_html = String::new();
+    for code in &recovery_codes {
+        writeln!(codes_html, "<li><code>{}</code></li>", code).unwrap();
+    }
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"
+    <html>
+      <head>
+        <meta http-equiv="content-type" content="text/html; charset=utf-8">
+        <title>Two-factor authentication</title>
+      </head>
+      <body>
+        <p>Two-factor authentication is on.</p>
+        <p>Keep these recovery codes somewhere safe, each of them logs you in once
+        if you lose your authenticator app. They won't be shown again.</p>
+        <ul>
+          {codes_html}
+        </ul>
+        <a href="/admin/dashboard">&lt; - Back</a>
+      </body>
+    </html>"#
+        )))
+}
+
+#[tracing::instrument(
+    name = "Disable two-factor authentication",
+    skip(form_data, db_pool, auditor)
+)]
+pub async fn disable_two_factor(
+    form_data: web::Form<CodeFormData>,
+    user_id: web::ReqData<UserId>,
+    db_pool: web::Data<PgPool>,
+    auditor: Auditor,
+) -> Result<HttpResponse, actix_web::Error> {
+    let user_id = user_id.into_inner();
+
+    if !verify_second_factor(*user_id, &form_data.code, &db_pool)
+        .await
+        .map_err(e500)?
+    {
+        FlashMessage::error("Invalid code.").send();
+        return Ok(see_other("/admin/2fa"));
+    }
+
+    remove_two_factor_settings(*user_id, &db_pool)
+        .await
+        .map_err(e500)?;
+    auditor
+        .record(AuditAction::DisableTwoFactor, None)
+        .await
+        .map_err(e500)?;
+
+    FlashMessage::info("Two-factor authentication is off.").send();
+    Ok(see_other("/admin/2fa"))
+}
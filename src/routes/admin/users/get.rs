@@ -0,0 +1,122 @@
+use actix_web::{http::header::ContentType, web, HttpResponse};
+use actix_web_flash_messages::IncomingFlashMessages;
+use htmlescape::encode_minimal;
+use sqlx::PgPool;
+use std::fmt::Write;
+
+use crate::{
+    authentication::{list_users, Role, UserId},
+    utils::e500,
+};
+
+pub async fn users_page(
+    user_id: web::ReqData<UserId>,
+    db_pool: web::Data<PgPool>,
+    flash_messages: IncomingFlashMessages,
+) -> Result<HttpResponse, actix_web::Error> {
+    let current_user_id = *user_id.into_inner();
+    let users = list_users(&db_pool).await.map_err(e500)?;
+
+    let mut message_html = String::new();
+    for m in flash_messages.iter() {
+        writeln!(message_html, "<p><i>{}</i></p>", m.content()).unwrap();
+    }
+
+    let mut rows_html = String::new();
+    for user in &users {
+        let (toggle_action, toggle_label) = if user.disabled {
+            ("enable", "Enable")
+        } else {
+            ("disable", "Disable")
+        };
+        let username = if user.id == current_user_id {
+            format!("{} (you)", encode_minimal(&user.username))
+        } else {
+            encode_minimal(&user.username)
+        };
+        writeln!(
+            rows_html,
+            r#"<tr>
+                <td>{username}</td>
+                <td>
+                    <form action="/admin/users/{id}/role" method="POST">
+                        <select name="role">{role_options}</select>
+                        <input type="submit" value="Change role"/>
+                    </form>
+                </td>
+                <td>
+                    <form action="/admin/users/{id}/email" method="POST">
+                        <input type="email" placeholder="No email address" name="email" value="{email}"/>
+                        <input type="submit" value="Change email"/>
+                    </form>
+                </td>
+                <td>{status}</td>
+                <td>
+                    <form action="/admin/users/{id}/{toggle_action}" method="POST">
+                        <input type="submit" value="{toggle_label}"/>
+                    </form>
+                </td>
+                <td>
+                    <form action="/admin/users/{id}/delete" method="POST">
+                        <input type="submit" value="Delete"/>
+                    </form>
+                </td>
+            </tr>"#,
+            id = user.id,
+            role_options = role_options(user.role),
+            email = encode_minimal(user.email.as_deref().unwrap_or_default()),
+            status = if user.disabled { "Disabled" } else { "Enabled" },
+        )
+        .unwrap();
+    }
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"
+    <html>
+      <head>
+        <meta http-equiv="content-type" content="text/html; charset=utf-8">
+        <title>Users</title>
+      </head>
+      <body>
+        {message_html}
+        <h1>Users</h1>
+        <table>
+          <tr>
+            <th>Username</th>
+            <th>Role</th>
+            <th>Email</th>
+            <th>Status</th>
+            <th></th>
+            <th></th>
+          </tr>
+          {rows_html}
+        </table>
+        <h2>Add a user</h2>
+        <form action="/admin/users" method="POST">
+          <input type="text" placeholder="Enter username" name="username" />
+          <input type="password" placeholder="Enter password" name="password" />
+          <input type="password" placeholder="Enter password again" name="password_check" />
+          <select name="role">{new_user_role_options}</select>
+          <input type="submit" value="Add" />
+        </form>
+        <a href="/admin/dashboard">&lt; - Back</a>
+      </body>
+    </html>"#,
+            new_user_role_options = role_options(Role::Editor),
+        )))
+}
+
+fn role_options(selected: Role) -> String {
+    let mut options = String::new();
+    for role in Role::ALL {
+        write!(
+            options,
+            r#"<option value="{role}"{selected}>{role}</option>"#,
+            selected = if role == selected { " selected" } else { "" },
+        )
+        .unwrap();
+    }
+    options
+}
@@ -0,0 +1,237 @@
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use htmlescape::encode_minimal;
+use secrecy::{ExposeSecret, Secret};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::{
+    audit_log::{AuditAction, Auditor},
+    authentication::{
+        create_user, delete_user, find_user, set_disabled, set_email, set_role, Role,
+        UserManagementError,
+    },
+    domains::SubscriberEmail,
+    utils::{e400, e500, see_other},
+};
+
+const USERS_PAGE: &str = "/admin/users";
+
+#[derive(serde::Deserialize)]
+pub struct NewUserFormData {
+    pub username: String,
+    pub password: Secret<String>,
+    pub password_check: Secret<String>,
+    pub role: String,
+}
+
+#[derive(serde::Deserialize)]
+pub struct RoleFormData {
+    pub role: String,
+}
+
+#[derive(serde::Deserialize)]
+pub struct EmailFormData {
+    pub email: String,
+}
+
+#[tracing::instrument(
+    name = "Add a user",
+    skip(form_data, db_pool, auditor),
+    fields(username = %form_data.username, role = %form_data.role)
+)]
+pub async fn add_user(
+    form_data: web::Form<NewUserFormData>,
+    db_pool: web::Data<PgPool>,
+    auditor: Auditor,
+) -> Result<HttpResponse, actix_web::Error> {
+    let NewUserFormData {
+        username,
+        password,
+        password_check,
+        role,
+    } = form_data.0;
+    let role = Role::parse(&role).map_err(e400)?;
+
+    if password.expose_secret() != password_check.expose_secret() {
+        FlashMessage::error("The passwords don't match.").send();
+        return Ok(see_other(USERS_PAGE));
+    }
+
+    match create_user(&username, password, role, &db_pool).await {
+        Ok(_) => {
+            auditor
+                .record(
+                    AuditAction::AddUser,
+                    Some(&format!("user:{} as {}", username.trim(), role)),
+                )
+                .await
+                .map_err(e500)?;
+            FlashMessage::info(format!(
+                "{} has been added as {}.",
+                encode_minimal(username.trim()),
+                role
+            ))
+            .send()
+        }
+        Err(e) => flash_or_fail(e)?,
+    }
+    Ok(see_other(USERS_PAGE))
+}
+
+#[tracing::instrument(name = "Change the role of a user", skip(form_data, db_pool, auditor))]
+pub async fn change_user_role(
+    user_id: web::Path<Uuid>,
+    form_data: web::Form<RoleFormData>,
+    db_pool: web::Data<PgPool>,
+    auditor: Auditor,
+) -> Result<HttpResponse, actix_web::Error> {
+    let role = Role::parse(&form_data.role).map_err(e400)?;
+
+    update_user(
+        user_id.into_inner(),
+        |user_id| set_role(user_id, role, &db_pool),
+        AuditAction::ChangeUserRole,
+        &format!("is now {}", role),
+        &db_pool,
+        &auditor,
+    )
+    .await
+}
+
+/// An empty email address removes it, leaving the user unable to reset a forgotten password.
+#[tracing::instrument(
+    name = "Change the email address of a user",
+    skip(form_data, db_pool, auditor)
+)]
+pub async fn change_user_email(
+    user_id: web::Path<Uuid>,
+    form_data: web::Form<EmailFormData>,
+    db_pool: web::Data<PgPool>,
+    auditor: Auditor,
+) -> Result<HttpResponse, actix_web::Error> {
+    let email = form_data.0.email.trim().to_string();
+    let email = if email.is_empty() {
+        None
+    } else {
+        match SubscriberEmail::parse(email) {
+            Ok(email) => Some(email),
+            Err(e) => {
+                FlashMessage::error(encode_minimal(&e)).send();
+                return Ok(see_other(USERS_PAGE));
+            }
+        }
+    };
+    let outcome = match &email {
+        Some(email) => format!("now uses {}", email),
+        None => "has no email address anymore".into(),
+    };
+
+    update_user(
+        user_id.into_inner(),
+        |user_id| set_email(user_id, email.as_ref(), &db_pool),
+        AuditAction::ChangeUserEmail,
+        &outcome,
+        &db_pool,
+        &auditor,
+    )
+    .await
+}
+
+#[tracing::instrument(name = "Disable a user", skip(db_pool, auditor))]
+pub async fn disable_user(
+    user_id: web::Path<Uuid>,
+    db_pool: web::Data<PgPool>,
+    auditor: Auditor,
+) -> Result<HttpResponse, actix_web::Error> {
+    update_user(
+        user_id.into_inner(),
+        |user_id| set_disabled(user_id, true, &db_pool),
+        AuditAction::DisableUser,
+        "has been disabled",
+        &db_pool,
+        &auditor,
+    )
+    .await
+}
+
+#[tracing::instrument(name = "Enable a user", skip(db_pool, auditor))]
+pub async fn enable_user(
+    user_id: web::Path<Uuid>,
+    db_pool: web::Data<PgPool>,
+    auditor: Auditor,
+) -> Result<HttpResponse, actix_web::Error> {
+    update_user(
+        user_id.into_inner(),
+        |user_id| set_disabled(user_id, false, &db_pool),
+        AuditAction::EnableUser,
+        "has been enabled",
+        &db_pool,
+        &auditor,
+    )
+    .await
+}
+
+#[tracing::instrument(name = "Remove a user", skip(db_pool, auditor))]
+pub async fn remove_user(
+    user_id: web::Path<Uuid>,
+    db_pool: web::Data<PgPool>,
+    auditor: Auditor,
+) -> Result<HttpResponse, actix_web::Error> {
+    update_user(
+        user_id.into_inner(),
+        |user_id| delete_user(user_id, &db_pool),
+        AuditAction::DeleteUser,
+        "has been deleted",
+        &db_pool,
+        &auditor,
+    )
+    .await
+}
+
+/// Applies `change` to the user, and tells the owner how it went on the users page.
+/// `outcome` is appended to the username in the audit log as well.
+async fn update_user<F, Fut>(
+    user_id: Uuid,
+    change: F,
+    action: AuditAction,
+    outcome: &str,
+    db_pool: &PgPool,
+    auditor: &Auditor,
+) -> Result<HttpResponse, actix_web::Error>
+where
+    F: FnOnce(Uuid) -> Fut,
+    Fut: std::future::Future<Output = Result<(), UserManagementError>>,
+{
+    let user = match find_user(user_id, db_pool).await {
+        Ok(user) => user,
+        Err(e) => {
+            flash_or_fail(e)?;
+            return Ok(see_other(USERS_PAGE));
+        }
+    };
+
+    match change(user_id).await {
+        Ok(()) => {
+            auditor
+                .record(action, Some(&format!("user:{} {}", user.username, outcome)))
+                .await
+                .map_err(e500)?;
+            FlashMessage::info(encode_minimal(&format!("{} {}.", user.username, outcome))).send()
+        }
+        Err(e) => flash_or_fail(e)?,
+    }
+    Ok(see_other(USERS_PAGE))
+}
+
+/// Mistakes are reported on the users page, anything else is a server error.
+fn flash_or_fail(e: UserManagementError) -> Result<(), actix_web::Error> {
+    match e {
+        UserManagementError::UnexpectedError(_) => Err(e500(e)),
+        // Some of them quote the username
+        e => {
+            FlashMessage::error(encode_minimal(&e.to_string())).send();
+            Ok(())
+        }
+    }
+}
@@ -0,0 +1,125 @@
+use actix_web::{http::header::ContentType, web, HttpResponse};
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use htmlescape::encode_minimal;
+use sqlx::PgPool;
+use std::fmt::Write;
+use uuid::Uuid;
+
+use crate::utils::{e404, e500};
+
+struct ArchivedIssue {
+    id: Uuid,
+    title: String,
+    html_content: String,
+    published_at: DateTime<Utc>,
+}
+
+pub async fn archive(db_pool: web::Data<PgPool>) -> Result<HttpResponse, actix_web::Error> {
+    let mut issues_html = String::new();
+    for issue in get_archived_issues(&db_pool).await.map_err(e500)? {
+        writeln!(
+            issues_html,
+            r#"<li><a href="/archive/{}">{}</a> ({})</li>"#,
+            issue.id,
+            encode_minimal(&issue.title),
+            issue.published_at.format("%Y-%m-%d"),
+        )
+        .unwrap();
+    }
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+    <html lang="en">
+      <head>
+        <meta charset="UTF-8">
+        <meta name="viewport" content="width=device-width, initial-scale=1.0">
+        <title>Newsletter Archive</title>
+      </head>
+      <body>
+        <h1>Newsletter archive</h1>
+        <ul>
+          {issues_html}
+        </ul>
+      </body>
+    </html>"#
+        )))
+}
+
+pub async fn archived_issue(
+    issue_id: web::Path<Uuid>,
+    db_pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let issue = get_archived_issue(issue_id.into_inner(), &db_pool)
+        .await
+        .map_err(e500)?
+        .ok_or_else(|| e404("Newsletter issue not found."))?;
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+    <html lang="en">
+      <head>
+        <meta charset="UTF-8">
+        <meta name="viewport" content="width=device-width, initial-scale=1.0">
+        <title>{title}</title>
+      </head>
+      <body>
+        <h1>{title}</h1>
+        <p>Published on {published_at}</p>
+        {html_content}
+        <a href="/archive">&lt; - All issues</a>
+      </body>
+    </html>"#,
+            title = encode_minimal(&issue.title),
+            published_at = issue.published_at.format("%Y-%m-%d"),
+            html_content = issue.html_content,
+        )))
+}
+
+#[tracing::instrument(name = "Get archived newsletter issues", skip(db_pool))]
+async fn get_archived_issues(db_pool: &PgPool) -> Result<Vec<ArchivedIssue>, anyhow::Error> {
+    let issues = sqlx::query_as!(
+        ArchivedIssue,
+        r#"
+        SELECT id, title, html_content, published_at AS "published_at!"
+        FROM newsletter_issues
+        WHERE
+            status = 'published' AND
+            published_at <= now()
+        ORDER BY published_at DESC
+    "#
+    )
+    .fetch_all(db_pool)
+    .await
+    .context("Failed to fetch archived newsletter issues.")?;
+
+    Ok(issues)
+}
+
+#[tracing::instrument(name = "Get an archived newsletter issue", skip(db_pool))]
+async fn get_archived_issue(
+    issue_id: Uuid,
+    db_pool: &PgPool,
+) -> Result<Option<ArchivedIssue>, anyhow::Error> {
+    let issue = sqlx::query_as!(
+        ArchivedIssue,
+        r#"
+        SELECT id, title, html_content, published_at AS "published_at!"
+        FROM newsletter_issues
+        WHERE
+            id = $1 AND
+            status = 'published' AND
+            published_at <= now()
+    "#,
+        issue_id
+    )
+    .fetch_optional(db_pool)
+    .await
+    .context("Failed to fetch archived newsletter issue.")?;
+
+    Ok(issue)
+}
@@ -26,6 +26,7 @@ pub async fn login_form(flash_messages: IncomingFlashMessages) -> HttpResponse {
                 <input type="password" placeholder="Password" name="password">
                 <input type="submit" value="Login">
             </form>
+            <a href="/login/forgot">Forgot your password?</a>
         </body>
         </html>"#,
             error_html,
@@ -0,0 +1,261 @@
+use actix_web::{http::header::ContentType, web, HttpRequest, HttpResponse};
+use actix_web_flash_messages::{FlashMessage, IncomingFlashMessages};
+use htmlescape::encode_minimal;
+use secrecy::{ExposeSecret, Secret};
+use sqlx::PgPool;
+use std::fmt::Write;
+use tracing::Instrument;
+
+use crate::{
+    audit_log::{record_audit_event, AuditAction},
+    authentication::{
+        clear_failed_logins, client_ip, create_password_reset_token, find_user_id,
+        is_valid_password_length, is_valid_password_reset_token, reset_password_with_token,
+    },
+    configuration::LoginThrottlingSettings,
+    domains::SubscriberEmail,
+    email_client::EmailClient,
+    startup::ApplicationBaseUrl,
+    utils::{e500, see_other},
+};
+
+const INVALID_LINK_MESSAGE: &str = "This password reset link is invalid or has expired.";
+
+#[derive(serde::Deserialize)]
+pub struct ForgotPasswordFormData {
+    pub email: String,
+}
+
+#[derive(serde::Deserialize)]
+pub struct ResetPasswordQuery {
+    pub token: Secret<String>,
+}
+
+#[derive(serde::Deserialize)]
+pub struct ResetPasswordFormData {
+    pub token: Secret<String>,
+    pub new_password: Secret<String>,
+    pub new_password_check: Secret<String>,
+}
+
+pub async fn forgot_password_form(flash_messages: IncomingFlashMessages) -> HttpResponse {
+    let mut message_html = String::new();
+    for m in flash_messages.iter() {
+        writeln!(message_html, "<p><i>{}</i></p>", m.content()).unwrap();
+    }
+
+    HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+        <html lang="en">
+        <head>
+            <meta charset="UTF-8">
+            <meta name="viewport" content="width=device-width, initial-scale=1.0">
+            <title>Forgot your password?</title>
+        </head>
+        <body>
+            {message_html}
+            <p>Enter the email address of your account, we'll send you a link to reset your password.</p>
+            <form action="/login/forgot" method="post">
+                <input type="email" placeholder="Email address" name="email">
+                <input type="submit" value="Send">
+            </form>
+            <a href="/login">&lt; - Back to login</a>
+        </body>
+        </html>"#
+        ))
+}
+
+/// Says the same thing whether the email address belongs to a user or not,
+/// so that it can't be used to find out who has an account. The email is sent
+/// in the background, for the answer not to take longer when it does.
+#[tracing::instrument(
+    name = "Send a password reset link",
+    skip(form_data, db_pool, email_client, base_url)
+)]
+pub async fn forgot_password(
+    form_data: web::Form<ForgotPasswordFormData>,
+    db_pool: web::Data<PgPool>,
+    email_client: web::Data<EmailClient>,
+    base_url: web::Data<ApplicationBaseUrl>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let email = match SubscriberEmail::parse(form_data.0.email) {
+        Ok(email) => email,
+        Err(e) => {
+            FlashMessage::error(encode_minimal(&e)).send();
+            return Ok(see_other("/login/forgot"));
+        }
+    };
+
+    if let Some(reset_token) = create_password_reset_token(&email, &db_pool)
+        .await
+        .map_err(e500)?
+    {
+        tokio::spawn(
+            async move {
+                if let Err(e) = send_password_reset_email(
+                    &email,
+                    &reset_token.username,
+                    reset_token.token.expose_secret(),
+                    &email_client,
+                    &base_url.0,
+                )
+                .await
+                {
+                    tracing::error!(
+                        error.cause_chain = ?e,
+                        error.message = %e,
+                        "Failed to send a password reset email."
+                    );
+                }
+            }
+            .instrument(tracing::Span::current()),
+        );
+    }
+
+    FlashMessage::info(
+        "If an account uses this email address, a link to reset its password has been sent to it.",
+    )
+    .send();
+    Ok(see_other("/login/forgot"))
+}
+
+async fn send_password_reset_email(
+    email: &SubscriberEmail,
+    username: &str,
+    token: &str,
+    email_client: &EmailClient,
+    base_url: &str,
+) -> Result<(), anyhow::Error> {
+    let reset_link = format!("{}/login/reset?token={}", base_url, token);
+    let html_body = format!(
+        "Hi {},<br />\
+    Click <a href=\"{}\">here</a> to reset your password, the link is valid for an hour.<br />\
+    If you didn't ask for it, you can ignore this email.",
+        encode_minimal(username),
+        reset_link
+    );
+    let text_body = format!(
+        "Hi {},\nVisit {} to reset your password, the link is valid for an hour.\n\
+    If you didn't ask for it, you can ignore this email.",
+        username, reset_link
+    );
+
+    email_client
+        .send_email(email, "Reset your password", &html_body, &text_body)
+        .await?;
+    Ok(())
+}
+
+#[tracing::instrument(
+    name = "Show the password reset form",
+    skip(query, db_pool, flash_messages)
+)]
+pub async fn reset_password_form(
+    query: web::Query<ResetPasswordQuery>,
+    db_pool: web::Data<PgPool>,
+    flash_messages: IncomingFlashMessages,
+) -> Result<HttpResponse, actix_web::Error> {
+    let token = query.0.token;
+    let body = if is_valid_password_reset_token(token.expose_secret(), &db_pool)
+        .await
+        .map_err(e500)?
+    {
+        let mut message_html = String::new();
+        for m in flash_messages.iter() {
+            writeln!(message_html, "<p><i>{}</i></p>", m.content()).unwrap();
+        }
+
+        format!(
+            r#"{message_html}
+            <form action="/login/reset" method="post">
+                <input type="hidden" name="token" value="{token}">
+                <input type="password" placeholder="Enter new password" name="new_password">
+                <input type="password" placeholder="Enter new password again" name="new_password_check">
+                <input type="submit" value="Reset password">
+            </form>"#,
+            token = encode_minimal(token.expose_secret()),
+        )
+    } else {
+        format!(
+            r#"<p>{}</p>
+            <a href="/login/forgot">Send a new link</a>"#,
+            INVALID_LINK_MESSAGE
+        )
+    };
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+        <html lang="en">
+        <head>
+            <meta charset="UTF-8">
+            <meta name="viewport" content="width=device-width, initial-scale=1.0">
+            <title>Reset your password</title>
+        </head>
+        <body>
+            {body}
+        </body>
+        </html>"#
+        )))
+}
+
+#[tracing::instrument(
+    name = "Reset a forgotten password",
+    skip(form_data, request, db_pool, throttling)
+)]
+pub async fn reset_forgotten_password(
+    form_data: web::Form<ResetPasswordFormData>,
+    request: HttpRequest,
+    db_pool: web::Data<PgPool>,
+    throttling: web::Data<LoginThrottlingSettings>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let ResetPasswordFormData {
+        token,
+        new_password,
+        new_password_check,
+    } = form_data.0;
+    let reset_form = format!(
+        "/login/reset?token={}",
+        urlencoding::encode(token.expose_secret())
+    );
+
+    if new_password.expose_secret() != new_password_check.expose_secret() {
+        FlashMessage::error("New password must match").send();
+        return Ok(see_other(&reset_form));
+    }
+    if !is_valid_password_length(&new_password) {
+        FlashMessage::error("New password must be between 12 and 128 characters long.").send();
+        return Ok(see_other(&reset_form));
+    }
+
+    match reset_password_with_token(&token, new_password, &db_pool)
+        .await
+        .map_err(e500)?
+    {
+        Some(username) => {
+            // Whoever got locked out by forgetting their password can log in right away
+            clear_failed_logins(&username, &db_pool)
+                .await
+                .map_err(e500)?;
+            let user_id = find_user_id(&username, &db_pool).await.map_err(e500)?;
+            record_audit_event(
+                db_pool.get_ref(),
+                user_id,
+                AuditAction::ResetForgottenPassword,
+                None,
+                &client_ip(&request, &throttling),
+            )
+            .await
+            .map_err(e500)?;
+            FlashMessage::info("Your password has been reset, you can log in with it.").send();
+            Ok(see_other("/login"))
+        }
+        None => {
+            FlashMessage::error(INVALID_LINK_MESSAGE).send();
+            Ok(see_other("/login/forgot"))
+        }
+    }
+}
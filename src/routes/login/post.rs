@@ -1,10 +1,18 @@
-use actix_web::{error::InternalError, http::header, web, HttpResponse};
+use std::time::Duration;
+
+use actix_web::{error::InternalError, http::header, web, HttpRequest, HttpResponse};
 use actix_web_flash_messages::FlashMessage;
 use secrecy::Secret;
 use sqlx::PgPool;
+use uuid::Uuid;
 
 use crate::{
-    authentication::{validate_credentials, AuthError, UserCredentials},
+    audit_log::{record_audit_event, AuditAction},
+    authentication::{
+        clear_failed_logins, client_ip, get_login_lockout, get_totp_secret, record_failed_login,
+        start_user_session, validate_credentials, AuthError, FailedLoginReason, UserCredentials,
+    },
+    configuration::LoginThrottlingSettings,
     routes::error_chain_fmt,
     session_state::TypedSession,
 };
@@ -13,6 +21,11 @@ use crate::{
 pub enum LoginError {
     #[error("Invalid credentials.")]
     AuthError(#[source] anyhow::Error),
+    #[error(
+        "Too many failed login attempts, please try again in {}.",
+        describe_wait(.0)
+    )]
+    LockedOut(Duration),
     #[error("Something went wrong.")]
     UnexpectedError(#[from] anyhow::Error),
 }
@@ -32,40 +45,91 @@ pub struct LoginFormData {
 }
 
 #[tracing::instrument(
-    skip(form_data, db_pool, session),
-    fields(username=tracing::field::Empty, user_id=tracing::field::Empty)
+    skip(form_data, request, db_pool, throttling, session),
+    fields(
+        username=tracing::field::Empty,
+        user_id=tracing::field::Empty,
+        client_ip=tracing::field::Empty
+    )
 )]
 pub async fn login(
     form_data: web::Form<LoginFormData>,
+    request: HttpRequest,
     db_pool: web::Data<PgPool>,
+    throttling: web::Data<LoginThrottlingSettings>,
     session: TypedSession,
 ) -> Result<HttpResponse, InternalError<LoginError>> {
     let user_credentials = UserCredentials {
         username: form_data.0.username,
         password: Secret::new(form_data.0.password),
     };
+    let username = user_credentials.username.clone();
+    let client_ip = client_ip(&request, &throttling);
+
+    tracing::Span::current().record("username", &tracing::field::display(&username));
+    tracing::Span::current().record("client_ip", &tracing::field::display(&client_ip));
 
-    tracing::Span::current().record(
-        "username",
-        &tracing::field::display(&user_credentials.username),
-    );
+    let unexpected = |e: anyhow::Error| login_redirect(LoginError::UnexpectedError(e));
+
+    if let Some(remaining) = get_login_lockout(&username, &client_ip, &db_pool)
+        .await
+        .map_err(unexpected)?
+    {
+        record_failed_login(
+            &username,
+            &client_ip,
+            FailedLoginReason::LockedOut,
+            &throttling,
+            &db_pool,
+        )
+        .await
+        .map_err(unexpected)?;
+        return Err(login_redirect(LoginError::LockedOut(remaining)));
+    }
 
     match validate_credentials(user_credentials, &db_pool).await {
         Ok(user_id) => {
             tracing::Span::current().record("user_id", &tracing::field::display(user_id));
 
-            session.renew();
-            session
-                .set_user_id(user_id)
-                .map_err(|e| login_redirect(LoginError::UnexpectedError(e.into())))?;
+            let has_two_factor = get_totp_secret(user_id, &db_pool)
+                .await
+                .map_err(unexpected)?
+                .is_some();
+
+            let destination = if has_two_factor {
+                session.renew();
+                session
+                    .set_pending_2fa_user_id(user_id)
+                    .map_err(|e| login_redirect(LoginError::UnexpectedError(e.into())))?;
+                "/login/2fa"
+            } else {
+                clear_failed_logins(&username, &db_pool)
+                    .await
+                    .map_err(unexpected)?;
+                log_in(user_id, &session, &request, &client_ip, &db_pool)
+                    .await
+                    .map_err(unexpected)?;
+                "/admin/dashboard"
+            };
 
             Ok(HttpResponse::SeeOther()
-                .insert_header((header::LOCATION, "/admin/dashboard"))
+                .insert_header((header::LOCATION, destination))
                 .finish())
         }
         Err(e) => {
             let e = match e {
-                AuthError::InvalidCredentials(_) => LoginError::AuthError(e.into()),
+                AuthError::InvalidCredentials(_) => {
+                    record_failed_login(
+                        &username,
+                        &client_ip,
+                        FailedLoginReason::InvalidCredentials,
+                        &throttling,
+                        &db_pool,
+                    )
+                    .await
+                    .map_err(unexpected)?;
+                    LoginError::AuthError(e.into())
+                }
                 AuthError::UnexpectedError(_) => LoginError::UnexpectedError(e.into()),
             };
 
@@ -74,6 +138,28 @@ pub async fn login(
     }
 }
 
+/// Records the new session, so that it can be listed and revoked from `/admin/sessions`,
+/// and the login in the audit log.
+pub(super) async fn log_in(
+    user_id: Uuid,
+    session: &TypedSession,
+    request: &HttpRequest,
+    client_ip: &str,
+    db_pool: &PgPool,
+) -> Result<(), anyhow::Error> {
+    let user_agent = request
+        .headers()
+        .get(header::USER_AGENT)
+        .and_then(|user_agent| user_agent.to_str().ok())
+        .unwrap_or("unknown");
+    let session_id = start_user_session(user_id, client_ip, user_agent, db_pool).await?;
+    record_audit_event(db_pool, user_id, AuditAction::LogIn, None, client_ip).await?;
+
+    session.renew();
+    session.set_user_id(user_id, session_id)?;
+    Ok(())
+}
+
 /// Redirect to login page with error message
 fn login_redirect(e: LoginError) -> InternalError<LoginError> {
     FlashMessage::error(e.to_string()).send();
@@ -82,3 +168,31 @@ fn login_redirect(e: LoginError) -> InternalError<LoginError> {
         .finish();
     InternalError::from_response(e, response)
 }
+
+/// Rounded up, so that the user doesn't come back too early.
+pub fn describe_wait(wait: &Duration) -> String {
+    let seconds = wait.as_secs() + u64::from(wait.subsec_nanos() > 0);
+    match seconds {
+        0..=1 => "1 second".into(),
+        2..=59 => format!("{} seconds", seconds),
+        60 => "1 minute".into(),
+        _ => format!("{} minutes", seconds.div_ceil(60)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::describe_wait;
+    use std::time::Duration;
+
+    #[test]
+    fn waits_are_rounded_up() {
+        assert_eq!(describe_wait(&Duration::from_millis(300)), "1 second");
+        assert_eq!(describe_wait(&Duration::from_millis(1001)), "2 seconds");
+        assert_eq!(describe_wait(&Duration::from_secs(59)), "59 seconds");
+        assert_eq!(describe_wait(&Duration::from_secs(60)), "1 minute");
+        assert_eq!(describe_wait(&Duration::from_secs(61)), "2 minutes");
+        assert_eq!(describe_wait(&Duration::from_secs(121)), "3 minutes");
+        assert_eq!(describe_wait(&Duration::from_secs(900)), "15 minutes");
+    }
+}
@@ -0,0 +1,133 @@
+use actix_web::{http::header::ContentType, web, HttpRequest, HttpResponse};
+use actix_web_flash_messages::{FlashMessage, IncomingFlashMessages};
+use sqlx::PgPool;
+use std::fmt::Write;
+
+use super::post::{describe_wait, log_in};
+use crate::{
+    authentication::{
+        clear_failed_logins, client_ip, get_login_lockout, record_failed_login,
+        verify_second_factor, FailedLoginReason,
+    },
+    configuration::LoginThrottlingSettings,
+    routes::get_username,
+    session_state::TypedSession,
+    utils::{e500, see_other},
+};
+
+/// Past this many invalid codes, the password has to be entered again.
+const MAX_FAILED_ATTEMPTS: u32 = 3;
+
+#[derive(serde::Deserialize)]
+pub struct TwoFactorFormData {
+    pub code: String,
+}
+
+pub async fn two_factor_form(
+    session: TypedSession,
+    flash_messages: IncomingFlashMessages,
+) -> Result<HttpResponse, actix_web::Error> {
+    if session.get_pending_2fa_user_id().map_err(e500)?.is_none() {
+        return Ok(see_other("/login"));
+    }
+
+    let mut error_html = String::new();
+    for m in flash_messages.iter() {
+        writeln!(error_html, "<p><i>{}</i></p>", m.content()).unwrap();
+    }
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+        <html lang="en">
+        <head>
+            <meta charset="UTF-8">
+            <meta name="viewport" content="width=device-width, initial-scale=1.0">
+            <title>Two-factor authentication</title>
+        </head>
+        <body>
+            {error_html}
+            <p>Enter the code from your authenticator app, or one of your recovery codes.</p>
+            <form action="/login/2fa" method="post">
+                <input type="text" placeholder="Code" name="code" autocomplete="one-time-code">
+                <input type="submit" value="Verify">
+            </form>
+        </body>
+        </html>"#
+        )))
+}
+
+/// Invalid codes count as failed logins, and lockouts apply here as well.
+#[tracing::instrument(
+    name = "Verify the second factor of a login",
+    skip(form_data, request, db_pool, throttling, session),
+    fields(user_id = tracing::field::Empty, client_ip = tracing::field::Empty)
+)]
+pub async fn verify_two_factor(
+    form_data: web::Form<TwoFactorFormData>,
+    request: HttpRequest,
+    db_pool: web::Data<PgPool>,
+    throttling: web::Data<LoginThrottlingSettings>,
+    session: TypedSession,
+) -> Result<HttpResponse, actix_web::Error> {
+    let user_id = match session.get_pending_2fa_user_id().map_err(e500)? {
+        Some(user_id) => user_id,
+        None => return Ok(see_other("/login")),
+    };
+    let client_ip = client_ip(&request, &throttling);
+    tracing::Span::current().record("user_id", &tracing::field::display(user_id));
+    tracing::Span::current().record("client_ip", &tracing::field::display(&client_ip));
+    let username = get_username(user_id, &db_pool).await.map_err(e500)?;
+
+    if let Some(remaining) = get_login_lockout(&username, &client_ip, &db_pool)
+        .await
+        .map_err(e500)?
+    {
+        record_failed_login(
+            &username,
+            &client_ip,
+            FailedLoginReason::LockedOut,
+            &throttling,
+            &db_pool,
+        )
+        .await
+        .map_err(e500)?;
+        FlashMessage::error(format!(
+            "Too many failed login attempts, please try again in {}.",
+            describe_wait(&remaining)
+        ))
+        .send();
+        return Ok(see_other("/login/2fa"));
+    }
+
+    if !verify_second_factor(user_id, &form_data.code, &db_pool)
+        .await
+        .map_err(e500)?
+    {
+        record_failed_login(
+            &username,
+            &client_ip,
+            FailedLoginReason::InvalidTwoFactorCode,
+            &throttling,
+            &db_pool,
+        )
+        .await
+        .map_err(e500)?;
+        if session.record_failed_2fa_attempt().map_err(e500)? >= MAX_FAILED_ATTEMPTS {
+            session.log_out();
+            FlashMessage::error("Too many invalid codes, please log in again.").send();
+            return Ok(see_other("/login"));
+        }
+        FlashMessage::error("Invalid code.").send();
+        return Ok(see_other("/login/2fa"));
+    }
+
+    clear_failed_logins(&username, &db_pool)
+        .await
+        .map_err(e500)?;
+    log_in(user_id, &session, &request, &client_ip, &db_pool)
+        .await
+        .map_err(e500)?;
+    Ok(see_other("/admin/dashboard"))
+}
@@ -1,13 +1,18 @@
 mod admin;
+mod archive;
 mod health_check;
 mod home;
 mod login;
 mod subscriptions;
 mod subscriptions_confirm;
+mod subscriptions_outcome;
+mod subscriptions_unsubscribe;
 
 pub use admin::*;
+pub use archive::*;
 pub use health_check::*;
 pub use home::*;
 pub use login::*;
 pub use subscriptions::*;
 pub use subscriptions_confirm::*;
+pub use subscriptions_unsubscribe::*;
@@ -1,13 +1,15 @@
-use actix_web::{web, HttpResponse, ResponseError, Result};
+use actix_web::{error::InternalError, web, HttpRequest, HttpResponse, ResponseError, Result};
 use anyhow::Context;
 use chrono::Utc;
 use rand::{distributions::Alphanumeric, thread_rng, Rng};
 use sqlx::{PgPool, Postgres, Transaction};
 use uuid::Uuid;
 
+use super::subscriptions_outcome::{ResponseFormat, SubscriptionOutcome};
 use crate::{
+    configuration::SubscriptionSettings,
     domains::{NewSubscriber, SubscriberEmail, SubscriberName},
-    email_client::EmailClient,
+    email_client::{EmailClient, EmailError},
     startup::ApplicationBaseUrl,
 };
 
@@ -27,51 +29,105 @@ impl TryFrom<SubscriptionData> for NewSubscriber {
     }
 }
 
+/// Subscribing again is fine: pending subscribers get their confirmation email again,
+/// with a new link once theirs has expired, unsubscribed ones get a new one to opt back in.
+/// Confirmed subscribers get the same answer as everyone else, so that it doesn't tell
+/// who is subscribed, and an email telling them there is nothing to do: sending it takes
+/// as long as sending a confirmation email, and keeps the response time from telling either.
 #[tracing::instrument(
     name = "Adding a new subscriber",
-    skip(data, db_pool, email_client, base_url),
+    skip(request, data, db_pool, email_client, base_url, settings),
     fields(
         subscriber_email = %data.email,
         subscriber_name = %data.name
     )
 )]
 pub async fn subscribe(
+    request: HttpRequest,
     data: web::Form<SubscriptionData>,
     db_pool: web::Data<PgPool>,
     email_client: web::Data<EmailClient>,
     base_url: web::Data<ApplicationBaseUrl>,
-) -> Result<HttpResponse, SubscribeError> {
-    let new_subscriber = data.0.try_into().map_err(SubscribeError::ValidationError)?;
+    settings: web::Data<SubscriptionSettings>,
+) -> Result<HttpResponse, InternalError<SubscribeError>> {
+    let format = ResponseFormat::of(&request);
+    let (name, email) = (data.name.clone(), data.email.clone());
+
+    match add_subscriber(data.0, &db_pool, &email_client, &base_url.0, &settings).await {
+        Ok(()) => Ok(SubscriptionOutcome::PendingConfirmation.response(format)),
+        Err(e) => {
+            let outcome = match &e {
+                SubscribeError::ValidationError(error) => SubscriptionOutcome::InvalidData {
+                    error: error.clone(),
+                    name,
+                    email,
+                },
+                SubscribeError::UnexpectedError(_) => SubscriptionOutcome::UnexpectedError,
+            };
+            Err(InternalError::from_response(e, outcome.response(format)))
+        }
+    }
+}
+
+async fn add_subscriber(
+    data: SubscriptionData,
+    db_pool: &PgPool,
+    email_client: &EmailClient,
+    base_url: &str,
+    settings: &SubscriptionSettings,
+) -> Result<(), SubscribeError> {
+    let new_subscriber = data.try_into().map_err(SubscribeError::ValidationError)?;
 
     let mut transaction = db_pool
         .begin()
         .await
         .context("Failed to acquire connection from DB pool.")?;
 
-    let new_subscriber_s_id = insert_subscriber(&mut transaction, &new_subscriber)
+    let (subscriber_id, status) = insert_or_lock_subscriber(&mut transaction, &new_subscriber)
         .await
         .context("Failed to insert new subscriber.")?;
-
-    let subscription_token = generate_subscription_token();
-    insert_subscription_token(&mut transaction, new_subscriber_s_id, &subscription_token)
+    let subscription_token = if status == "confirmed" {
+        None
+    } else {
+        match get_subscription_token(
+            &mut transaction,
+            subscriber_id,
+            settings.confirmation_token_ttl(),
+        )
         .await
-        .context("Failed to insert subscription token.")?;
+        .context("Failed to fetch the subscription token.")?
+        {
+            Some(subscription_token) => Some(subscription_token),
+            None => {
+                let subscription_token = generate_subscription_token();
+                insert_subscription_token(&mut transaction, subscriber_id, &subscription_token)
+                    .await
+                    .context("Failed to insert subscription token.")?;
+                Some(subscription_token)
+            }
+        }
+    };
 
     transaction
         .commit()
         .await
         .context("Failed to commit SQL transaction for saving new subscriber.")?;
 
-    send_confirmation_email(
-        new_subscriber,
-        &email_client,
-        &base_url.0,
-        &subscription_token,
-    )
-    .await
-    .context("Failed to send confirmation email.")?;
+    match subscription_token {
+        Some(subscription_token) => {
+            send_confirmation_email(new_subscriber, email_client, base_url, &subscription_token)
+                .await
+                .context("Failed to send confirmation email.")?;
+        }
+        None => {
+            tracing::info!("The subscriber has already confirmed their subscription.");
+            send_already_subscribed_email(new_subscriber, email_client)
+                .await
+                .context("Failed to send the already subscribed email.")?;
+        }
+    }
 
-    Ok(HttpResponse::Ok().finish())
+    Ok(())
 }
 
 pub fn generate_subscription_token() -> String {
@@ -94,7 +150,8 @@ pub async fn insert_subscription_token(
 ) -> Result<(), InsertTokenError> {
     sqlx::query!(
         r#"
-        INSERT INTO subscription_tokens (subscriber_id, subscription_token) VALUES ($1, $2)
+        INSERT INTO subscription_tokens (subscriber_id, subscription_token, created_at)
+        VALUES ($1, $2, now())
     "#,
         subscriber_id,
         subscription_token
@@ -109,33 +166,79 @@ pub async fn insert_subscription_token(
     Ok(())
 }
 
+/// Returns the id and the status of the subscriber, who is saved as pending if they are new.
+/// They are locked until the transaction ends, to handle concurrent subscriptions one at a time.
 #[tracing::instrument(
     name = "Saving new subscriber details to the db",
     skip(transaction, new_subscriber)
 )]
-pub async fn insert_subscriber(
+pub async fn insert_or_lock_subscriber(
     transaction: &mut Transaction<'_, Postgres>,
     new_subscriber: &NewSubscriber,
-) -> Result<Uuid, sqlx::Error> {
-    let subscriber_id = Uuid::new_v4();
+) -> Result<(Uuid, String), sqlx::Error> {
     sqlx::query!(
         r#"
     INSERT INTO subscriptions (id, email, name, subscribed_at, status)
     VALUES ($1, $2, $3, $4, 'pending_confirmation')
+    ON CONFLICT (email) DO NOTHING
     "#,
-        subscriber_id,
+        Uuid::new_v4(),
         new_subscriber.email.as_ref(),
         new_subscriber.name.as_ref(),
         Utc::now()
     )
-    .execute(transaction)
+    .execute(&mut *transaction)
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to execute query {:?}", e);
+        e
+    })?;
+
+    let subscriber = sqlx::query!(
+        "SELECT id, status FROM subscriptions WHERE email = $1 FOR UPDATE",
+        new_subscriber.email.as_ref()
+    )
+    .fetch_one(transaction)
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to execute query {:?}", e);
+        e
+    })?;
+
+    Ok((subscriber.id, subscriber.status))
+}
+
+/// Unsubscribing deletes the tokens, so this is only ever the token of a pending confirmation.
+/// Tokens that are older than `ttl`, or have been used, are left out.
+#[tracing::instrument(
+    name = "Get the subscription token of a subscriber",
+    skip(transaction, subscriber_id)
+)]
+async fn get_subscription_token(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+    ttl: std::time::Duration,
+) -> Result<Option<String>, sqlx::Error> {
+    let row = sqlx::query!(
+        r#"
+        SELECT subscription_token FROM subscription_tokens
+        WHERE subscriber_id = $1
+            AND consumed_at IS NULL
+            AND created_at > now() - make_interval(secs => $2)
+        ORDER BY created_at DESC
+        LIMIT 1
+    "#,
+        subscriber_id,
+        ttl.as_secs_f64()
+    )
+    .fetch_optional(transaction)
     .await
     .map_err(|e| {
         tracing::error!("Failed to execute query {:?}", e);
         e
     })?;
 
-    Ok(subscriber_id)
+    Ok(row.map(|r| r.subscription_token))
 }
 
 #[tracing::instrument(
@@ -147,7 +250,7 @@ async fn send_confirmation_email(
     email_client: &EmailClient,
     base_url: &str,
     subscription_token: &str,
-) -> Result<(), reqwest::Error> {
+) -> Result<(), EmailError> {
     let confirmation_link = format!(
         "{}/subscriptions/confirm?subscription_token={}",
         base_url, subscription_token
@@ -167,6 +270,30 @@ async fn send_confirmation_email(
         .await
 }
 
+#[tracing::instrument(
+    name = "Tell a confirmed subscriber they are already subscribed",
+    skip(new_subscriber, email_client)
+)]
+async fn send_already_subscribed_email(
+    new_subscriber: NewSubscriber,
+    email_client: &EmailClient,
+) -> Result<(), EmailError> {
+    let html_body =
+        "You are already subscribed to our newsletter, there is nothing else to do.<br />\
+    If you didn't ask to subscribe again, you can ignore this email.";
+    let text_body = "You are already subscribed to our newsletter, there is nothing else to do.\n\
+    If you didn't ask to subscribe again, you can ignore this email.";
+
+    email_client
+        .send_email(
+            &new_subscriber.email,
+            "You are already subscribed",
+            html_body,
+            text_body,
+        )
+        .await
+}
+
 pub struct InsertTokenError(sqlx::Error);
 
 impl std::fmt::Debug for InsertTokenError {
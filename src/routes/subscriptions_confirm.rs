@@ -1,66 +1,152 @@
-use actix_web::{web, HttpResponse};
-use sqlx::PgPool;
+use actix_web::{error::InternalError, web, HttpRequest, HttpResponse};
+use anyhow::Context;
+use sqlx::{PgPool, Postgres, Transaction};
 use uuid::Uuid;
 
+use super::subscriptions_outcome::{ResponseFormat, SubscriptionOutcome};
+use crate::configuration::SubscriptionSettings;
+
 #[derive(Debug, serde::Deserialize)]
 pub struct QueryParam {
     pub subscription_token: String,
 }
 
-#[tracing::instrument(name = "Confirm a pending subscription", skip(param))]
-pub async fn confirm(param: web::Query<QueryParam>, db_pool: web::Data<PgPool>) -> HttpResponse {
-    let subscriber_id =
-        match get_subscriber_id_from_token(&db_pool, &param.subscription_token).await {
-            Ok(value) => value,
-            Err(_) => return HttpResponse::InternalServerError().finish(),
-        };
+/// Tokens are single-use: once one has confirmed the subscription,
+/// following it again only tells that the subscription is confirmed.
+#[tracing::instrument(
+    name = "Confirm a pending subscription",
+    skip(request, param, db_pool, settings)
+)]
+pub async fn confirm(
+    request: HttpRequest,
+    param: web::Query<QueryParam>,
+    db_pool: web::Data<PgPool>,
+    settings: web::Data<SubscriptionSettings>,
+) -> Result<HttpResponse, InternalError<anyhow::Error>> {
+    let format = ResponseFormat::of(&request);
 
-    match subscriber_id {
-        None => HttpResponse::Unauthorized().finish(),
-        Some(id) => match confirm_user_subscription(&db_pool, id).await {
-            Err(_) => HttpResponse::InternalServerError().finish(),
-            Ok(_) => HttpResponse::Ok().finish(),
-        },
+    match confirm_subscription(&param.subscription_token, &db_pool, &settings).await {
+        Ok(outcome) => Ok(outcome.response(format)),
+        Err(e) => Err(InternalError::from_response(
+            e,
+            SubscriptionOutcome::UnexpectedError.response(format),
+        )),
     }
 }
 
-#[tracing::instrument(name = "Confirm user subscription", skip(db_pool, subscriber_id))]
-async fn confirm_user_subscription(
+async fn confirm_subscription(
+    subscription_token: &str,
     db_pool: &PgPool,
-    subscriber_id: Uuid,
-) -> Result<(), sqlx::Error> {
-    sqlx::query!(
-        r#"UPDATE subscriptions SET status = 'confirmed' WHERE id = $1"#,
-        subscriber_id
+    settings: &SubscriptionSettings,
+) -> Result<SubscriptionOutcome, anyhow::Error> {
+    let mut transaction = db_pool
+        .begin()
+        .await
+        .context("Failed to acquire connection from DB pool.")?;
+
+    let token = get_and_lock_subscription_token(
+        &mut transaction,
+        subscription_token,
+        settings.confirmation_token_ttl(),
     )
-    .execute(db_pool)
-    .await
-    .map_err(|e| {
-        tracing::error!("Failed to execute query {:?}", e);
-        e
-    })?;
+    .await?;
 
-    Ok(())
+    let outcome = match token {
+        None => SubscriptionOutcome::InvalidLink,
+        Some(token) if token.is_consumed => {
+            if token.status == "confirmed" {
+                SubscriptionOutcome::AlreadyConfirmed
+            } else {
+                SubscriptionOutcome::InvalidLink
+            }
+        }
+        Some(token) if token.is_expired => SubscriptionOutcome::ExpiredLink {
+            name: token.name,
+            email: token.email,
+        },
+        Some(token) => {
+            confirm_user_subscription(&mut transaction, token.subscriber_id).await?;
+            SubscriptionOutcome::Confirmed
+        }
+    };
+
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit the confirmation of the subscription.")?;
+    Ok(outcome)
+}
+
+struct SubscriptionToken {
+    subscriber_id: Uuid,
+    name: String,
+    email: String,
+    status: String,
+    is_consumed: bool,
+    is_expired: bool,
 }
 
+/// The token is locked until the transaction ends, concurrent confirmations
+/// with the same link wait for each other and only the first one confirms.
 #[tracing::instrument(
-    name = "Get subscriber ID from subscription token",
-    skip(db_pool, subscription_token)
+    name = "Get and lock a subscription token",
+    skip(transaction, subscription_token)
 )]
-async fn get_subscriber_id_from_token(
-    db_pool: &PgPool,
+async fn get_and_lock_subscription_token(
+    transaction: &mut Transaction<'_, Postgres>,
     subscription_token: &str,
-) -> Result<Option<Uuid>, sqlx::Error> {
-    let result = sqlx::query!(
-        r#"SELECT subscriber_id FROM subscription_tokens WHERE subscription_token = $1"#,
-        subscription_token
+    ttl: std::time::Duration,
+) -> Result<Option<SubscriptionToken>, anyhow::Error> {
+    let token = sqlx::query_as!(
+        SubscriptionToken,
+        r#"
+        SELECT
+            subscriptions.id AS subscriber_id,
+            subscriptions.name,
+            subscriptions.email,
+            subscriptions.status,
+            subscription_tokens.consumed_at IS NOT NULL AS "is_consumed!",
+            subscription_tokens.created_at <= now() - make_interval(secs => $2) AS "is_expired!"
+        FROM subscription_tokens
+        JOIN subscriptions ON subscriptions.id = subscription_tokens.subscriber_id
+        WHERE subscription_tokens.subscription_token = $1
+        FOR UPDATE OF subscription_tokens
+    "#,
+        subscription_token,
+        ttl.as_secs_f64()
     )
-    .fetch_optional(db_pool)
+    .fetch_optional(transaction)
     .await
-    .map_err(|e| {
-        tracing::error!("Failed to execute query {:?}", e);
-        e
-    })?;
+    .context("Failed to fetch the subscription token.")?;
+
+    Ok(token)
+}
 
-    Ok(result.map(|r| r.subscriber_id))
+/// The other links sent to the subscriber, if they asked for the email more than once,
+/// are used up as well.
+#[tracing::instrument(name = "Confirm user subscription", skip(transaction, subscriber_id))]
+async fn confirm_user_subscription(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+) -> Result<(), anyhow::Error> {
+    sqlx::query!(
+        r#"UPDATE subscriptions SET status = 'confirmed' WHERE id = $1"#,
+        subscriber_id
+    )
+    .execute(&mut *transaction)
+    .await
+    .context("Failed to confirm the subscription.")?;
+
+    sqlx::query!(
+        r#"
+        UPDATE subscription_tokens SET consumed_at = now()
+        WHERE subscriber_id = $1 AND consumed_at IS NULL
+    "#,
+        subscriber_id
+    )
+    .execute(transaction)
+    .await
+    .context("Failed to use up the subscription tokens of the subscriber.")?;
+
+    Ok(())
 }
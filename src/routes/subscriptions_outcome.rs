@@ -0,0 +1,208 @@
+use actix_web::{
+    http::header::{Accept, ContentType, Header},
+    http::StatusCode,
+    HttpRequest, HttpResponse,
+};
+use htmlescape::encode_minimal;
+
+/// Browsers get a page, API clients get a JSON body.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ResponseFormat {
+    Html,
+    Json,
+}
+
+impl ResponseFormat {
+    /// Browsers always ask for HTML, clients that accept anything are API clients.
+    pub fn of(request: &HttpRequest) -> Self {
+        let accept = match Accept::parse(request) {
+            Ok(accept) => accept,
+            Err(_) => return ResponseFormat::Json,
+        };
+
+        accept
+            .ranked()
+            .iter()
+            .find_map(|mime| match mime.essence_str() {
+                "text/html" | "text/*" => Some(ResponseFormat::Html),
+                "application/json" | "application/*" | "*/*" => Some(ResponseFormat::Json),
+                _ => None,
+            })
+            .unwrap_or(ResponseFormat::Json)
+    }
+}
+
+/// What subscribing, or following a confirmation link, came to.
+#[derive(Debug, PartialEq, Eq)]
+pub enum SubscriptionOutcome {
+    PendingConfirmation,
+    Confirmed,
+    AlreadyConfirmed,
+    InvalidLink,
+    ExpiredLink {
+        name: String,
+        email: String,
+    },
+    InvalidData {
+        error: String,
+        name: String,
+        email: String,
+    },
+    UnexpectedError,
+}
+
+impl SubscriptionOutcome {
+    pub fn status_code(&self) -> StatusCode {
+        match self {
+            SubscriptionOutcome::PendingConfirmation
+            | SubscriptionOutcome::Confirmed
+            | SubscriptionOutcome::AlreadyConfirmed => StatusCode::OK,
+            SubscriptionOutcome::InvalidLink => StatusCode::UNAUTHORIZED,
+            SubscriptionOutcome::ExpiredLink { .. } => StatusCode::GONE,
+            SubscriptionOutcome::InvalidData { .. } => StatusCode::BAD_REQUEST,
+            SubscriptionOutcome::UnexpectedError => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SubscriptionOutcome::PendingConfirmation => "pending_confirmation",
+            SubscriptionOutcome::Confirmed => "confirmed",
+            SubscriptionOutcome::AlreadyConfirmed => "already_confirmed",
+            SubscriptionOutcome::InvalidLink => "invalid_link",
+            SubscriptionOutcome::ExpiredLink { .. } => "expired_link",
+            SubscriptionOutcome::InvalidData { .. } => "invalid_data",
+            SubscriptionOutcome::UnexpectedError => "unexpected_error",
+        }
+    }
+
+    pub fn message(&self) -> &str {
+        match self {
+            SubscriptionOutcome::PendingConfirmation => {
+                "Thanks for subscribing! Follow the link we have emailed you to confirm your subscription."
+            }
+            SubscriptionOutcome::Confirmed => {
+                "Your subscription is confirmed, you will receive our next issues."
+            }
+            SubscriptionOutcome::AlreadyConfirmed => "Your subscription is already confirmed.",
+            SubscriptionOutcome::InvalidLink => {
+                "This confirmation link is invalid or has already been used."
+            }
+            SubscriptionOutcome::ExpiredLink { .. } => "This confirmation link has expired.",
+            SubscriptionOutcome::InvalidData { error, .. } => error,
+            SubscriptionOutcome::UnexpectedError => {
+                "Something went wrong on our side, please try again later."
+            }
+        }
+    }
+
+    /// The subscription form to show along the message, with its fields filled in.
+    fn form(&self) -> Option<(&str, &str, &str)> {
+        match self {
+            SubscriptionOutcome::InvalidLink => {
+                Some(("Do you want us to send you a new one?", "", ""))
+            }
+            SubscriptionOutcome::ExpiredLink { name, email } => {
+                Some(("Do you want us to send you a new one?", name, email))
+            }
+            SubscriptionOutcome::InvalidData { name, email, .. } => {
+                Some(("Please correct it and try again.", name, email))
+            }
+            _ => None,
+        }
+    }
+
+    /// The JSON body is `{"status": ..., "message": ...}`, `status` being one of the
+    /// `as_str` values.
+    pub fn response(&self, format: ResponseFormat) -> HttpResponse {
+        let mut response = HttpResponse::build(self.status_code());
+        match format {
+            ResponseFormat::Json => response.json(serde_json::json!({
+                "status": self.as_str(),
+                "message": self.message(),
+            })),
+            ResponseFormat::Html => response.content_type(ContentType::html()).body(self.page()),
+        }
+    }
+
+    fn page(&self) -> String {
+        let form_html = match self.form() {
+            Some((prompt, name, email)) => format!(
+                r#"<p>{prompt}</p>
+            <form action="/subscriptions" method="POST">
+                <input type="text" placeholder="Your name" name="name" value="{name}" />
+                <input type="email" placeholder="Your email" name="email" value="{email}" />
+                <input type="submit" value="Subscribe" />
+            </form>"#,
+                name = encode_minimal(name),
+                email = encode_minimal(email),
+            ),
+            None => String::new(),
+        };
+
+        format!(
+            r#"
+    <html>
+        <head>
+            <meta http-equiv="content-type" content="text/html; charset=utf-8">
+            <title>Newsletter subscription</title>
+        </head>
+        <body>
+            <p>{message}</p>
+            {form_html}
+        </body>
+    </html>"#,
+            message = encode_minimal(self.message()),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ResponseFormat, SubscriptionOutcome};
+    use actix_web::test::TestRequest;
+
+    fn format_for(accept: Option<&str>) -> ResponseFormat {
+        let request = match accept {
+            Some(accept) => TestRequest::default().insert_header(("Accept", accept)),
+            None => TestRequest::default(),
+        };
+        ResponseFormat::of(&request.to_http_request())
+    }
+
+    #[test]
+    fn browsers_get_html() {
+        assert_eq!(
+            format_for(Some(
+                "text/html,application/xhtml+xml,application/xml;q=0.9,*/*;q=0.8"
+            )),
+            ResponseFormat::Html
+        );
+    }
+
+    #[test]
+    fn api_clients_get_json() {
+        assert_eq!(format_for(None), ResponseFormat::Json);
+        assert_eq!(format_for(Some("*/*")), ResponseFormat::Json);
+        assert_eq!(format_for(Some("application/json")), ResponseFormat::Json);
+        assert_eq!(
+            format_for(Some("text/html;q=0.5, application/json")),
+            ResponseFormat::Json
+        );
+    }
+
+    #[test]
+    fn what_the_subscriber_typed_is_escaped() {
+        let outcome = SubscriptionOutcome::InvalidData {
+            error: "<b> is not a valid subscriber email.".into(),
+            name: "\"><script>".into(),
+            email: "<b>".into(),
+        };
+
+        let page = outcome.page();
+
+        assert!(!page.contains("<b>"));
+        assert!(!page.contains("<script>"));
+        assert!(page.contains("&lt;b&gt; is not a valid subscriber email."));
+    }
+}
@@ -0,0 +1,196 @@
+use actix_web::{http::header::ContentType, web, HttpResponse};
+use anyhow::Context;
+use sqlx::{PgPool, Postgres, Transaction};
+use uuid::Uuid;
+
+use crate::{
+    domains::UnsubscribeToken,
+    startup::HmacSecret,
+    utils::{e400, e401, e500},
+};
+
+#[derive(Debug, serde::Deserialize)]
+pub struct UnsubscribeQuery {
+    pub token: String,
+}
+
+/// Ask for a confirmation first: link scanners and prefetchers
+/// follow every link in an email, they shouldn't unsubscribe anybody.
+#[tracing::instrument(name = "Show the unsubscribe form", skip(query, hmac_secret))]
+pub async fn unsubscribe_form(
+    query: web::Query<UnsubscribeQuery>,
+    hmac_secret: web::Data<HmacSecret>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let token = UnsubscribeToken::parse(query.0.token, &hmac_secret.0).map_err(e401)?;
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"
+    <html>
+        <head></head>
+        <body>
+            <p>Do you want to stop receiving our newsletter?</p>
+            <form action="/subscriptions/unsubscribe?token={}" method="POST">
+                <input type="submit" value="Unsubscribe"/>
+            </form>
+        </body>
+    </html>"#,
+            token.as_ref()
+        )))
+}
+
+#[tracing::instrument(
+    name = "Unsubscribe a subscriber",
+    skip(query, db_pool, hmac_secret),
+    fields(subscriber_id = tracing::field::Empty)
+)]
+pub async fn unsubscribe(
+    query: web::Query<UnsubscribeQuery>,
+    db_pool: web::Data<PgPool>,
+    hmac_secret: web::Data<HmacSecret>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let token = UnsubscribeToken::parse(query.0.token, &hmac_secret.0).map_err(e401)?;
+    tracing::Span::current().record(
+        "subscriber_id",
+        &tracing::field::display(token.subscriber_id()),
+    );
+    unsubscribe_subscriber(&token, &db_pool).await?;
+
+    Ok(HttpResponse::Ok().content_type(ContentType::html()).body(
+        r#"
+    <html>
+        <head></head>
+        <body>
+            <p>You have been unsubscribed, you won't receive our newsletter anymore.</p>
+        </body>
+    </html>"#,
+    ))
+}
+
+#[derive(serde::Deserialize)]
+pub struct OneClickFormData {
+    #[serde(rename = "List-Unsubscribe")]
+    list_unsubscribe: String,
+}
+
+/// The target of the `List-Unsubscribe-Post` header (RFC 8058): mailbox providers
+/// POST `List-Unsubscribe=One-Click` to it, so there's nobody to show a confirmation page to.
+#[tracing::instrument(
+    name = "Unsubscribe a subscriber with one click",
+    skip(query, form_data, db_pool, hmac_secret),
+    fields(subscriber_id = tracing::field::Empty)
+)]
+pub async fn unsubscribe_one_click(
+    query: web::Query<UnsubscribeQuery>,
+    form_data: web::Form<OneClickFormData>,
+    db_pool: web::Data<PgPool>,
+    hmac_secret: web::Data<HmacSecret>,
+) -> Result<HttpResponse, actix_web::Error> {
+    if form_data.list_unsubscribe != "One-Click" {
+        return Err(e400("Expected a List-Unsubscribe=One-Click request body."));
+    }
+
+    let token = UnsubscribeToken::parse(query.0.token, &hmac_secret.0).map_err(e401)?;
+    tracing::Span::current().record(
+        "subscriber_id",
+        &tracing::field::display(token.subscriber_id()),
+    );
+    unsubscribe_subscriber(&token, &db_pool).await?;
+
+    Ok(HttpResponse::Ok().finish())
+}
+
+#[tracing::instrument(skip_all)]
+async fn unsubscribe_subscriber(
+    token: &UnsubscribeToken,
+    db_pool: &PgPool,
+) -> Result<(), actix_web::Error> {
+    let mut transaction = db_pool
+        .begin()
+        .await
+        .context("Failed to acquire connection from DB pool.")
+        .map_err(e500)?;
+
+    let subscriber_email = mark_subscriber_as_unsubscribed(token.subscriber_id(), &mut transaction)
+        .await
+        .context("Failed to update the subscription status.")
+        .map_err(e500)?
+        .ok_or_else(|| e401("The unsubscribe token is invalid."))?;
+
+    cancel_pending_deliveries(&subscriber_email, &mut transaction)
+        .await
+        .context("Failed to cancel the pending deliveries to the subscriber.")
+        .map_err(e500)?;
+
+    // An old confirmation link must not subscribe them again, only a new subscription can
+    sqlx::query!(
+        "DELETE FROM subscription_tokens WHERE subscriber_id = $1",
+        token.subscriber_id()
+    )
+    .execute(&mut transaction)
+    .await
+    .context("Failed to delete the subscription tokens of the subscriber.")
+    .map_err(e500)?;
+
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction for unsubscribing a subscriber.")
+        .map_err(e500)?;
+
+    Ok(())
+}
+
+/// Returns the email of the subscriber, or `None` if they don't exist.
+#[tracing::instrument(skip_all)]
+async fn mark_subscriber_as_unsubscribed(
+    subscriber_id: Uuid,
+    transaction: &mut Transaction<'_, Postgres>,
+) -> Result<Option<String>, sqlx::Error> {
+    let row = sqlx::query!(
+        r#"
+        UPDATE subscriptions
+        SET status = 'unsubscribed'
+        WHERE id = $1
+        RETURNING email
+    "#,
+        subscriber_id
+    )
+    .fetch_optional(transaction)
+    .await?;
+
+    Ok(row.map(|r| r.email))
+}
+
+/// Drop the subscriber from the issues that are still going out,
+/// marking them as completed if they were only waiting on this subscriber.
+#[tracing::instrument(skip_all)]
+async fn cancel_pending_deliveries(
+    subscriber_email: &str,
+    transaction: &mut Transaction<'_, Postgres>,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        WITH cancelled AS (
+            DELETE FROM issue_delivery_queue
+            WHERE subscriber_email = $1
+            RETURNING newsletter_issue_id
+        )
+        UPDATE newsletter_issues i
+        SET
+            n_total_recipients = i.n_total_recipients - 1,
+            completed_at = CASE
+                WHEN i.n_delivered + i.n_failed >= i.n_total_recipients - 1 THEN now()
+                ELSE i.completed_at
+            END
+        FROM cancelled
+        WHERE i.id = cancelled.newsletter_issue_id
+    "#,
+        subscriber_email
+    )
+    .execute(transaction)
+    .await?;
+
+    Ok(())
+}
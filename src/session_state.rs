@@ -8,19 +8,68 @@ pub struct TypedSession(Session);
 
 impl TypedSession {
     const USER_ID_KEY: &'static str = "user_id";
+    const SESSION_ID_KEY: &'static str = "session_id";
+    const PENDING_2FA_USER_ID_KEY: &'static str = "pending_2fa_user_id";
+    const FAILED_2FA_ATTEMPTS_KEY: &'static str = "failed_2fa_attempts";
+    const TOTP_ENROLLMENT_SECRET_KEY: &'static str = "totp_enrollment_secret";
 
     pub fn renew(&self) {
         self.0.renew()
     }
 
-    pub fn set_user_id(&self, value: Uuid) -> Result<(), serde_json::Error> {
+    /// `session_id` refers to the `user_sessions` row the login has been recorded in.
+    pub fn set_user_id(&self, value: Uuid, session_id: Uuid) -> Result<(), serde_json::Error> {
+        self.0.remove(Self::PENDING_2FA_USER_ID_KEY);
+        self.0.remove(Self::FAILED_2FA_ATTEMPTS_KEY);
+        self.0.insert(Self::SESSION_ID_KEY, session_id)?;
         self.0.insert(Self::USER_ID_KEY, value)
     }
 
+    /// The password of the user has been verified, the second factor hasn't yet:
+    /// they are not logged in until it is.
+    pub fn set_pending_2fa_user_id(&self, value: Uuid) -> Result<(), serde_json::Error> {
+        self.0.remove(Self::USER_ID_KEY);
+        self.0.remove(Self::SESSION_ID_KEY);
+        self.0.remove(Self::FAILED_2FA_ATTEMPTS_KEY);
+        self.0.insert(Self::PENDING_2FA_USER_ID_KEY, value)
+    }
+
+    pub fn get_pending_2fa_user_id(&self) -> Result<Option<Uuid>, serde_json::Error> {
+        self.0.get::<Uuid>(Self::PENDING_2FA_USER_ID_KEY)
+    }
+
+    /// Returns how many invalid codes have been entered since the password was verified.
+    pub fn record_failed_2fa_attempt(&self) -> Result<u32, serde_json::Error> {
+        let n_attempts = self
+            .0
+            .get::<u32>(Self::FAILED_2FA_ATTEMPTS_KEY)?
+            .unwrap_or_default()
+            + 1;
+        self.0.insert(Self::FAILED_2FA_ATTEMPTS_KEY, n_attempts)?;
+        Ok(n_attempts)
+    }
+
+    /// The TOTP secret being set up, until the user proves their app has it.
+    pub fn set_totp_enrollment_secret(&self, value: &str) -> Result<(), serde_json::Error> {
+        self.0.insert(Self::TOTP_ENROLLMENT_SECRET_KEY, value)
+    }
+
+    pub fn get_totp_enrollment_secret(&self) -> Result<Option<String>, serde_json::Error> {
+        self.0.get::<String>(Self::TOTP_ENROLLMENT_SECRET_KEY)
+    }
+
+    pub fn remove_totp_enrollment_secret(&self) {
+        self.0.remove(Self::TOTP_ENROLLMENT_SECRET_KEY);
+    }
+
     pub fn get_user_id(&self) -> Result<Option<Uuid>, serde_json::Error> {
         self.0.get::<Uuid>(Self::USER_ID_KEY)
     }
 
+    pub fn get_session_id(&self) -> Result<Option<Uuid>, serde_json::Error> {
+        self.0.get::<Uuid>(Self::SESSION_ID_KEY)
+    }
+
     pub fn log_out(&self) {
         self.0.purge();
     }
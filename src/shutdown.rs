@@ -0,0 +1,106 @@
+use std::sync::Arc;
+
+use tokio::sync::watch;
+
+/// Tells the long-running tasks of the process (the API and the delivery workers)
+/// that it's time to stop. Every clone observes the same signal.
+#[derive(Clone)]
+pub struct Shutdown {
+    sender: Arc<watch::Sender<bool>>,
+    receiver: watch::Receiver<bool>,
+}
+
+impl Shutdown {
+    pub fn new() -> Self {
+        let (sender, receiver) = watch::channel(false);
+        Self {
+            sender: Arc::new(sender),
+            receiver,
+        }
+    }
+
+    pub fn trigger(&self) {
+        // We hold a receiver ourselves, sending can't fail.
+        let _ = self.sender.send(true);
+    }
+
+    pub fn is_triggered(&self) -> bool {
+        *self.receiver.borrow()
+    }
+
+    /// Resolves once the shutdown has been triggered, right away if it already was.
+    pub async fn wait(&self) {
+        let mut receiver = self.receiver.clone();
+        while !*receiver.borrow() {
+            if receiver.changed().await.is_err() {
+                return;
+            }
+        }
+    }
+}
+
+impl Default for Shutdown {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Resolves on SIGTERM (sent by orchestrators on deploys) or Ctrl-C.
+pub async fn wait_for_termination_signal() {
+    let ctrl_c = async {
+        tokio::signal::ctrl_c()
+            .await
+            .expect("Failed to listen for Ctrl-C");
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())
+            .expect("Failed to listen for SIGTERM")
+            .recv()
+            .await;
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => {},
+        _ = terminate => {},
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Shutdown;
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn wait_resolves_once_the_shutdown_is_triggered() {
+        let shutdown = Shutdown::new();
+        let waiter = tokio::spawn({
+            let shutdown = shutdown.clone();
+            async move { shutdown.wait().await }
+        });
+        assert!(!shutdown.is_triggered());
+
+        shutdown.trigger();
+
+        tokio::time::timeout(Duration::from_secs(1), waiter)
+            .await
+            .expect("The waiter was never woken up")
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn clones_see_a_shutdown_triggered_before_they_wait() {
+        let shutdown = Shutdown::new();
+        let clone = shutdown.clone();
+
+        shutdown.trigger();
+
+        assert!(clone.is_triggered());
+        tokio::time::timeout(Duration::from_secs(1), clone.wait())
+            .await
+            .expect("Waiting for a triggered shutdown should return right away");
+    }
+}
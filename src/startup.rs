@@ -2,11 +2,12 @@ use std::net::TcpListener;
 
 use actix_web::dev::Server;
 use secrecy::Secret;
-use sqlx::{postgres::PgPoolOptions, Pool, Postgres};
+use sqlx::{postgres::PgPoolOptions, PgPool, Pool, Postgres};
 
 use crate::{
     configuration::{DBSettings, Settings},
     run,
+    shutdown::Shutdown,
 };
 use std::time::Duration;
 
@@ -16,6 +17,22 @@ pub fn get_connection_pool(configuration: &DBSettings) -> Pool<Postgres> {
         .connect_lazy_with(configuration.with_db())
 }
 
+/// For when the default of 10 connections isn't enough, e.g. for many delivery workers.
+pub fn get_connection_pool_of_size(
+    configuration: &DBSettings,
+    max_connections: u32,
+) -> Pool<Postgres> {
+    PgPoolOptions::new()
+        .max_connections(max_connections)
+        .connect_timeout(Duration::from_secs(10))
+        .connect_lazy_with(configuration.with_db())
+}
+
+/// Apply the migrations embedded in the binary that haven't been applied yet.
+pub async fn migrate_database(db_pool: &PgPool) -> Result<(), sqlx::migrate::MigrateError> {
+    sqlx::migrate!("./migrations").run(db_pool).await
+}
+
 pub struct HmacSecret(pub Secret<String>);
 
 // We need to define a wrapper type in order to retrieve the URL
@@ -39,6 +56,8 @@ impl Application {
             configuration.application.host, configuration.application.port
         );
 
+        let drain_timeout = configuration.application.drain_timeout();
+
         let listener = TcpListener::bind(address)?;
         let port = listener.local_addr().unwrap().port();
         let server = run(
@@ -47,7 +66,11 @@ impl Application {
             email_client,
             configuration.application.base_url,
             configuration.application.hmac_secret,
+            configuration.application.public_archive,
             configuration.redis_uri,
+            drain_timeout,
+            configuration.login_throttling,
+            configuration.subscriptions,
         )
         .await?;
 
@@ -58,7 +81,15 @@ impl Application {
         self.port
     }
 
-    pub async fn run_until_stopped(self) -> Result<(), std::io::Error> {
+    /// Serve requests until `shutdown` is triggered, then stop accepting connections
+    /// and give in-flight requests up to the drain timeout to complete.
+    pub async fn run_until_stopped(self, shutdown: Shutdown) -> Result<(), std::io::Error> {
+        let handle = self.server.handle();
+        tokio::spawn(async move {
+            shutdown.wait().await;
+            handle.stop(true).await;
+        });
+
         self.server.await
     }
 }
@@ -0,0 +1,100 @@
+use std::time::Duration;
+
+use anyhow::Context;
+use sqlx::PgPool;
+use tracing::Instrument;
+
+use crate::{
+    configuration::{Settings, SubscriptionSettings},
+    shutdown::Shutdown,
+    startup::get_connection_pool,
+};
+
+/// Deletes the unused confirmation tokens older than `ttl`, then the pending subscriptions
+/// left without any, as nobody can confirm them anymore.
+/// Used tokens are kept, following them again tells that the subscription is confirmed.
+/// Returns the number of subscriptions deleted.
+#[tracing::instrument(name = "Purge stale pending subscriptions", skip(db_pool))]
+pub async fn purge_stale_subscriptions(
+    db_pool: &PgPool,
+    ttl: Duration,
+) -> Result<u64, anyhow::Error> {
+    let mut transaction = db_pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool.")?;
+
+    sqlx::query!(
+        r#"
+        DELETE FROM subscription_tokens
+        WHERE consumed_at IS NULL AND created_at <= now() - make_interval(secs => $1)
+    "#,
+        ttl.as_secs_f64()
+    )
+    .execute(&mut transaction)
+    .await
+    .context("Failed deleting the expired subscription tokens.")?;
+
+    // Subscribing again, with a fresh token, locks the subscriber: the ones it
+    // happens to concurrently keep their token and are left alone.
+    let n_deleted = sqlx::query!(
+        r#"
+        DELETE FROM subscriptions
+        WHERE status = 'pending_confirmation'
+            AND subscribed_at <= now() - make_interval(secs => $1)
+            AND NOT EXISTS (
+                SELECT 1 FROM subscription_tokens
+                WHERE subscription_tokens.subscriber_id = subscriptions.id
+            )
+    "#,
+        ttl.as_secs_f64()
+    )
+    .execute(&mut transaction)
+    .await
+    .context("Failed deleting the stale pending subscriptions.")?
+    .rows_affected();
+
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit the purge of stale pending subscriptions.")?;
+    Ok(n_deleted)
+}
+
+async fn purge_loop(db_pool: PgPool, settings: SubscriptionSettings, shutdown: Shutdown) {
+    while !shutdown.is_triggered() {
+        match purge_stale_subscriptions(&db_pool, settings.confirmation_token_ttl()).await {
+            Ok(0) => {}
+            Ok(n_deleted) => {
+                tracing::info!("Deleted {} stale pending subscriptions.", n_deleted);
+            }
+            // Try again at the next interval.
+            Err(e) => {
+                tracing::error!(
+                    error.cause_chain = ?e,
+                    error.message = %e,
+                    "Failed to purge stale pending subscriptions."
+                );
+            }
+        }
+
+        tokio::select! {
+            _ = tokio::time::sleep(settings.purge_interval()) => {}
+            _ = shutdown.wait() => {}
+        }
+    }
+}
+
+/// Periodically purge the pending subscriptions that can't be confirmed anymore,
+/// until `shutdown` is triggered.
+pub async fn run_purge_until_stopped(
+    configuration: Settings,
+    shutdown: Shutdown,
+) -> Result<(), anyhow::Error> {
+    let connection_pool = get_connection_pool(&configuration.database);
+    purge_loop(connection_pool, configuration.subscriptions, shutdown)
+        .instrument(tracing::info_span!("Subscription purge"))
+        .await;
+
+    Ok(())
+}
@@ -11,7 +11,28 @@ pub fn e400<T>(err: T) -> actix_web::Error
 where
     T: std::fmt::Debug + std::fmt::Display + 'static,
 {
-    actix_web::error::ErrorBadGateway(err)
+    actix_web::error::ErrorBadRequest(err)
+}
+
+pub fn e401<T>(err: T) -> actix_web::Error
+where
+    T: std::fmt::Debug + std::fmt::Display + 'static,
+{
+    actix_web::error::ErrorUnauthorized(err)
+}
+
+pub fn e403<T>(err: T) -> actix_web::Error
+where
+    T: std::fmt::Debug + std::fmt::Display + 'static,
+{
+    actix_web::error::ErrorForbidden(err)
+}
+
+pub fn e404<T>(err: T) -> actix_web::Error
+where
+    T: std::fmt::Debug + std::fmt::Display + 'static,
+{
+    actix_web::error::ErrorNotFound(err)
 }
 
 pub fn see_other(destination: &str) -> HttpResponse {
@@ -19,3 +40,22 @@ pub fn see_other(destination: &str) -> HttpResponse {
         .insert_header((header::LOCATION, destination))
         .finish()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{e400, e404, e500};
+
+    #[test]
+    fn error_helpers_answer_with_their_status_code() {
+        for (error, expected_status) in [
+            (e400("invalid input"), 400),
+            (e404("invalid input"), 404),
+            (e500("invalid input"), 500),
+        ] {
+            assert_eq!(
+                error.as_response_error().status_code().as_u16(),
+                expected_status
+            );
+        }
+    }
+}
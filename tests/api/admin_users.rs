@@ -0,0 +1,263 @@
+use uuid::Uuid;
+use zero2prod::authentication::{find_user_id, list_users, Role};
+
+use crate::helpers::{spawn_app, TestApp, TestUser};
+
+async fn log_in_as(app: &TestApp, role: Role) -> TestUser {
+    let user = TestUser::generate_with_role(role);
+    user.store(&app.db_pool).await;
+    user.login(app).await;
+    user
+}
+
+fn new_user_form(username: &str, role: Role) -> serde_json::Value {
+    serde_json::json!({
+        "username": username,
+        "password": "a-long-enough-password",
+        "password_check": "a-long-enough-password",
+        "role": role.as_str(),
+    })
+}
+
+fn assert_is_redirect_to(response: &reqwest::Response, location: &str) {
+    assert_eq!(response.status().as_u16(), 303);
+    assert_eq!(response.headers().get("Location").unwrap(), location);
+}
+
+#[tokio::test]
+async fn must_be_logged_in_to_manage_users() {
+    let app = spawn_app().await;
+
+    let response = app.get_users().await;
+
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn only_owners_can_manage_users() {
+    let app = spawn_app().await;
+
+    for role in [Role::Editor, Role::Viewer] {
+        log_in_as(&app, role).await;
+
+        assert_eq!(app.get_users().await.status().as_u16(), 403);
+        let response = app.post_add_user(&new_user_form("ursula", role)).await;
+        assert_eq!(response.status().as_u16(), 403);
+        let response = app
+            .post_user_action(app.test_user.id, "delete", &serde_json::json!({}))
+            .await;
+        assert_eq!(response.status().as_u16(), 403);
+    }
+    assert!(find_user_id("ursula", &app.db_pool).await.is_err());
+}
+
+#[tokio::test]
+async fn viewers_cannot_publish_or_edit_newsletter_issues() {
+    let app = spawn_app().await;
+    log_in_as(&app, Role::Viewer).await;
+
+    assert_eq!(app.get_publish_newsletter().await.status().as_u16(), 403);
+    let response = app
+        .post_publish_newsletter(&serde_json::json!({
+            "title": "Newsletter Title",
+            "text_content": "Newsletter body as plain text",
+            "html_content": "<p>Newsletter body as plain text</p>",
+            "idempotency_key": Uuid::new_v4().to_string()
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 403);
+    let response = app
+        .post_create_draft(&serde_json::json!({
+            "title": "Draft",
+            "text_content": "",
+            "html_content": "",
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 403);
+    let response = app.post_cancel_scheduled_issue(Uuid::new_v4()).await;
+    assert_eq!(response.status().as_u16(), 403);
+}
+
+#[tokio::test]
+async fn viewers_can_still_browse_the_dashboard_and_past_issues() {
+    let app = spawn_app().await;
+    log_in_as(&app, Role::Viewer).await;
+
+    let dashboard_html = app.get_admin_dashboard_html().await;
+    assert!(dashboard_html.contains("(viewer)"));
+    assert!(dashboard_html.contains("/admin/newsletters/history"));
+    assert!(!dashboard_html.contains(r#"href="/admin/newsletters""#));
+    assert!(!dashboard_html.contains("/admin/users"));
+
+    let response = app.get_newsletter_history(None).await;
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn editors_can_publish_newsletter_issues() {
+    let app = spawn_app().await;
+    log_in_as(&app, Role::Editor).await;
+
+    assert_eq!(app.get_publish_newsletter().await.status().as_u16(), 200);
+    let dashboard_html = app.get_admin_dashboard_html().await;
+    assert!(dashboard_html.contains(r#"href="/admin/newsletters""#));
+    assert!(!dashboard_html.contains("/admin/users"));
+}
+
+#[tokio::test]
+async fn owners_can_add_users() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    let response = app
+        .post_add_user(&new_user_form("ursula", Role::Viewer))
+        .await;
+    assert_is_redirect_to(&response, "/admin/users");
+
+    let users_html = app.get_users_html().await;
+    assert!(users_html.contains("ursula has been added as viewer."));
+    let user_id = find_user_id("ursula", &app.db_pool).await.unwrap();
+    assert!(users_html.contains(&format!("/admin/users/{}/disable", user_id)));
+}
+
+#[tokio::test]
+async fn new_users_must_have_a_valid_password() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    let mut form = new_user_form("ursula", Role::Viewer);
+    form["password_check"] = "another-long-enough-password".into();
+    app.post_add_user(&form).await;
+    assert!(app
+        .get_users_html()
+        .await
+        .contains("The passwords don't match."));
+
+    let mut form = new_user_form("ursula", Role::Viewer);
+    form["password"] = "too-short".into();
+    form["password_check"] = "too-short".into();
+    app.post_add_user(&form).await;
+    assert!(app
+        .get_users_html()
+        .await
+        .contains("Passwords must be between 12 and 128 characters long."));
+
+    assert!(find_user_id("ursula", &app.db_pool).await.is_err());
+}
+
+#[tokio::test]
+async fn owners_can_change_the_role_of_a_user() {
+    let app = spawn_app().await;
+    let user = TestUser::generate_with_role(Role::Viewer);
+    user.store(&app.db_pool).await;
+    app.test_user.login(&app).await;
+
+    let response = app
+        .post_user_action(user.id, "role", &serde_json::json!({ "role": "editor" }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/users");
+
+    let users = list_users(&app.db_pool).await.unwrap();
+    let updated = users.iter().find(|u| u.id == user.id).unwrap();
+    assert_eq!(updated.role, Role::Editor);
+}
+
+#[tokio::test]
+async fn a_disabled_user_is_logged_out_and_cannot_log_back_in() {
+    let app = spawn_app().await;
+    let user = TestUser::generate_with_role(Role::Editor);
+    user.store(&app.db_pool).await;
+    app.test_user.login(&app).await;
+
+    app.post_user_action(user.id, "disable", &serde_json::json!({}))
+        .await;
+    assert!(app.get_users_html().await.contains("has been disabled."));
+
+    // Log in as the disabled user from a separate client
+    let client = reqwest::Client::builder()
+        .redirect(reqwest::redirect::Policy::none())
+        .cookie_store(true)
+        .build()
+        .unwrap();
+    let response = client
+        .post(format!("{}/login", app.address))
+        .form(&serde_json::json!({
+            "username": user.username,
+            "password": user.password,
+        }))
+        .send()
+        .await
+        .unwrap();
+    assert_is_redirect_to(&response, "/login");
+
+    app.post_user_action(user.id, "enable", &serde_json::json!({}))
+        .await;
+    user.login(&app).await;
+    assert_eq!(app.get_admin_dashboard().await.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn an_existing_session_ends_when_the_user_is_disabled() {
+    let app = spawn_app().await;
+    let user = log_in_as(&app, Role::Editor).await;
+    assert_eq!(app.get_admin_dashboard().await.status().as_u16(), 200);
+
+    sqlx::query!("UPDATE users SET disabled = true WHERE id = $1", user.id)
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+
+    assert_is_redirect_to(&app.get_admin_dashboard().await, "/login");
+}
+
+#[tokio::test]
+async fn owners_can_delete_users() {
+    let app = spawn_app().await;
+    let user = TestUser::generate_with_role(Role::Viewer);
+    user.store(&app.db_pool).await;
+    app.test_user.login(&app).await;
+
+    let response = app
+        .post_user_action(user.id, "delete", &serde_json::json!({}))
+        .await;
+    assert_is_redirect_to(&response, "/admin/users");
+
+    assert!(app.get_users_html().await.contains("has been deleted."));
+    assert!(find_user_id(&user.username, &app.db_pool).await.is_err());
+}
+
+#[tokio::test]
+async fn the_last_owner_cannot_remove_themselves() {
+    let app = spawn_app().await;
+    for user in list_users(&app.db_pool).await.unwrap() {
+        if user.id != app.test_user.id {
+            sqlx::query!("UPDATE users SET role = 'viewer' WHERE id = $1", user.id)
+                .execute(&app.db_pool)
+                .await
+                .unwrap();
+        }
+    }
+    app.test_user.login(&app).await;
+
+    app.post_user_action(app.test_user.id, "delete", &serde_json::json!({}))
+        .await;
+
+    assert!(app
+        .get_users_html()
+        .await
+        .contains("There must be at least one enabled owner left."));
+}
+
+#[tokio::test]
+async fn unknown_users_are_reported() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    app.post_user_action(Uuid::new_v4(), "disable", &serde_json::json!({}))
+        .await;
+
+    assert!(app
+        .get_users_html()
+        .await
+        .contains("There is no such user."));
+}
@@ -0,0 +1,237 @@
+use uuid::Uuid;
+use wiremock::{
+    matchers::{method, path},
+    Mock,
+};
+use zero2prod::authentication::Role;
+
+use crate::helpers::{accept_every_email, spawn_app, TestApp, TestUser};
+
+struct Entry {
+    actor_username: String,
+    action: String,
+    target: Option<String>,
+}
+
+/// Oldest first.
+async fn audit_entries(app: &TestApp) -> Vec<Entry> {
+    sqlx::query_as!(
+        Entry,
+        "SELECT actor_username, action, target FROM audit_log ORDER BY occurred_at"
+    )
+    .fetch_all(&app.db_pool)
+    .await
+    .unwrap()
+}
+
+async fn audit_actions(app: &TestApp) -> Vec<String> {
+    audit_entries(app)
+        .await
+        .into_iter()
+        .map(|entry| entry.action)
+        .collect()
+}
+
+#[tokio::test]
+async fn logging_in_and_out_is_recorded() {
+    let app = spawn_app().await;
+
+    app.test_user.login(&app).await;
+    app.post_logout().await;
+
+    let entries = audit_entries(&app).await;
+    assert_eq!(audit_actions(&app).await, ["log_in", "log_out"]);
+    assert!(entries
+        .iter()
+        .all(|entry| entry.actor_username == app.test_user.username));
+}
+
+#[tokio::test]
+async fn failed_logins_are_not_recorded() {
+    let app = spawn_app().await;
+
+    app.post_login(&serde_json::json!({
+        "username": &app.test_user.username,
+        "password": "wrong-password",
+    }))
+    .await;
+
+    assert!(audit_entries(&app).await.is_empty());
+}
+
+#[tokio::test]
+async fn changing_the_password_is_recorded() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let new_password = Uuid::new_v4().to_string();
+
+    app.post_change_password(&serde_json::json!({
+        "current_password": &app.test_user.password,
+        "new_password": &new_password,
+        "new_password_check": &new_password,
+    }))
+    .await;
+
+    assert_eq!(audit_actions(&app).await, ["log_in", "change_password"]);
+}
+
+#[tokio::test]
+async fn a_published_newsletter_is_recorded_once_even_if_the_form_is_resubmitted() {
+    let app = spawn_app().await;
+    Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        .respond_with(accept_every_email)
+        .mount(&app.email_server)
+        .await;
+    app.test_user.login(&app).await;
+    let newsletter_request_body = serde_json::json!({
+        "title": "Newsletter Title",
+        "text_content": "Newsletter body as plain text",
+        "html_content": "<p>Newsletter body as plain text</p>",
+        "idempotency_key": Uuid::new_v4().to_string()
+    });
+
+    app.post_publish_newsletter(&newsletter_request_body).await;
+    app.post_publish_newsletter(&newsletter_request_body).await;
+
+    let issue_id = sqlx::query!("SELECT id FROM newsletter_issues")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .id;
+    let entries = audit_entries(&app).await;
+    assert_eq!(entries.len(), 2);
+    assert_eq!(entries[1].action, "publish_newsletter");
+    assert_eq!(entries[1].target, Some(format!("issue:{}", issue_id)));
+}
+
+#[tokio::test]
+async fn user_management_is_recorded_with_its_target() {
+    let app = spawn_app().await;
+    let user = TestUser::generate_with_role(Role::Editor);
+    user.store(&app.db_pool).await;
+    app.test_user.login(&app).await;
+
+    app.post_user_action(user.id, "disable", &serde_json::json!({}))
+        .await;
+
+    let entries = audit_entries(&app).await;
+    assert_eq!(entries[1].action, "disable_user");
+    assert_eq!(
+        entries[1].target,
+        Some(format!("user:{} has been disabled", user.username))
+    );
+}
+
+#[tokio::test]
+async fn entries_outlive_the_user_who_made_them() {
+    let app = spawn_app().await;
+    let user = TestUser::generate_with_role(Role::Owner);
+    user.store(&app.db_pool).await;
+    user.login(&app).await;
+    app.post_logout().await;
+    app.test_user.login(&app).await;
+
+    app.post_user_action(user.id, "delete", &serde_json::json!({}))
+        .await;
+
+    let entries = audit_entries(&app).await;
+    assert_eq!(entries[0].actor_username, user.username);
+    assert_eq!(entries[0].action, "log_in");
+}
+
+#[tokio::test]
+async fn only_owners_can_read_the_audit_log() {
+    let app = spawn_app().await;
+    let user = TestUser::generate_with_role(Role::Editor);
+    user.store(&app.db_pool).await;
+    user.login(&app).await;
+
+    assert_eq!(app.get_audit_log(&[]).await.status().as_u16(), 403);
+    assert_eq!(app.get_audit_log_export(&[]).await.status().as_u16(), 403);
+}
+
+#[tokio::test]
+async fn the_audit_log_can_be_filtered() {
+    let app = spawn_app().await;
+    let user = TestUser::generate_with_role(Role::Editor);
+    user.store(&app.db_pool).await;
+    user.login(&app).await;
+    app.post_logout().await;
+    app.test_user.login(&app).await;
+
+    let html_page = app.get_audit_log_html(&[]).await;
+    assert!(html_page.contains(&user.username));
+    assert!(html_page.contains("log_out"));
+
+    let html_page = app
+        .get_audit_log_html(&[("actor", &app.test_user.username)])
+        .await;
+    assert!(!html_page.contains(&format!("<td>{}</td>", user.username)));
+
+    let html_page = app.get_audit_log_html(&[("action", "log_out")]).await;
+    assert!(html_page.contains(&format!("<td>{}</td>", user.username)));
+    assert!(!html_page.contains(&format!("<td>{}</td>", app.test_user.username)));
+
+    let html_page = app
+        .get_audit_log_html(&[("since", "2000-01-01"), ("until", "2000-12-31")])
+        .await;
+    assert!(!html_page.contains("<td>log_in</td>"));
+}
+
+#[tokio::test]
+async fn invalid_filters_are_rejected() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    for query in [
+        [("action", "launch_missiles")],
+        [("since", "yesterday")],
+        // The last day chrono knows, there is no next one to end it
+        [("until", "+262143-12-31")],
+    ] {
+        assert_eq!(app.get_audit_log(&query).await.status().as_u16(), 400);
+        assert_eq!(
+            app.get_audit_log_export(&query).await.status().as_u16(),
+            400
+        );
+    }
+}
+
+#[tokio::test]
+async fn pages_past_any_possible_offset_are_rejected() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    let page = i64::MAX.to_string();
+    assert_eq!(
+        app.get_audit_log(&[("page", &page)])
+            .await
+            .status()
+            .as_u16(),
+        400
+    );
+}
+
+#[tokio::test]
+async fn the_audit_log_can_be_exported_as_csv() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    app.post_logout().await;
+    app.test_user.login(&app).await;
+
+    let response = app.get_audit_log_export(&[("action", "log_in")]).await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(
+        response.headers().get("Content-Type").unwrap(),
+        "text/csv; charset=utf-8"
+    );
+    let csv = response.text().await.unwrap();
+    let lines: Vec<&str> = csv.lines().collect();
+    assert_eq!(lines[0], "occurred_at,actor,action,target,client_ip");
+    assert_eq!(lines.len(), 3);
+    assert!(lines[1..]
+        .iter()
+        .all(|line| line.ends_with(&format!(",{},log_in,,127.0.0.1", app.test_user.username))));
+}
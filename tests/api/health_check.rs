@@ -14,3 +14,20 @@ async fn health_check_works() {
     assert!(response.status().is_success());
     assert_eq!(Some(0), response.content_length());
 }
+
+#[tokio::test]
+async fn the_api_stops_serving_requests_on_shutdown() {
+    let app = spawn_app().await;
+    let client = reqwest::Client::new();
+
+    app.shutdown.trigger();
+    // Give the server a moment to stop listening
+    tokio::time::sleep(std::time::Duration::from_millis(500)).await;
+
+    let outcome = client
+        .get(format!("{}/health-check", app.address))
+        .send()
+        .await;
+
+    assert!(outcome.is_err());
+}
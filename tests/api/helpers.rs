@@ -2,15 +2,27 @@ use argon2::{
     password_hash::{rand_core::OsRng, SaltString},
     Algorithm, Argon2, Params, PasswordHasher, Version,
 };
+use fake::{
+    faker::{internet::en::SafeEmail, name::en::Name},
+    Fake,
+};
 use once_cell::sync::Lazy;
+use secrecy::Secret;
 use sqlx::{Connection, Executor, PgConnection, PgPool};
+use tokio::task::JoinHandle;
 use uuid::Uuid;
-use wiremock::MockServer;
+use wiremock::{
+    matchers::{method, path},
+    Mock, MockServer, ResponseTemplate,
+};
 use zero2prod::{
-    configuration::{get_configuration, DBSettings},
+    authentication::Role,
+    configuration::{get_configuration, DBSettings, IssueDeliverySettings, Settings},
     email_client::EmailClient,
-    issue_delivery_worker::{try_execute_task, ExecutionOutcome},
-    startup::{get_connection_pool, Application},
+    issue_delivery_worker::{run_worker_until_stopped, try_execute_task, ExecutionOutcome},
+    shutdown::Shutdown,
+    startup::{get_connection_pool, migrate_database, Application},
+    subscription_purge::purge_stale_subscriptions,
     telemetry::{get_subscriber, init_subscriber},
 };
 
@@ -20,17 +32,23 @@ pub struct ConfirmationLink {
 }
 
 pub struct TestUser {
-    id: Uuid,
+    pub id: Uuid,
     pub username: String,
     pub password: String,
+    pub role: Role,
 }
 
 impl TestUser {
     pub fn generate() -> Self {
+        Self::generate_with_role(Role::Owner)
+    }
+
+    pub fn generate_with_role(role: Role) -> Self {
         Self {
             id: Uuid::new_v4(),
             username: Uuid::new_v4().to_string(),
             password: Uuid::new_v4().to_string(),
+            role,
         }
     }
 
@@ -46,10 +64,11 @@ impl TestUser {
         .to_string();
 
         sqlx::query!(
-            r#"INSERT INTO users (id, username, password_hash) VALUES ($1, $2, $3)"#,
+            r#"INSERT INTO users (id, username, password_hash, role) VALUES ($1, $2, $3, $4)"#,
             self.id,
             self.username,
-            password_hash
+            password_hash,
+            self.role.as_str()
         )
         .execute(pool)
         .await
@@ -73,21 +92,80 @@ pub struct TestApp {
     pub test_user: TestUser,
     pub http_client: reqwest::Client,
     pub email_client: EmailClient,
+    pub issue_delivery_settings: IssueDeliverySettings,
+    pub hmac_secret: Secret<String>,
+    pub shutdown: Shutdown,
+    configuration: Settings,
 }
 
 impl TestApp {
+    /// Stops when the rate limit lets no more emails out: the rest isn't due yet.
     pub async fn dispatch_all_pending_emails(&self) {
         loop {
-            if let ExecutionOutcome::EmptyQueue =
-                try_execute_task(&self.email_client, &self.db_pool)
-                    .await
-                    .unwrap()
+            match try_execute_task(
+                &self.email_client,
+                &self.db_pool,
+                &self.issue_delivery_settings,
+                &self.address,
+                &self.hmac_secret,
+            )
+            .await
+            .unwrap()
             {
-                break;
+                ExecutionOutcome::EmptyQueue | ExecutionOutcome::RateLimited { .. } => break,
+                ExecutionOutcome::TaskCompleted => {}
             }
         }
     }
 
+    /// Run the delivery workers in the background, as the worker process does,
+    /// with the current `issue_delivery_settings`, until `shutdown` is triggered.
+    /// It's not `self.shutdown`, which would stop the API as well.
+    pub fn spawn_delivery_workers(
+        &self,
+        shutdown: &Shutdown,
+    ) -> JoinHandle<Result<(), anyhow::Error>> {
+        let mut configuration = self.configuration.clone();
+        configuration.issue_delivery = self.issue_delivery_settings.clone();
+        tokio::spawn(run_worker_until_stopped(configuration, shutdown.clone()))
+    }
+
+    /// Make the confirmation links sent so far, and the pending subscriptions,
+    /// older than the time to live of the links.
+    pub async fn expire_confirmation_links(&self) {
+        let ttl = self
+            .configuration
+            .subscriptions
+            .confirmation_token_ttl_seconds as f64;
+        sqlx::query!(
+            "UPDATE subscription_tokens SET created_at = created_at - make_interval(secs => $1)",
+            ttl
+        )
+        .execute(&self.db_pool)
+        .await
+        .unwrap();
+        sqlx::query!(
+            r#"
+            UPDATE subscriptions SET subscribed_at = subscribed_at - make_interval(secs => $1)
+            WHERE status = 'pending_confirmation'
+        "#,
+            ttl
+        )
+        .execute(&self.db_pool)
+        .await
+        .unwrap();
+    }
+
+    /// Do what the purge job does periodically.
+    pub async fn purge_stale_subscriptions(&self) -> u64 {
+        purge_stale_subscriptions(
+            &self.db_pool,
+            self.configuration.subscriptions.confirmation_token_ttl(),
+        )
+        .await
+        .unwrap()
+    }
+
     pub async fn post_subscription(&self, body: String) -> reqwest::Response {
         self.http_client
             .post(format!("{}/subscriptions", self.address))
@@ -142,6 +220,225 @@ impl TestApp {
             .expect("Failed to send POST request to /admin/password")
     }
 
+    pub async fn get_issue_delivery_status(&self, issue_id: Uuid) -> reqwest::Response {
+        self.http_client
+            .get(format!("{}/admin/newsletters/{}", self.address, issue_id))
+            .send()
+            .await
+            .expect("Failed getting the newsletter issue status page.")
+    }
+
+    pub async fn get_issue_delivery_status_json(&self, issue_id: Uuid) -> serde_json::Value {
+        self.http_client
+            .get(format!(
+                "{}/admin/newsletters/{}/status",
+                self.address, issue_id
+            ))
+            .send()
+            .await
+            .expect("Failed getting the newsletter issue status.")
+            .json()
+            .await
+            .unwrap()
+    }
+
+    pub async fn post_cancel_scheduled_issue(&self, issue_id: Uuid) -> reqwest::Response {
+        self.http_client
+            .post(format!(
+                "{}/admin/newsletters/{}/cancel",
+                self.address, issue_id
+            ))
+            .send()
+            .await
+            .expect("Failed to send POST request to cancel a newsletter issue.")
+    }
+
+    pub async fn post_reschedule_issue<Body>(
+        &self,
+        issue_id: Uuid,
+        body: &Body,
+    ) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.http_client
+            .post(format!(
+                "{}/admin/newsletters/{}/reschedule",
+                self.address, issue_id
+            ))
+            .form(body)
+            .send()
+            .await
+            .expect("Failed to send POST request to reschedule a newsletter issue.")
+    }
+
+    pub async fn get_newsletter_history(&self, page: Option<i64>) -> reqwest::Response {
+        let url = match page {
+            Some(page) => format!("{}/admin/newsletters/history?page={}", self.address, page),
+            None => format!("{}/admin/newsletters/history", self.address),
+        };
+        self.http_client
+            .get(url)
+            .send()
+            .await
+            .expect("Failed getting the newsletter history page.")
+    }
+
+    pub async fn get_archive(&self) -> reqwest::Response {
+        self.http_client
+            .get(format!("{}/archive", self.address))
+            .send()
+            .await
+            .expect("Failed getting the newsletter archive.")
+    }
+
+    pub async fn get_archived_issue(&self, issue_id: Uuid) -> reqwest::Response {
+        self.http_client
+            .get(format!("{}/archive/{}", self.address, issue_id))
+            .send()
+            .await
+            .expect("Failed getting an archived newsletter issue.")
+    }
+
+    pub async fn get_drafts_html(&self) -> String {
+        self.http_client
+            .get(format!("{}/admin/newsletters/drafts", self.address))
+            .send()
+            .await
+            .expect("Failed getting the drafts page.")
+            .text()
+            .await
+            .unwrap()
+    }
+
+    pub async fn post_create_draft<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.http_client
+            .post(format!("{}/admin/newsletters/drafts", self.address))
+            .form(body)
+            .send()
+            .await
+            .expect("Failed to send POST request to create a draft.")
+    }
+
+    pub async fn get_edit_draft(&self, draft_id: Uuid) -> reqwest::Response {
+        self.http_client
+            .get(format!(
+                "{}/admin/newsletters/drafts/{}",
+                self.address, draft_id
+            ))
+            .send()
+            .await
+            .expect("Failed getting the edit draft page.")
+    }
+
+    pub async fn post_update_draft<Body>(&self, draft_id: Uuid, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.http_client
+            .post(format!(
+                "{}/admin/newsletters/drafts/{}",
+                self.address, draft_id
+            ))
+            .form(body)
+            .send()
+            .await
+            .expect("Failed to send POST request to update a draft.")
+    }
+
+    pub async fn post_delete_draft(&self, draft_id: Uuid) -> reqwest::Response {
+        self.http_client
+            .post(format!(
+                "{}/admin/newsletters/drafts/{}/delete",
+                self.address, draft_id
+            ))
+            .send()
+            .await
+            .expect("Failed to send POST request to delete a draft.")
+    }
+
+    pub async fn get_draft_preview(&self, draft_id: Uuid, format: &str) -> reqwest::Response {
+        self.http_client
+            .get(format!(
+                "{}/admin/newsletters/drafts/{}/preview/{}",
+                self.address, draft_id, format
+            ))
+            .send()
+            .await
+            .expect("Failed getting the draft preview.")
+    }
+
+    pub async fn post_send_test_draft<Body>(&self, draft_id: Uuid, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.http_client
+            .post(format!(
+                "{}/admin/newsletters/drafts/{}/test",
+                self.address, draft_id
+            ))
+            .form(body)
+            .send()
+            .await
+            .expect("Failed to send POST request to send a test copy of a draft.")
+    }
+
+    pub async fn post_publish_draft<Body>(&self, draft_id: Uuid, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.http_client
+            .post(format!(
+                "{}/admin/newsletters/drafts/{}/publish",
+                self.address, draft_id
+            ))
+            .form(body)
+            .send()
+            .await
+            .expect("Failed to send POST request to publish a draft.")
+    }
+
+    pub async fn get_issue_delivery_failures(&self, issue_id: Uuid) -> reqwest::Response {
+        self.http_client
+            .get(format!(
+                "{}/admin/newsletters/{}/failures",
+                self.address, issue_id
+            ))
+            .send()
+            .await
+            .expect("Failed getting the failed deliveries page.")
+    }
+
+    pub async fn get_issue_delivery_failures_html(&self, issue_id: Uuid) -> String {
+        self.get_issue_delivery_failures(issue_id)
+            .await
+            .text()
+            .await
+            .unwrap()
+    }
+
+    pub async fn post_requeue_failed_delivery<Body>(
+        &self,
+        issue_id: Uuid,
+        body: &Body,
+    ) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.http_client
+            .post(format!(
+                "{}/admin/newsletters/{}/failures/requeue",
+                self.address, issue_id
+            ))
+            .form(body)
+            .send()
+            .await
+            .expect("Failed to send POST request to requeue a failed delivery.")
+    }
+
     pub fn get_confirmation_link_from_email_body(
         &self,
         email_request: &wiremock::Request,
@@ -163,12 +460,61 @@ impl TestApp {
             confirmation_link
         };
 
-        let html = get_links(&body["html_body"].as_str().unwrap());
-        let plain_text = get_links(&body["text_body"].as_str().unwrap());
+        let html = get_links(body["HtmlBody"].as_str().unwrap());
+        let plain_text = get_links(body["TextBody"].as_str().unwrap());
 
         ConfirmationLink { html, plain_text }
     }
 
+    /// The unsubscribe link the delivery worker appended to the plain text body
+    /// of the first issue of a batch.
+    pub fn get_unsubscribe_link_from_email_body(
+        &self,
+        email_request: &wiremock::Request,
+    ) -> reqwest::Url {
+        let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+
+        let link = linkify::LinkFinder::new()
+            .links(body[0]["TextBody"].as_str().unwrap())
+            .filter(|l| *l.kind() == linkify::LinkKind::Url)
+            .find(|l| l.as_str().contains("/subscriptions/unsubscribe"))
+            .expect("No unsubscribe link in the email body.");
+
+        reqwest::Url::parse(link.as_str()).unwrap()
+    }
+
+    pub async fn get_unsubscribe(&self, token: &str) -> reqwest::Response {
+        self.http_client
+            .get(format!("{}/subscriptions/unsubscribe", self.address))
+            .query(&[("token", token)])
+            .send()
+            .await
+            .expect("Failed getting the unsubscribe page.")
+    }
+
+    pub async fn post_unsubscribe(&self, token: &str) -> reqwest::Response {
+        self.http_client
+            .post(format!("{}/subscriptions/unsubscribe", self.address))
+            .query(&[("token", token)])
+            .send()
+            .await
+            .expect("Failed to send POST request to unsubscribe.")
+    }
+
+    pub async fn post_unsubscribe_one_click(&self, token: &str, body: &str) -> reqwest::Response {
+        self.http_client
+            .post(format!(
+                "{}/subscriptions/unsubscribe/one-click",
+                self.address
+            ))
+            .query(&[("token", token)])
+            .header("Content-Type", "application/x-www-form-urlencoded")
+            .body(body.to_owned())
+            .send()
+            .await
+            .expect("Failed to send POST request to unsubscribe with one click.")
+    }
+
     pub async fn get_login_html(&self) -> String {
         self.http_client
             .get(format!("{}/login", self.address))
@@ -223,6 +569,206 @@ impl TestApp {
             .await
             .expect("Failed getting change password form.")
     }
+
+    pub async fn post_login_2fa(&self, code: &str) -> reqwest::Response {
+        self.http_client
+            .post(format!("{}/login/2fa", self.address))
+            .form(&serde_json::json!({ "code": code }))
+            .send()
+            .await
+            .expect("Failed to send POST request to /login/2fa")
+    }
+
+    pub async fn get_login_2fa(&self) -> reqwest::Response {
+        self.http_client
+            .get(format!("{}/login/2fa", self.address))
+            .send()
+            .await
+            .expect("Failed to send GET request to /login/2fa")
+    }
+
+    pub async fn get_two_factor_settings_html(&self) -> String {
+        self.http_client
+            .get(format!("{}/admin/2fa", self.address))
+            .send()
+            .await
+            .expect("Failed to send GET request to /admin/2fa")
+            .text()
+            .await
+            .unwrap()
+    }
+
+    pub async fn post_enable_2fa(&self, code: &str) -> reqwest::Response {
+        self.http_client
+            .post(format!("{}/admin/2fa", self.address))
+            .form(&serde_json::json!({ "code": code }))
+            .send()
+            .await
+            .expect("Failed to send POST request to /admin/2fa")
+    }
+
+    pub async fn post_disable_2fa(&self, code: &str) -> reqwest::Response {
+        self.http_client
+            .post(format!("{}/admin/2fa/disable", self.address))
+            .form(&serde_json::json!({ "code": code }))
+            .send()
+            .await
+            .expect("Failed to send POST request to /admin/2fa/disable")
+    }
+
+    pub async fn get_forgot_password_html(&self) -> String {
+        self.http_client
+            .get(format!("{}/login/forgot", self.address))
+            .send()
+            .await
+            .expect("Failed to send GET request to /login/forgot")
+            .text()
+            .await
+            .unwrap()
+    }
+
+    /// For emails sent in the background: waits until at least `n` have been received.
+    pub async fn wait_for_emails(&self, n: usize) -> Vec<wiremock::Request> {
+        for _ in 0..500 {
+            let email_requests = self.email_server.received_requests().await.unwrap();
+            if email_requests.len() >= n {
+                return email_requests;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        }
+        panic!("{} emails haven't been sent within 5 seconds.", n);
+    }
+
+    pub async fn post_forgot_password(&self, email: &str) -> reqwest::Response {
+        self.http_client
+            .post(format!("{}/login/forgot", self.address))
+            .form(&serde_json::json!({ "email": email }))
+            .send()
+            .await
+            .expect("Failed to send POST request to /login/forgot")
+    }
+
+    pub async fn get_reset_password_html(&self, token: &str) -> String {
+        self.http_client
+            .get(format!("{}/login/reset", self.address))
+            .query(&[("token", token)])
+            .send()
+            .await
+            .expect("Failed to send GET request to /login/reset")
+            .text()
+            .await
+            .unwrap()
+    }
+
+    pub async fn post_reset_password<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.http_client
+            .post(format!("{}/login/reset", self.address))
+            .form(body)
+            .send()
+            .await
+            .expect("Failed to send POST request to /login/reset")
+    }
+
+    pub async fn get_sessions(&self) -> reqwest::Response {
+        self.http_client
+            .get(format!("{}/admin/sessions", self.address))
+            .send()
+            .await
+            .expect("Failed to send GET request to /admin/sessions")
+    }
+
+    pub async fn get_sessions_html(&self) -> String {
+        self.get_sessions().await.text().await.unwrap()
+    }
+
+    pub async fn post_revoke_session(&self, session_id: Uuid) -> reqwest::Response {
+        self.http_client
+            .post(format!(
+                "{}/admin/sessions/{}/revoke",
+                self.address, session_id
+            ))
+            .send()
+            .await
+            .expect("Failed to send POST request to revoke a session.")
+    }
+
+    pub async fn post_revoke_other_sessions(&self) -> reqwest::Response {
+        self.http_client
+            .post(format!("{}/admin/sessions/revoke-others", self.address))
+            .send()
+            .await
+            .expect("Failed to send POST request to revoke the other sessions.")
+    }
+
+    pub async fn get_audit_log(&self, query: &[(&str, &str)]) -> reqwest::Response {
+        self.http_client
+            .get(format!("{}/admin/audit", self.address))
+            .query(query)
+            .send()
+            .await
+            .expect("Failed to send GET request to /admin/audit")
+    }
+
+    pub async fn get_audit_log_html(&self, query: &[(&str, &str)]) -> String {
+        self.get_audit_log(query).await.text().await.unwrap()
+    }
+
+    pub async fn get_audit_log_export(&self, query: &[(&str, &str)]) -> reqwest::Response {
+        self.http_client
+            .get(format!("{}/admin/audit/export", self.address))
+            .query(query)
+            .send()
+            .await
+            .expect("Failed to send GET request to /admin/audit/export")
+    }
+
+    pub async fn get_users(&self) -> reqwest::Response {
+        self.http_client
+            .get(format!("{}/admin/users", self.address))
+            .send()
+            .await
+            .expect("Failed to send GET request to /admin/users")
+    }
+
+    pub async fn get_users_html(&self) -> String {
+        self.get_users().await.text().await.unwrap()
+    }
+
+    pub async fn post_add_user<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.http_client
+            .post(format!("{}/admin/users", self.address))
+            .form(body)
+            .send()
+            .await
+            .expect("Failed to send POST request to /admin/users")
+    }
+
+    /// `action` is one of `role`, `email`, `disable`, `enable` or `delete`.
+    pub async fn post_user_action<Body>(
+        &self,
+        user_id: Uuid,
+        action: &str,
+        body: &Body,
+    ) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.http_client
+            .post(format!(
+                "{}/admin/users/{}/{}",
+                self.address, user_id, action
+            ))
+            .form(body)
+            .send()
+            .await
+            .expect("Failed to send a user management request.")
+    }
 }
 
 static TRACING: Lazy<()> = Lazy::new(|| {
@@ -259,7 +805,8 @@ pub async fn spawn_app() -> TestApp {
 
     let application_port = application.port();
     let address = format!("http://127.0.0.1:{}", application.port());
-    tokio::spawn(application.run_until_stopped());
+    let shutdown = Shutdown::new();
+    tokio::spawn(application.run_until_stopped(shutdown.clone()));
 
     let http_client = reqwest::Client::builder()
         .redirect(reqwest::redirect::Policy::none())
@@ -274,7 +821,11 @@ pub async fn spawn_app() -> TestApp {
         port: application_port,
         test_user: TestUser::generate(),
         http_client,
-        email_client: configuration.email_client.client(),
+        email_client: configuration.email_client.clone().client(),
+        issue_delivery_settings: configuration.issue_delivery.clone(),
+        hmac_secret: configuration.application.hmac_secret.clone(),
+        shutdown,
+        configuration,
     };
 
     test_app_instance
@@ -293,16 +844,88 @@ async fn configure_db(settings: &DBSettings) -> PgPool {
     connection
         .execute(format!(r#"CREATE DATABASE "{}";"#, settings.db_name).as_str())
         .await
-        .expect(format!("Failed to create {} database", settings.db_name).as_str());
+        .unwrap_or_else(|_| panic!("Failed to create {} database", settings.db_name));
 
     let connection_pool = PgPool::connect_with(settings.with_db())
         .await
-        .expect(format!("Failed to connect to database {}", settings.db_name).as_str());
+        .unwrap_or_else(|_| panic!("Failed to connect to database {}", settings.db_name));
 
-    sqlx::migrate!("./migrations")
-        .run(&connection_pool)
+    migrate_database(&connection_pool)
         .await
         .expect("Failed to migrate the database");
 
     connection_pool
 }
+
+pub async fn create_unconfirmed_subscriber(app: &TestApp) -> ConfirmationLink {
+    let name = Name().fake::<String>();
+    // Fake addresses repeat often enough to collide when a test creates a dozen subscribers
+    let email = format!("{}-{}", Uuid::new_v4(), SafeEmail().fake::<String>());
+    let body = serde_urlencoded::to_string(serde_json::json!({
+        "name": name,
+        "email": email,
+    }))
+    .unwrap();
+
+    let _mock_guard = Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .named("Create unconfirmed subscriber")
+        .expect(1)
+        .mount_as_scoped(&app.email_server)
+        .await;
+
+    app.post_subscription(body)
+        .await
+        .error_for_status()
+        .unwrap();
+
+    let email_request = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+
+    app.get_confirmation_link_from_email_body(&email_request)
+}
+
+pub async fn create_confirmed_subscriber(app: &TestApp) {
+    let confirmation_link = create_unconfirmed_subscriber(app).await;
+
+    reqwest::get(confirmation_link.plain_text)
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+}
+
+/// Answer a request to Postmark's batch endpoint as if every email had been accepted.
+pub fn accept_every_email(request: &wiremock::Request) -> ResponseTemplate {
+    let emails: Vec<serde_json::Value> = serde_json::from_slice(&request.body).unwrap();
+    let results = emails
+        .iter()
+        .map(|_| serde_json::json!({ "ErrorCode": 0, "Message": "OK" }))
+        .collect::<Vec<_>>();
+
+    ResponseTemplate::new(200).set_body_json(results)
+}
+
+/// Publish a newsletter issue as the (already logged in) test user and return its ID.
+pub async fn publish_newsletter(app: &TestApp) -> Uuid {
+    let newsletter_request_body = serde_json::json!({
+        "title": "Newsletter Title",
+        "text_content": "Newsletter body as plain text",
+        "html_content": "<p>Newsletter body as plain text</p>",
+        "idempotency_key": Uuid::new_v4().to_string()
+    });
+    let response = app.post_publish_newsletter(&newsletter_request_body).await;
+    assert_eq!(response.status().as_u16(), 303);
+
+    sqlx::query!("SELECT id FROM newsletter_issues ORDER BY published_at DESC LIMIT 1")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .id
+}
@@ -0,0 +1,262 @@
+use uuid::Uuid;
+use wiremock::{
+    matchers::{method, path},
+    Mock, ResponseTemplate,
+};
+use zero2prod::domains::UnsubscribeToken;
+
+use crate::helpers::{
+    accept_every_email, create_confirmed_subscriber, publish_newsletter, spawn_app,
+};
+
+#[tokio::test]
+async fn must_be_logged_in_to_see_failed_deliveries() {
+    let app = spawn_app().await;
+
+    let response = app.get_issue_delivery_failures(Uuid::new_v4()).await;
+
+    assert_eq!(response.status().as_u16(), 303);
+    assert_eq!(response.headers().get("Location").unwrap(), "/login");
+}
+
+#[tokio::test]
+async fn must_be_logged_in_to_requeue_a_failed_delivery() {
+    let app = spawn_app().await;
+
+    let response = app
+        .post_requeue_failed_delivery(
+            Uuid::new_v4(),
+            &serde_json::json!({ "subscriber_email": "ursula@example.com" }),
+        )
+        .await;
+
+    assert_eq!(response.status().as_u16(), 303);
+    assert_eq!(response.headers().get("Location").unwrap(), "/login");
+}
+
+#[tokio::test]
+async fn rejected_deliveries_are_moved_to_the_dead_letter_table_without_retrying() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+
+    Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(422))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let issue_id = publish_newsletter(&app).await;
+    app.dispatch_all_pending_emails().await;
+
+    let n_queued = sqlx::query!(r#"SELECT COUNT(*) AS "count!" FROM issue_delivery_queue"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .count;
+    assert_eq!(n_queued, 0);
+
+    let failure = sqlx::query!(
+        "SELECT newsletter_issue_id, n_attempts, error_message FROM issue_delivery_failures"
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .expect("The rejected delivery should be recorded.");
+    assert_eq!(failure.newsletter_issue_id, issue_id);
+    assert_eq!(failure.n_attempts, 1);
+    assert!(failure.error_message.contains("422"));
+}
+
+#[tokio::test]
+async fn exhausted_deliveries_are_moved_to_the_dead_letter_table() {
+    let mut app = spawn_app().await;
+    app.issue_delivery_settings.base_backoff_seconds = 0;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+
+    Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(500))
+        .expect(app.issue_delivery_settings.max_attempts as u64)
+        .mount(&app.email_server)
+        .await;
+
+    publish_newsletter(&app).await;
+    app.dispatch_all_pending_emails().await;
+
+    let failure = sqlx::query!("SELECT n_attempts FROM issue_delivery_failures")
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("The exhausted delivery should be recorded.");
+    assert_eq!(failure.n_attempts, app.issue_delivery_settings.max_attempts);
+}
+
+#[tokio::test]
+async fn rate_limited_deliveries_are_postponed_without_counting_an_attempt() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+
+    Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(429).insert_header("Retry-After", "120"))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let issue_id = publish_newsletter(&app).await;
+    app.dispatch_all_pending_emails().await;
+
+    let task = sqlx::query!(
+        r#"
+        SELECT n_attempts, execute_after > now() + interval '100 seconds' AS "postponed!"
+        FROM issue_delivery_queue
+        "#
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .expect("The rate limited delivery should still be queued.");
+    assert_eq!(task.n_attempts, 0);
+    assert!(task.postponed);
+
+    let status = app.get_issue_delivery_status_json(issue_id).await;
+    assert_eq!(status["failed"], 0);
+    assert_eq!(status["pending"], 1);
+}
+
+#[tokio::test]
+async fn failed_deliveries_are_listed_and_can_be_requeued() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+
+    let subscriber_email = sqlx::query!("SELECT email FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .email;
+
+    let rejection_guard = Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(422))
+        .expect(1)
+        .mount_as_scoped(&app.email_server)
+        .await;
+
+    let issue_id = publish_newsletter(&app).await;
+    app.dispatch_all_pending_emails().await;
+    drop(rejection_guard);
+
+    let failures_html = app.get_issue_delivery_failures_html(issue_id).await;
+    assert!(failures_html.contains(&subscriber_email));
+
+    let response = app
+        .post_requeue_failed_delivery(
+            issue_id,
+            &serde_json::json!({ "subscriber_email": &subscriber_email }),
+        )
+        .await;
+    assert_eq!(response.status().as_u16(), 303);
+    assert_eq!(
+        response.headers().get("Location").unwrap(),
+        &format!("/admin/newsletters/{}/failures", issue_id)
+    );
+
+    let failures_html = app.get_issue_delivery_failures_html(issue_id).await;
+    assert!(failures_html.contains(&format!(
+        "The delivery to {} has been requeued.",
+        subscriber_email
+    )));
+    assert!(failures_html.contains("0 failed deliveries."));
+
+    Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        .respond_with(accept_every_email)
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    app.dispatch_all_pending_emails().await;
+}
+
+#[tokio::test]
+async fn deliveries_to_unsubscribed_subscribers_cannot_be_requeued() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+
+    let subscriber = sqlx::query!("SELECT id, email FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+
+    Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(422))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let issue_id = publish_newsletter(&app).await;
+    app.dispatch_all_pending_emails().await;
+
+    let token = UnsubscribeToken::generate(subscriber.id, &app.hmac_secret);
+    app.post_unsubscribe(token.as_ref()).await;
+
+    app.post_requeue_failed_delivery(
+        issue_id,
+        &serde_json::json!({ "subscriber_email": &subscriber.email }),
+    )
+    .await;
+
+    let failures_html = app.get_issue_delivery_failures_html(issue_id).await;
+    assert!(failures_html.contains(&format!(
+        "{} has unsubscribed, the delivery can't be requeued.",
+        subscriber.email
+    )));
+    assert!(failures_html.contains("1 failed deliveries."));
+}
+
+#[tokio::test]
+async fn each_email_of_a_batch_is_handled_individually() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+
+    // Postmark refuses the first email of the batch and accepts the other one
+    Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!([
+            { "ErrorCode": 406, "Message": "Inactive recipient" },
+            { "ErrorCode": 0, "Message": "OK" },
+        ])))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let issue_id = publish_newsletter(&app).await;
+    app.dispatch_all_pending_emails().await;
+
+    let status = app.get_issue_delivery_status_json(issue_id).await;
+    assert_eq!(status["delivered"], 1);
+    assert_eq!(status["failed"], 1);
+    assert_eq!(status["pending"], 0);
+
+    let failure = sqlx::query!("SELECT error_message FROM issue_delivery_failures")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert!(failure.error_message.contains("Inactive recipient"));
+}
+
+#[tokio::test]
+async fn failed_deliveries_page_returns_404_for_an_unknown_issue() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    let response = app.get_issue_delivery_failures(Uuid::new_v4()).await;
+
+    assert_eq!(response.status().as_u16(), 404);
+}
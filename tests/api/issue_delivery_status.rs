@@ -0,0 +1,105 @@
+use uuid::Uuid;
+use wiremock::{
+    matchers::{method, path},
+    Mock, ResponseTemplate,
+};
+
+use crate::helpers::{
+    accept_every_email, create_confirmed_subscriber, publish_newsletter, spawn_app,
+};
+
+#[tokio::test]
+async fn must_be_logged_in_to_see_the_delivery_status() {
+    let app = spawn_app().await;
+
+    let response = app.get_issue_delivery_status(Uuid::new_v4()).await;
+
+    assert_eq!(response.status().as_u16(), 303);
+    assert_eq!(response.headers().get("Location").unwrap(), "/login");
+}
+
+#[tokio::test]
+async fn delivery_status_returns_404_for_an_unknown_issue() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    let response = app.get_issue_delivery_status(Uuid::new_v4()).await;
+
+    assert_eq!(response.status().as_u16(), 404);
+}
+
+#[tokio::test]
+async fn delivery_status_tracks_the_progress_of_an_issue() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+
+    let issue_id = publish_newsletter(&app).await;
+
+    let status = app.get_issue_delivery_status_json(issue_id).await;
+    assert_eq!(status["total_recipients"], 2);
+    assert_eq!(status["delivered"], 0);
+    assert_eq!(status["failed"], 0);
+    assert_eq!(status["pending"], 2);
+    assert!(status["completed_at"].is_null());
+
+    Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        .respond_with(accept_every_email)
+        // Both emails go out in a single batch
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    app.dispatch_all_pending_emails().await;
+
+    let status = app.get_issue_delivery_status_json(issue_id).await;
+    assert_eq!(status["delivered"], 2);
+    assert_eq!(status["pending"], 0);
+    assert!(status["completed_at"].is_string());
+}
+
+#[tokio::test]
+async fn permanently_failed_deliveries_are_counted_as_failed() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+
+    Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(422))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let issue_id = publish_newsletter(&app).await;
+    app.dispatch_all_pending_emails().await;
+
+    let status = app.get_issue_delivery_status_json(issue_id).await;
+    assert_eq!(status["delivered"], 0);
+    assert_eq!(status["failed"], 1);
+    assert_eq!(status["pending"], 0);
+    assert!(status["completed_at"].is_string());
+
+    let status_html = app
+        .get_issue_delivery_status(issue_id)
+        .await
+        .text()
+        .await
+        .unwrap();
+    assert!(status_html.contains("Failed: 1"));
+    assert!(status_html.contains("Completed at"));
+}
+
+#[tokio::test]
+async fn an_issue_without_recipients_is_completed_right_away() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    let issue_id = publish_newsletter(&app).await;
+
+    let status = app.get_issue_delivery_status_json(issue_id).await;
+    assert_eq!(status["total_recipients"], 0);
+    assert!(status["completed_at"].is_string());
+}
@@ -0,0 +1,271 @@
+use std::time::Duration;
+
+use chrono::Utc;
+use secrecy::Secret;
+use sqlx::postgres::PgListener;
+use uuid::Uuid;
+use wiremock::{
+    matchers::{method, path},
+    Mock, ResponseTemplate,
+};
+use zero2prod::{
+    configuration::RateLimitSettings,
+    domains::SubscriberEmail,
+    email_client::{EmailClient, PostmarkTransport, RateLimiter},
+    issue_delivery_worker::{try_execute_task, ExecutionOutcome, ISSUE_DELIVERY_CHANNEL},
+    shutdown::Shutdown,
+};
+
+use crate::helpers::{
+    accept_every_email, create_confirmed_subscriber, publish_newsletter, spawn_app, TestApp,
+};
+
+/// Long enough for the tests to fail if the workers fall back to polling.
+const ONE_HOUR_IN_MILLISECONDS: u64 = 60 * 60 * 1000;
+
+async fn wait_until_delivered(app: &TestApp, issue_id: Uuid, timeout: Duration) -> bool {
+    let deadline = tokio::time::Instant::now() + timeout;
+    while tokio::time::Instant::now() < deadline {
+        let status = app.get_issue_delivery_status_json(issue_id).await;
+        if status["completed_at"].is_string() {
+            return true;
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+    false
+}
+
+#[tokio::test]
+async fn publishing_an_issue_notifies_the_delivery_workers() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+
+    let mut listener = PgListener::connect_with(&app.db_pool).await.unwrap();
+    listener.listen(ISSUE_DELIVERY_CHANNEL).await.unwrap();
+
+    publish_newsletter(&app).await;
+
+    let notification = tokio::time::timeout(Duration::from_secs(5), listener.recv())
+        .await
+        .expect("No notification was sent")
+        .unwrap();
+    assert_eq!(notification.channel(), ISSUE_DELIVERY_CHANNEL);
+}
+
+#[tokio::test]
+async fn idle_workers_are_woken_up_by_a_published_issue() {
+    let mut app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+
+    Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        .respond_with(accept_every_email)
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    app.issue_delivery_settings
+        .empty_queue_poll_interval_milliseconds = ONE_HOUR_IN_MILLISECONDS;
+    app.spawn_delivery_workers(&Shutdown::new());
+    // Let the workers find the queue empty and go idle
+    tokio::time::sleep(Duration::from_millis(500)).await;
+
+    let issue_id = publish_newsletter(&app).await;
+
+    assert!(wait_until_delivered(&app, issue_id, Duration::from_secs(5)).await);
+}
+
+#[tokio::test]
+async fn more_workers_than_default_pool_connections_deliver_an_issue() {
+    let mut app = spawn_app().await;
+    for _ in 0..12 {
+        create_confirmed_subscriber(&app).await;
+    }
+    app.test_user.login(&app).await;
+
+    Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        .respond_with(accept_every_email)
+        .expect(12)
+        .mount(&app.email_server)
+        .await;
+
+    app.issue_delivery_settings.n_workers = 12;
+    app.issue_delivery_settings.batch_size = 1;
+    app.spawn_delivery_workers(&Shutdown::new());
+
+    let issue_id = publish_newsletter(&app).await;
+
+    assert!(wait_until_delivered(&app, issue_id, Duration::from_secs(10)).await);
+}
+
+#[tokio::test]
+async fn workers_claim_no_more_deliveries_than_the_rate_limit_lets_out() {
+    let mut app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+
+    Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        .respond_with(accept_every_email)
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    app.email_client = EmailClient::new(
+        SubscriberEmail::parse("newsletter@example.com".into()).unwrap(),
+        Box::new(PostmarkTransport::new(
+            app.email_server.uri(),
+            Secret::new("token".into()),
+            Duration::from_secs(2),
+        )),
+    )
+    .with_rate_limiter(RateLimiter::new(&RateLimitSettings {
+        messages_per_second: None,
+        messages_per_hour: Some(1),
+    }));
+    publish_newsletter(&app).await;
+
+    let mut outcomes = Vec::new();
+    for _ in 0..2 {
+        outcomes.push(
+            try_execute_task(
+                &app.email_client,
+                &app.db_pool,
+                &app.issue_delivery_settings,
+                &app.address,
+                &app.hmac_secret,
+            )
+            .await
+            .unwrap(),
+        );
+    }
+
+    assert!(matches!(outcomes[0], ExecutionOutcome::TaskCompleted));
+    assert!(matches!(
+        outcomes[1],
+        ExecutionOutcome::RateLimited { retry_after } if retry_after > Duration::from_secs(60)
+    ));
+    // The other delivery is still in the queue, unclaimed and untouched
+    let queued = sqlx::query!("SELECT n_attempts FROM issue_delivery_queue")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(queued.len(), 1);
+    assert_eq!(queued[0].n_attempts, 0);
+}
+
+#[tokio::test]
+async fn idle_workers_wake_up_when_a_scheduled_issue_is_due() {
+    let mut app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+
+    Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        .respond_with(accept_every_email)
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    app.issue_delivery_settings
+        .empty_queue_poll_interval_milliseconds = ONE_HOUR_IN_MILLISECONDS;
+    app.spawn_delivery_workers(&Shutdown::new());
+    tokio::time::sleep(Duration::from_millis(500)).await;
+
+    let send_at = Utc::now() + chrono::Duration::seconds(2);
+    let response = app
+        .post_publish_newsletter(&serde_json::json!({
+            "title": "Newsletter Title",
+            "text_content": "Newsletter body as plain text",
+            "html_content": "<p>Newsletter body as plain text</p>",
+            "idempotency_key": Uuid::new_v4().to_string(),
+            "send_at": send_at.to_rfc3339(),
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 303);
+    let issue_id = sqlx::query!("SELECT id FROM newsletter_issues")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .id;
+
+    // Nothing notifies the workers when the issue becomes due
+    assert!(wait_until_delivered(&app, issue_id, Duration::from_secs(10)).await);
+}
+
+#[tokio::test]
+async fn workers_finish_their_in_flight_batch_on_shutdown() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+
+    Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        .respond_with(|request: &wiremock::Request| {
+            accept_every_email(request).set_delay(Duration::from_secs(1))
+        })
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let issue_id = publish_newsletter(&app).await;
+    let shutdown = Shutdown::new();
+    let workers = app.spawn_delivery_workers(&shutdown);
+    while !app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .iter()
+        .any(|request| request.url.path() == "/email/batch")
+    {
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+
+    // The batch is being sent
+    shutdown.trigger();
+    tokio::time::timeout(Duration::from_secs(10), workers)
+        .await
+        .expect("The workers didn't stop")
+        .unwrap()
+        .unwrap();
+
+    let status = app.get_issue_delivery_status_json(issue_id).await;
+    assert_eq!(status["delivered"], 1);
+    assert_eq!(status["pending"], 0);
+}
+
+#[tokio::test]
+async fn workers_stop_claiming_deliveries_on_shutdown() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+
+    Mock::given(path("/email/batch"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    let shutdown = Shutdown::new();
+    let workers = app.spawn_delivery_workers(&shutdown);
+    // Let the workers find the queue empty and go idle
+    tokio::time::sleep(Duration::from_millis(500)).await;
+
+    shutdown.trigger();
+    tokio::time::timeout(Duration::from_secs(1), workers)
+        .await
+        .expect("Idle workers should stop right away")
+        .unwrap()
+        .unwrap();
+
+    let issue_id = publish_newsletter(&app).await;
+    tokio::time::sleep(Duration::from_millis(500)).await;
+
+    let status = app.get_issue_delivery_status_json(issue_id).await;
+    assert_eq!(status["pending"], 1);
+}
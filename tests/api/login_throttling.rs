@@ -0,0 +1,167 @@
+use crate::helpers::{spawn_app, TestApp};
+
+async fn post_password(app: &TestApp, password: &str) -> reqwest::Response {
+    app.post_login(&serde_json::json!({
+        "username": &app.test_user.username,
+        "password": password,
+    }))
+    .await
+}
+
+fn assert_is_redirect_to(response: &reqwest::Response, location: &str) {
+    assert_eq!(response.status().as_u16(), 303);
+    assert_eq!(response.headers().get("Location").unwrap(), location);
+}
+
+/// Instead of waiting for them to run out.
+async fn expire_login_lockouts(app: &TestApp) {
+    sqlx::query!("UPDATE login_throttles SET locked_until = now()")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+}
+
+#[tokio::test]
+async fn logins_are_delayed_after_a_few_failed_attempts() {
+    let app = spawn_app().await;
+
+    // 3 free attempts, then the 4th one gets the next attempt delayed
+    for _ in 0..4 {
+        assert_is_redirect_to(&post_password(&app, "wrong-password").await, "/login");
+        assert!(app.get_login_html().await.contains("Invalid credentials."));
+    }
+
+    // Even with the right password
+    let response = post_password(&app, &app.test_user.password).await;
+    assert_is_redirect_to(&response, "/login");
+    assert!(app
+        .get_login_html()
+        .await
+        .contains("Too many failed login attempts, please try again in 1 second."));
+    assert_is_redirect_to(&app.get_admin_dashboard().await, "/login");
+}
+
+#[tokio::test]
+async fn the_delay_doubles_with_every_failed_attempt() {
+    let app = spawn_app().await;
+    for _ in 0..4 {
+        post_password(&app, "wrong-password").await;
+    }
+    expire_login_lockouts(&app).await;
+
+    post_password(&app, "wrong-password").await;
+    post_password(&app, &app.test_user.password).await;
+
+    assert!(app
+        .get_login_html()
+        .await
+        .contains("please try again in 2 seconds."));
+}
+
+#[tokio::test]
+async fn a_successful_login_clears_the_failed_attempts_of_the_username_only() {
+    let app = spawn_app().await;
+    for _ in 0..4 {
+        post_password(&app, "wrong-password").await;
+    }
+    expire_login_lockouts(&app).await;
+
+    let response = post_password(&app, &app.test_user.password).await;
+    assert_is_redirect_to(&response, "/admin/dashboard");
+
+    // Otherwise an attacker could reset their IP by logging into their own account
+    let throttle_keys: Vec<String> = sqlx::query!("SELECT throttle_key FROM login_throttles")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|row| row.throttle_key)
+        .collect();
+    assert_eq!(throttle_keys, vec!["ip:127.0.0.1".to_string()]);
+}
+
+#[tokio::test]
+async fn reaching_the_maximum_locks_the_username_out() {
+    let app = spawn_app().await;
+    sqlx::query!(
+        r#"
+        INSERT INTO login_throttles (throttle_key, n_failures, last_failure_at)
+        VALUES ($1, 9, now())
+    "#,
+        format!("username:{}", app.test_user.username)
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+
+    post_password(&app, "wrong-password").await;
+    post_password(&app, &app.test_user.password).await;
+
+    assert!(app
+        .get_login_html()
+        .await
+        .contains("please try again in 15 minutes."));
+}
+
+#[tokio::test]
+async fn a_locked_out_client_ip_cannot_log_into_any_account() {
+    let app = spawn_app().await;
+    sqlx::query!(
+        r#"
+        INSERT INTO login_throttles (throttle_key, n_failures, last_failure_at, locked_until)
+        VALUES ('ip:127.0.0.1', 50, now(), now() + interval '15 minutes')
+    "#
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+
+    let response = post_password(&app, &app.test_user.password).await;
+
+    assert_is_redirect_to(&response, "/login");
+    assert!(app
+        .get_login_html()
+        .await
+        .contains("Too many failed login attempts"));
+}
+
+#[tokio::test]
+async fn failed_logins_are_audited() {
+    let app = spawn_app().await;
+    for _ in 0..5 {
+        post_password(&app, "wrong-password").await;
+    }
+
+    let failed_logins =
+        sqlx::query!("SELECT username, client_ip, reason FROM failed_logins ORDER BY attempted_at")
+            .fetch_all(&app.db_pool)
+            .await
+            .unwrap();
+
+    assert_eq!(failed_logins.len(), 5);
+    assert!(failed_logins
+        .iter()
+        .all(|f| f.username == app.test_user.username && f.client_ip == "127.0.0.1"));
+    assert_eq!(failed_logins[0].reason, "invalid_credentials");
+    // The 5th attempt came during the delay
+    assert_eq!(failed_logins[4].reason, "locked_out");
+}
+
+#[tokio::test]
+async fn failed_logins_older_than_a_lockout_are_deleted() {
+    let app = spawn_app().await;
+    post_password(&app, "wrong-password").await;
+    sqlx::query!("UPDATE failed_logins SET attempted_at = now() - interval '1 day'")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+
+    post_password(&app, "wrong-password").await;
+
+    let n_failed_logins = sqlx::query!(r#"SELECT COUNT(*) AS "n!" FROM failed_logins"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .n;
+    assert_eq!(n_failed_logins, 1);
+}
@@ -1,8 +1,23 @@
 mod admin_dashboard;
+mod admin_users;
+mod audit_log;
 mod change_password;
 mod health_check;
 mod helpers;
+mod issue_delivery_failures;
+mod issue_delivery_status;
+mod issue_delivery_worker;
 mod login;
+mod login_throttling;
+mod newsletter_archive;
+mod newsletter_drafts;
 mod newsletters;
+mod password_reset;
+mod scheduled_newsletters;
+mod subscription_purge;
 mod subscriptions;
 mod subscriptions_confirm;
+mod subscriptions_unsubscribe;
+mod two_factor;
+mod user_management;
+mod user_sessions;
@@ -0,0 +1,141 @@
+use uuid::Uuid;
+
+use crate::helpers::{publish_newsletter, spawn_app, TestApp};
+
+async fn insert_issue(app: &TestApp, title: &str, status: &str, published_in: &str) -> Uuid {
+    let issue_id = Uuid::new_v4();
+    sqlx::query!(
+        r#"
+        INSERT INTO newsletter_issues (id, title, text_content, html_content, published_at, status)
+        VALUES ($1, $2, 'Plain text body', '<p>HTML body</p>', now() + $3::text::interval, $4)
+    "#,
+        issue_id,
+        title,
+        published_in,
+        status
+    )
+    .execute(&app.db_pool)
+    .await
+    .expect("Failed to insert newsletter issue.");
+
+    issue_id
+}
+
+#[tokio::test]
+async fn must_be_logged_in_to_see_the_newsletter_history() {
+    let app = spawn_app().await;
+
+    let response = app.get_newsletter_history(None).await;
+
+    assert_eq!(response.status().as_u16(), 303);
+    assert_eq!(response.headers().get("Location").unwrap(), "/login");
+}
+
+#[tokio::test]
+async fn newsletter_history_lists_published_issues_with_their_delivery_stats() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    let issue_id = publish_newsletter(&app).await;
+    insert_issue(&app, "A draft", "draft", "0 seconds").await;
+
+    let history_html = app.get_newsletter_history(None).await.text().await.unwrap();
+
+    assert!(history_html.contains(&format!(
+        r#"<a href="/admin/newsletters/{}">Newsletter Title</a>"#,
+        issue_id
+    )));
+    assert!(!history_html.contains("A draft"));
+    assert!(history_html.contains("Page 1 of 1"));
+}
+
+#[tokio::test]
+async fn newsletter_history_is_paginated() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    for i in 0..21 {
+        insert_issue(
+            &app,
+            &format!("Issue #{}", i),
+            "published",
+            &format!("-{} days", i),
+        )
+        .await;
+    }
+
+    let first_page = app.get_newsletter_history(None).await.text().await.unwrap();
+    assert!(first_page.contains("Issue #0<"));
+    assert!(!first_page.contains("Issue #20<"));
+    assert!(first_page.contains("Page 1 of 2"));
+    assert!(first_page.contains(r#"href="/admin/newsletters/history?page=2""#));
+
+    let second_page = app
+        .get_newsletter_history(Some(2))
+        .await
+        .text()
+        .await
+        .unwrap();
+    assert!(second_page.contains("Issue #20<"));
+    assert!(!second_page.contains("Issue #0<"));
+    assert!(second_page.contains("Page 2 of 2"));
+
+    let response = app.get_newsletter_history(Some(0)).await;
+    assert_eq!(response.status().as_u16(), 400);
+}
+
+#[tokio::test]
+async fn newsletter_history_rejects_pages_past_any_possible_offset() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    let response = app.get_newsletter_history(Some(i64::MAX)).await;
+
+    assert_eq!(response.status().as_u16(), 400);
+}
+
+#[tokio::test]
+async fn public_archive_only_lists_issues_that_went_out() {
+    let app = spawn_app().await;
+
+    let published_id = insert_issue(&app, "Published issue", "published", "-1 day").await;
+    let scheduled_id = insert_issue(&app, "Scheduled issue", "published", "1 day").await;
+    insert_issue(&app, "A draft", "draft", "-1 day").await;
+
+    let response = app.get_archive().await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let archive_html = response.text().await.unwrap();
+    assert!(archive_html.contains(&format!(
+        r#"<a href="/archive/{}">Published issue</a>"#,
+        published_id
+    )));
+    assert!(!archive_html.contains("Scheduled issue"));
+    assert!(!archive_html.contains("A draft"));
+
+    let response = app.get_archived_issue(scheduled_id).await;
+    assert_eq!(response.status().as_u16(), 404);
+}
+
+#[tokio::test]
+async fn archived_issues_render_their_html_content() {
+    let app = spawn_app().await;
+
+    let issue_id = insert_issue(&app, "Published issue", "published", "-1 day").await;
+
+    let response = app.get_archived_issue(issue_id).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let issue_html = response.text().await.unwrap();
+    assert!(issue_html.contains("<h1>Published issue</h1>"));
+    assert!(issue_html.contains("<p>HTML body</p>"));
+}
+
+#[tokio::test]
+async fn archived_issue_returns_404_for_an_unknown_issue() {
+    let app = spawn_app().await;
+
+    let response = app.get_archived_issue(Uuid::new_v4()).await;
+
+    assert_eq!(response.status().as_u16(), 404);
+}
@@ -0,0 +1,210 @@
+use uuid::Uuid;
+use wiremock::{
+    matchers::{any, method, path},
+    Mock, ResponseTemplate,
+};
+
+use crate::helpers::{accept_every_email, create_confirmed_subscriber, spawn_app, TestApp};
+
+async fn create_draft(app: &TestApp) -> Uuid {
+    let response = app
+        .post_create_draft(&serde_json::json!({
+            "title": "Draft Title",
+            "text_content": "Draft body as plain text",
+            "html_content": "<p>Draft body as HTML</p>",
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 303);
+
+    let location = response
+        .headers()
+        .get("Location")
+        .unwrap()
+        .to_str()
+        .unwrap();
+    location
+        .strip_prefix("/admin/newsletters/drafts/")
+        .unwrap()
+        .parse()
+        .unwrap()
+}
+
+#[tokio::test]
+async fn must_be_logged_in_to_manage_drafts() {
+    let app = spawn_app().await;
+
+    let response = app
+        .post_create_draft(&serde_json::json!({
+            "title": "Draft Title",
+            "text_content": "Draft body as plain text",
+            "html_content": "<p>Draft body as HTML</p>",
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 303);
+    assert_eq!(response.headers().get("Location").unwrap(), "/login");
+
+    let response = app.get_edit_draft(Uuid::new_v4()).await;
+    assert_eq!(response.status().as_u16(), 303);
+    assert_eq!(response.headers().get("Location").unwrap(), "/login");
+}
+
+#[tokio::test]
+async fn drafts_can_be_created_listed_and_edited() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    let draft_id = create_draft(&app).await;
+    assert!(app.get_drafts_html().await.contains("Draft Title"));
+
+    let response = app
+        .post_update_draft(
+            draft_id,
+            &serde_json::json!({
+                "title": "Edited Title",
+                "text_content": "Edited body",
+                "html_content": "<p>Edited body</p>",
+            }),
+        )
+        .await;
+    assert_eq!(response.status().as_u16(), 303);
+
+    let edit_html = app.get_edit_draft(draft_id).await.text().await.unwrap();
+    assert!(edit_html.contains("The draft has been saved."));
+    assert!(edit_html.contains(r#"value="Edited Title""#));
+    assert!(edit_html.contains("&lt;p&gt;Edited body&lt;/p&gt;"));
+}
+
+#[tokio::test]
+async fn drafts_are_not_delivered() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    create_draft(&app).await;
+    app.dispatch_all_pending_emails().await;
+}
+
+#[tokio::test]
+async fn drafts_can_be_previewed_as_html_and_plain_text() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    let draft_id = create_draft(&app).await;
+
+    let response = app.get_draft_preview(draft_id, "html").await;
+    assert!(response
+        .headers()
+        .get("Content-Type")
+        .unwrap()
+        .to_str()
+        .unwrap()
+        .starts_with("text/html"));
+    assert_eq!(response.text().await.unwrap(), "<p>Draft body as HTML</p>");
+
+    let response = app.get_draft_preview(draft_id, "text").await;
+    assert!(response
+        .headers()
+        .get("Content-Type")
+        .unwrap()
+        .to_str()
+        .unwrap()
+        .starts_with("text/plain"));
+    assert_eq!(response.text().await.unwrap(), "Draft body as plain text");
+}
+
+#[tokio::test]
+async fn a_test_copy_is_sent_only_to_the_given_address() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+
+    let draft_id = create_draft(&app).await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app
+        .post_send_test_draft(
+            draft_id,
+            &serde_json::json!({ "email": "editor@example.com" }),
+        )
+        .await;
+    assert_eq!(response.status().as_u16(), 303);
+
+    let edit_html = app.get_edit_draft(draft_id).await.text().await.unwrap();
+    assert!(edit_html.contains("A test copy has been sent to editor@example.com."));
+
+    let email_request = &app.email_server.received_requests().await.unwrap()[1];
+    let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+    assert_eq!(body["To"], "editor@example.com");
+    assert_eq!(body["Subject"], "[Test] Draft Title");
+
+    app.dispatch_all_pending_emails().await;
+}
+
+#[tokio::test]
+async fn publishing_a_draft_delivers_it_to_confirmed_subscribers() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+
+    let draft_id = create_draft(&app).await;
+
+    Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        .respond_with(accept_every_email)
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let body = serde_json::json!({ "idempotency_key": Uuid::new_v4().to_string() });
+    let response = app.post_publish_draft(draft_id, &body).await;
+    assert_eq!(response.status().as_u16(), 303);
+    assert_eq!(
+        response.headers().get("Location").unwrap(),
+        "/admin/newsletters"
+    );
+
+    // Publishing is idempotent
+    let response = app.post_publish_draft(draft_id, &body).await;
+    assert_eq!(response.status().as_u16(), 303);
+
+    let publish_newsletter_html = app.get_publish_newsletter_html().await;
+    assert!(publish_newsletter_html
+        .contains("The newsletter issue has been accepted, emails will go out shortly."));
+
+    // It is no longer a draft
+    let response = app.get_edit_draft(draft_id).await;
+    assert_eq!(response.status().as_u16(), 404);
+
+    app.dispatch_all_pending_emails().await;
+}
+
+#[tokio::test]
+async fn drafts_can_be_deleted() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    let draft_id = create_draft(&app).await;
+
+    let response = app.post_delete_draft(draft_id).await;
+    assert_eq!(response.status().as_u16(), 303);
+    assert_eq!(
+        response.headers().get("Location").unwrap(),
+        "/admin/newsletters/drafts"
+    );
+
+    let drafts_html = app.get_drafts_html().await;
+    assert!(drafts_html.contains("The draft has been deleted."));
+    assert!(!drafts_html.contains("Draft Title"));
+}
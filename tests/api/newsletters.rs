@@ -1,16 +1,14 @@
 use std::time::Duration;
 
-use fake::{
-    faker::{internet::en::SafeEmail, name::en::Name},
-    Fake,
-};
 use uuid::Uuid;
 use wiremock::{
     matchers::{any, method, path},
-    Mock, ResponseTemplate,
+    Mock, Request, ResponseTemplate,
 };
 
-use crate::helpers::{spawn_app, ConfirmationLink, TestApp};
+use crate::helpers::{
+    accept_every_email, create_confirmed_subscriber, create_unconfirmed_subscriber, spawn_app,
+};
 
 #[tokio::test]
 async fn must_be_logged_in_to_see_newsletter_issue_form() {
@@ -98,9 +96,9 @@ async fn newsletters_are_delivered_to_confirmed_subscribers() {
         "/admin/dashboard"
     );
 
-    Mock::given(path("/email"))
+    Mock::given(path("/email/batch"))
         .and(method("POST"))
-        .respond_with(ResponseTemplate::new(200))
+        .respond_with(accept_every_email)
         .expect(1)
         .mount(&app.email_server)
         .await;
@@ -133,9 +131,9 @@ async fn newsletter_creation_is_idempotent() {
     create_confirmed_subscriber(&app).await;
     app.test_user.login(&app).await;
 
-    Mock::given(path("/email"))
+    Mock::given(path("/email/batch"))
         .and(method("POST"))
-        .respond_with(ResponseTemplate::new(200))
+        .respond_with(accept_every_email)
         .expect(1)
         .mount(&app.email_server)
         .await;
@@ -177,9 +175,11 @@ async fn concurrent_form_submission_is_handled_gracefully() {
     create_confirmed_subscriber(&app).await;
     app.test_user.login(&app).await;
 
-    Mock::given(path("/email"))
+    Mock::given(path("/email/batch"))
         .and(method("POST"))
-        .respond_with(ResponseTemplate::new(200).set_delay(Duration::from_secs(2)))
+        .respond_with(|request: &Request| {
+            accept_every_email(request).set_delay(Duration::from_secs(2))
+        })
         .expect(1)
         .mount(&app.email_server)
         .await;
@@ -258,45 +258,178 @@ async fn returns_400_given_invalid_body() {
     }
 }
 
-async fn create_unconfirmed_subscriber(app: &TestApp) -> ConfirmationLink {
-    let name = Name().fake::<String>();
-    let email = SafeEmail().fake::<String>();
-    let body = serde_urlencoded::to_string(serde_json::json!({
-        "name": name,
-        "email": email,
-    }))
-    .unwrap();
+#[tokio::test]
+async fn an_empty_idempotency_key_is_rejected_with_400() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    let response = app
+        .post_publish_newsletter(&serde_json::json!({
+            "title": "Newsletter title",
+            "text_content": "Newsletter body as plain text",
+            "html_content": "<p>Newsletter body as plain text</p>",
+            "idempotency_key": ""
+        }))
+        .await;
+
+    assert_eq!(response.status().as_u16(), 400);
+}
+
+#[tokio::test]
+async fn failed_deliveries_are_rescheduled_with_a_backoff() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
 
-    let _mock_guard = Mock::given(path("/email"))
+    Mock::given(path("/email/batch"))
         .and(method("POST"))
-        .respond_with(ResponseTemplate::new(200))
-        .named("Create unconfirmed subscriber")
+        .respond_with(ResponseTemplate::new(500))
         .expect(1)
-        .mount_as_scoped(&app.email_server)
+        .mount(&app.email_server)
         .await;
 
-    app.post_subscription(body.into())
-        .await
-        .error_for_status()
-        .unwrap();
+    let newsletter_request_body = serde_json::json!({
+        "title": "Newsletter Title",
+        "text_content": "Newsletter body as plain text",
+        "html_content": "<p>Newsletter body as plain text</p>",
+        "idempotency_key": Uuid::new_v4().to_string()
+    });
+    let response = app.post_publish_newsletter(&newsletter_request_body).await;
+    assert_eq!(response.status().as_u16(), 303);
 
-    let email_request = app
-        .email_server
-        .received_requests()
-        .await
-        .unwrap()
-        .pop()
-        .unwrap();
+    app.dispatch_all_pending_emails().await;
+
+    let task = sqlx::query!(
+        "SELECT n_attempts, execute_after > now() AS \"is_delayed!\" FROM issue_delivery_queue"
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .expect("The failed delivery should still be in the queue.");
 
-    app.get_confirmation_link_from_email_body(&email_request)
+    assert_eq!(task.n_attempts, 1);
+    assert!(task.is_delayed);
 }
 
-async fn create_confirmed_subscriber(app: &TestApp) {
-    let confirmation_link = create_unconfirmed_subscriber(app).await;
+#[tokio::test]
+async fn deliveries_are_given_up_after_max_attempts() {
+    let mut app = spawn_app().await;
+    app.issue_delivery_settings.base_backoff_seconds = 0;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+
+    Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(500))
+        .expect(app.issue_delivery_settings.max_attempts as u64)
+        .mount(&app.email_server)
+        .await;
+
+    let newsletter_request_body = serde_json::json!({
+        "title": "Newsletter Title",
+        "text_content": "Newsletter body as plain text",
+        "html_content": "<p>Newsletter body as plain text</p>",
+        "idempotency_key": Uuid::new_v4().to_string()
+    });
+    let response = app.post_publish_newsletter(&newsletter_request_body).await;
+    assert_eq!(response.status().as_u16(), 303);
+
+    app.dispatch_all_pending_emails().await;
 
-    reqwest::get(confirmation_link.plain_text)
+    let n_queued = sqlx::query!(r#"SELECT COUNT(*) AS "count!" FROM issue_delivery_queue"#)
+        .fetch_one(&app.db_pool)
         .await
         .unwrap()
-        .error_for_status()
-        .unwrap();
+        .count;
+    assert_eq!(n_queued, 0);
+}
+
+#[tokio::test]
+async fn newsletters_are_delivered_in_batches() {
+    let mut app = spawn_app().await;
+    app.issue_delivery_settings.batch_size = 2;
+    for _ in 0..3 {
+        create_confirmed_subscriber(&app).await;
+    }
+    app.test_user.login(&app).await;
+
+    Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        .respond_with(accept_every_email)
+        .expect(2)
+        .mount(&app.email_server)
+        .await;
+
+    let newsletter_request_body = serde_json::json!({
+        "title": "Newsletter Title",
+        "text_content": "Newsletter body as plain text",
+        "html_content": "<p>Newsletter body as plain text</p>",
+        "idempotency_key": Uuid::new_v4().to_string()
+    });
+    let response = app.post_publish_newsletter(&newsletter_request_body).await;
+    assert_eq!(response.status().as_u16(), 303);
+
+    app.dispatch_all_pending_emails().await;
+
+    let mut recipients = Vec::new();
+    for request in app.email_server.received_requests().await.unwrap() {
+        if request.url.path() != "/email/batch" {
+            continue;
+        }
+        let batch: Vec<serde_json::Value> = serde_json::from_slice(&request.body).unwrap();
+        assert!(batch.len() <= 2);
+        recipients.extend(batch.into_iter().map(|email| email["To"].clone()));
+    }
+    recipients.sort_by_key(|to| to.to_string());
+    recipients.dedup();
+    assert_eq!(recipients.len(), 3);
+}
+
+#[tokio::test]
+async fn concurrent_workers_never_deliver_an_issue_twice_to_the_same_subscriber() {
+    let mut app = spawn_app().await;
+    app.issue_delivery_settings.batch_size = 1;
+    for _ in 0..8 {
+        create_confirmed_subscriber(&app).await;
+    }
+    app.test_user.login(&app).await;
+
+    // Slow sends keep the tasks locked long enough for the workers to overlap
+    Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        .respond_with(|request: &Request| {
+            accept_every_email(request).set_delay(Duration::from_millis(200))
+        })
+        .expect(8)
+        .mount(&app.email_server)
+        .await;
+
+    let newsletter_request_body = serde_json::json!({
+        "title": "Newsletter Title",
+        "text_content": "Newsletter body as plain text",
+        "html_content": "<p>Newsletter body as plain text</p>",
+        "idempotency_key": Uuid::new_v4().to_string()
+    });
+    let response = app.post_publish_newsletter(&newsletter_request_body).await;
+    assert_eq!(response.status().as_u16(), 303);
+
+    tokio::join!(
+        app.dispatch_all_pending_emails(),
+        app.dispatch_all_pending_emails(),
+        app.dispatch_all_pending_emails(),
+        app.dispatch_all_pending_emails(),
+    );
+
+    let mut recipients = Vec::new();
+    for request in app.email_server.received_requests().await.unwrap() {
+        if request.url.path() != "/email/batch" {
+            continue;
+        }
+        let batch: Vec<serde_json::Value> = serde_json::from_slice(&request.body).unwrap();
+        recipients.extend(batch.into_iter().map(|email| email["To"].to_string()));
+    }
+    let n_sent = recipients.len();
+    recipients.sort();
+    recipients.dedup();
+    assert_eq!(n_sent, 8);
+    assert_eq!(recipients.len(), 8);
 }
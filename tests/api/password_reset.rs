@@ -0,0 +1,338 @@
+use wiremock::{
+    matchers::{method, path},
+    Mock, ResponseTemplate,
+};
+use zero2prod::{authentication::set_email, domains::SubscriberEmail};
+
+use crate::helpers::{spawn_app, TestApp};
+
+const EMAIL: &str = "ursula@example.com";
+const NEW_PASSWORD: &str = "a-brand-new-password";
+const SENT_MESSAGE: &str =
+    "If an account uses this email address, a link to reset its password has been sent to it.";
+const INVALID_LINK_MESSAGE: &str = "This password reset link is invalid or has expired.";
+
+fn assert_is_redirect_to(response: &reqwest::Response, location: &str) {
+    assert_eq!(response.status().as_u16(), 303);
+    assert_eq!(response.headers().get("Location").unwrap(), location);
+}
+
+async fn give_the_test_user_an_email(app: &TestApp) {
+    let email = SubscriberEmail::parse(EMAIL.into()).unwrap();
+    set_email(app.test_user.id, Some(&email), &app.db_pool)
+        .await
+        .unwrap();
+}
+
+/// Asks for a reset link and returns the token it carries.
+async fn request_reset_token(app: &TestApp) -> String {
+    let _mock_guard = Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount_as_scoped(&app.email_server)
+        .await;
+
+    let n_emails_before = app.email_server.received_requests().await.unwrap().len();
+    let response = app.post_forgot_password(EMAIL).await;
+    assert_is_redirect_to(&response, "/login/forgot");
+
+    let email_request = app
+        .wait_for_emails(n_emails_before + 1)
+        .await
+        .pop()
+        .unwrap();
+    let reset_link = app.get_confirmation_link_from_email_body(&email_request);
+    assert_eq!(reset_link.html, reset_link.plain_text);
+    assert_eq!(reset_link.html.path(), "/login/reset");
+
+    reset_link
+        .html
+        .query_pairs()
+        .find(|(key, _)| key == "token")
+        .unwrap()
+        .1
+        .into_owned()
+}
+
+async fn post_new_password(app: &TestApp, token: &str) -> reqwest::Response {
+    app.post_reset_password(&serde_json::json!({
+        "token": token,
+        "new_password": NEW_PASSWORD,
+        "new_password_check": NEW_PASSWORD,
+    }))
+    .await
+}
+
+async fn login(app: &TestApp, password: &str) -> reqwest::Response {
+    app.post_login(&serde_json::json!({
+        "username": &app.test_user.username,
+        "password": password,
+    }))
+    .await
+}
+
+#[tokio::test]
+async fn the_login_page_links_to_the_forgot_password_form() {
+    let app = spawn_app().await;
+
+    assert!(app
+        .get_login_html()
+        .await
+        .contains(r#"href="/login/forgot""#));
+}
+
+#[tokio::test]
+async fn a_reset_link_lets_the_user_choose_a_new_password() {
+    let app = spawn_app().await;
+    give_the_test_user_an_email(&app).await;
+
+    let token = request_reset_token(&app).await;
+    assert!(app.get_forgot_password_html().await.contains(SENT_MESSAGE));
+    assert!(app
+        .get_reset_password_html(&token)
+        .await
+        .contains(r#"name="new_password""#));
+
+    let response = post_new_password(&app, &token).await;
+    assert_is_redirect_to(&response, "/login");
+    assert!(app
+        .get_login_html()
+        .await
+        .contains("Your password has been reset, you can log in with it."));
+
+    assert_is_redirect_to(&login(&app, &app.test_user.password).await, "/login");
+    assert_is_redirect_to(&login(&app, NEW_PASSWORD).await, "/admin/dashboard");
+}
+
+#[tokio::test]
+async fn unknown_email_addresses_get_the_same_answer_and_no_email() {
+    let app = spawn_app().await;
+    give_the_test_user_an_email(&app).await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app.post_forgot_password("someone-else@example.com").await;
+
+    assert_is_redirect_to(&response, "/login/forgot");
+    assert!(app.get_forgot_password_html().await.contains(SENT_MESSAGE));
+}
+
+#[tokio::test]
+async fn the_answer_does_not_wait_for_the_reset_email_to_be_sent() {
+    let app = spawn_app().await;
+    give_the_test_user_an_email(&app).await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200).set_delay(std::time::Duration::from_secs(2)))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let started_at = std::time::Instant::now();
+    let response = app.post_forgot_password(EMAIL).await;
+
+    assert_is_redirect_to(&response, "/login/forgot");
+    assert!(started_at.elapsed() < std::time::Duration::from_secs(1));
+    app.wait_for_emails(1).await;
+}
+
+#[tokio::test]
+async fn disabled_users_are_not_sent_a_reset_link() {
+    let app = spawn_app().await;
+    give_the_test_user_an_email(&app).await;
+    sqlx::query!(
+        "UPDATE users SET disabled = true WHERE id = $1",
+        app.test_user.id
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app.post_forgot_password(EMAIL).await;
+
+    assert_is_redirect_to(&response, "/login/forgot");
+}
+
+#[tokio::test]
+async fn reset_links_are_not_sent_again_right_away() {
+    let app = spawn_app().await;
+    give_the_test_user_an_email(&app).await;
+    request_reset_token(&app).await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app.post_forgot_password(EMAIL).await;
+
+    assert_is_redirect_to(&response, "/login/forgot");
+    assert!(app.get_forgot_password_html().await.contains(SENT_MESSAGE));
+}
+
+#[tokio::test]
+async fn a_reset_link_can_only_be_used_once() {
+    let app = spawn_app().await;
+    give_the_test_user_an_email(&app).await;
+    let token = request_reset_token(&app).await;
+    assert_is_redirect_to(&post_new_password(&app, &token).await, "/login");
+
+    let response = post_new_password(&app, &token).await;
+
+    assert_is_redirect_to(&response, "/login/forgot");
+    assert!(app
+        .get_forgot_password_html()
+        .await
+        .contains(INVALID_LINK_MESSAGE));
+    assert!(app
+        .get_reset_password_html(&token)
+        .await
+        .contains(INVALID_LINK_MESSAGE));
+}
+
+#[tokio::test]
+async fn expired_reset_links_are_rejected() {
+    let app = spawn_app().await;
+    give_the_test_user_an_email(&app).await;
+    let token = request_reset_token(&app).await;
+    sqlx::query!("UPDATE password_reset_tokens SET expires_at = now()")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+
+    assert!(app
+        .get_reset_password_html(&token)
+        .await
+        .contains(INVALID_LINK_MESSAGE));
+    assert_is_redirect_to(&post_new_password(&app, &token).await, "/login/forgot");
+    assert_is_redirect_to(&login(&app, NEW_PASSWORD).await, "/login");
+}
+
+#[tokio::test]
+async fn the_new_password_must_match_the_policy() {
+    let app = spawn_app().await;
+    give_the_test_user_an_email(&app).await;
+    let token = request_reset_token(&app).await;
+    let reset_form = format!("/login/reset?token={}", token);
+
+    let response = app
+        .post_reset_password(&serde_json::json!({
+            "token": &token,
+            "new_password": NEW_PASSWORD,
+            "new_password_check": "another-new-password",
+        }))
+        .await;
+    assert_is_redirect_to(&response, &reset_form);
+    assert!(app
+        .get_reset_password_html(&token)
+        .await
+        .contains("New password must match"));
+
+    let response = app
+        .post_reset_password(&serde_json::json!({
+            "token": &token,
+            "new_password": "too-short",
+            "new_password_check": "too-short",
+        }))
+        .await;
+    assert_is_redirect_to(&response, &reset_form);
+    assert!(app
+        .get_reset_password_html(&token)
+        .await
+        .contains("New password must be between 12 and 128 characters long."));
+
+    // The token is still good
+    assert_is_redirect_to(&post_new_password(&app, &token).await, "/login");
+}
+
+#[tokio::test]
+async fn resetting_the_password_lifts_the_login_lockout_of_the_username() {
+    let app = spawn_app().await;
+    give_the_test_user_an_email(&app).await;
+    for _ in 0..10 {
+        login(&app, "wrong-password").await;
+    }
+    let token = request_reset_token(&app).await;
+
+    assert_is_redirect_to(&post_new_password(&app, &token).await, "/login");
+
+    let n_throttles = sqlx::query!(
+        "SELECT COUNT(*) AS \"n!\" FROM login_throttles WHERE throttle_key = $1",
+        format!("username:{}", app.test_user.username)
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap()
+    .n;
+    assert_eq!(n_throttles, 0);
+}
+
+#[tokio::test]
+async fn owners_can_change_the_email_address_of_a_user() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    let response = app
+        .post_user_action(
+            app.test_user.id,
+            "email",
+            &serde_json::json!({ "email": EMAIL }),
+        )
+        .await;
+    assert_is_redirect_to(&response, "/admin/users");
+    let html_page = app.get_users_html().await;
+    assert!(html_page.contains(&format!("now uses {}", EMAIL)));
+    assert!(html_page.contains(&format!(r#"value="{}""#, EMAIL)));
+
+    let response = app
+        .post_user_action(
+            app.test_user.id,
+            "email",
+            &serde_json::json!({ "email": "" }),
+        )
+        .await;
+    assert_is_redirect_to(&response, "/admin/users");
+    assert!(app
+        .get_users_html()
+        .await
+        .contains("has no email address anymore"));
+}
+
+#[tokio::test]
+async fn invalid_email_addresses_are_rejected() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    let response = app
+        .post_user_action(
+            app.test_user.id,
+            "email",
+            &serde_json::json!({ "email": "not-an-email" }),
+        )
+        .await;
+    assert_is_redirect_to(&response, "/admin/users");
+    assert!(app.get_users_html().await.contains("is not a valid"));
+
+    let response = app.post_forgot_password("not-an-email").await;
+    assert_is_redirect_to(&response, "/login/forgot");
+    assert!(app
+        .get_forgot_password_html()
+        .await
+        .contains("is not a valid"));
+}
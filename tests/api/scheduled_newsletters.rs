@@ -0,0 +1,208 @@
+use chrono::{Duration, Utc};
+use uuid::Uuid;
+use wiremock::{
+    matchers::{any, method, path},
+    Mock, ResponseTemplate,
+};
+
+use crate::helpers::{
+    accept_every_email, create_confirmed_subscriber, publish_newsletter, spawn_app, TestApp,
+};
+
+fn in_one_hour() -> String {
+    (Utc::now() + Duration::hours(1))
+        .format("%Y-%m-%dT%H:%M")
+        .to_string()
+}
+
+async fn schedule_newsletter(app: &TestApp, send_at: &str) -> Uuid {
+    let newsletter_request_body = serde_json::json!({
+        "title": "Newsletter Title",
+        "text_content": "Newsletter body as plain text",
+        "html_content": "<p>Newsletter body as plain text</p>",
+        "idempotency_key": Uuid::new_v4().to_string(),
+        "send_at": send_at,
+    });
+    let response = app.post_publish_newsletter(&newsletter_request_body).await;
+    assert_eq!(response.status().as_u16(), 303);
+    assert_eq!(
+        response.headers().get("Location").unwrap(),
+        "/admin/newsletters"
+    );
+
+    sqlx::query!("SELECT id FROM newsletter_issues")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .id
+}
+
+async fn n_queued_tasks(app: &TestApp) -> i64 {
+    sqlx::query!(r#"SELECT COUNT(*) AS "count!" FROM issue_delivery_queue"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .count
+}
+
+#[tokio::test]
+async fn scheduled_issues_are_not_delivered_before_their_time() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    schedule_newsletter(&app, &in_one_hour()).await;
+
+    let publish_newsletter_html = app.get_publish_newsletter_html().await;
+    assert!(publish_newsletter_html
+        .contains("The newsletter issue has been scheduled, emails will go out at"));
+    assert!(publish_newsletter_html.contains("Newsletter Title</a> goes out at"));
+
+    app.dispatch_all_pending_emails().await;
+    assert_eq!(n_queued_tasks(&app).await, 1);
+}
+
+#[tokio::test]
+async fn scheduled_issues_are_delivered_once_their_time_has_come() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+
+    Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        .respond_with(accept_every_email)
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let issue_id = schedule_newsletter(&app, &in_one_hour()).await;
+
+    // Travel forward in time
+    sqlx::query!(
+        "UPDATE newsletter_issues SET published_at = now() - interval '1 minute' WHERE id = $1",
+        issue_id
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+
+    app.dispatch_all_pending_emails().await;
+    assert_eq!(n_queued_tasks(&app).await, 0);
+}
+
+#[tokio::test]
+async fn invalid_send_at_is_rejected_with_400() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    let newsletter_request_body = serde_json::json!({
+        "title": "Newsletter Title",
+        "text_content": "Newsletter body as plain text",
+        "html_content": "<p>Newsletter body as plain text</p>",
+        "idempotency_key": Uuid::new_v4().to_string(),
+        "send_at": "next tuesday",
+    });
+    let response = app.post_publish_newsletter(&newsletter_request_body).await;
+
+    assert_eq!(response.status().as_u16(), 400);
+}
+
+#[tokio::test]
+async fn a_scheduled_issue_can_be_cancelled() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+
+    let issue_id = schedule_newsletter(&app, &in_one_hour()).await;
+
+    let response = app.post_cancel_scheduled_issue(issue_id).await;
+    assert_eq!(response.status().as_u16(), 303);
+    assert_eq!(
+        response.headers().get("Location").unwrap(),
+        "/admin/newsletters"
+    );
+
+    let publish_newsletter_html = app.get_publish_newsletter_html().await;
+    assert!(publish_newsletter_html.contains("The scheduled newsletter issue has been cancelled."));
+
+    assert_eq!(n_queued_tasks(&app).await, 0);
+    let issue = sqlx::query!("SELECT id FROM newsletter_issues WHERE id = $1", issue_id)
+        .fetch_optional(&app.db_pool)
+        .await
+        .unwrap();
+    assert!(issue.is_none());
+}
+
+#[tokio::test]
+async fn an_issue_that_already_went_out_cannot_be_cancelled() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+
+    let issue_id = publish_newsletter(&app).await;
+
+    let response = app.post_cancel_scheduled_issue(issue_id).await;
+    assert_eq!(response.status().as_u16(), 303);
+    assert_eq!(
+        response.headers().get("Location").unwrap(),
+        &format!("/admin/newsletters/{}", issue_id)
+    );
+
+    let status_html = app
+        .get_issue_delivery_status(issue_id)
+        .await
+        .text()
+        .await
+        .unwrap();
+    assert!(
+        status_html.contains("Only issues that haven&#x27;t gone out yet can be cancelled.")
+            || status_html.contains("Only issues that haven't gone out yet can be cancelled.")
+    );
+    assert_eq!(n_queued_tasks(&app).await, 1);
+}
+
+#[tokio::test]
+async fn a_scheduled_issue_can_be_rescheduled() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+
+    let issue_id = schedule_newsletter(&app, &in_one_hour()).await;
+
+    let response = app
+        .post_reschedule_issue(
+            issue_id,
+            &serde_json::json!({ "send_at": "2099-01-01T09:00" }),
+        )
+        .await;
+    assert_eq!(response.status().as_u16(), 303);
+
+    let status_html = app
+        .get_issue_delivery_status(issue_id)
+        .await
+        .text()
+        .await
+        .unwrap();
+    assert!(status_html.contains(
+        "The newsletter issue has been scheduled, emails will go out at 2099-01-01 09:00 UTC."
+    ));
+
+    let published_at = sqlx::query!(
+        "SELECT published_at FROM newsletter_issues WHERE id = $1",
+        issue_id
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap()
+    .published_at;
+    assert_eq!(
+        published_at.unwrap().to_rfc3339(),
+        "2099-01-01T09:00:00+00:00"
+    );
+}
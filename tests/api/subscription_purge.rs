@@ -0,0 +1,132 @@
+use wiremock::{
+    matchers::{method, path},
+    Mock, ResponseTemplate,
+};
+
+use crate::helpers::{spawn_app, TestApp};
+
+async fn subscribe(app: &TestApp, name: &str) {
+    let response = app
+        .post_subscription(format!("name={}&email={}%40example.com", name, name))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+async fn remaining_subscribers(app: &TestApp) -> Vec<String> {
+    sqlx::query!("SELECT name FROM subscriptions ORDER BY name")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|r| r.name)
+        .collect()
+}
+
+#[tokio::test]
+async fn pending_subscriptions_with_expired_links_are_purged() {
+    let app = spawn_app().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+
+    subscribe(&app, "stale").await;
+    app.expire_confirmation_links().await;
+    subscribe(&app, "fresh").await;
+
+    assert_eq!(app.purge_stale_subscriptions().await, 1);
+
+    assert_eq!(remaining_subscribers(&app).await, vec!["fresh"]);
+    let token_owners = sqlx::query!(
+        r#"
+        SELECT name AS "name!" FROM subscription_tokens
+        JOIN subscriptions ON subscriptions.id = subscription_tokens.subscriber_id
+    "#
+    )
+    .fetch_all(&app.db_pool)
+    .await
+    .unwrap();
+    assert_eq!(token_owners.len(), 1);
+    assert_eq!(token_owners[0].name, "fresh");
+}
+
+#[tokio::test]
+async fn pending_subscriptions_with_a_new_link_are_not_purged() {
+    let app = spawn_app().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+
+    subscribe(&app, "patient").await;
+    app.expire_confirmation_links().await;
+    // They asked for a new link, the subscription itself is still old
+    subscribe(&app, "patient").await;
+
+    assert_eq!(app.purge_stale_subscriptions().await, 0);
+    assert_eq!(remaining_subscribers(&app).await, vec!["patient"]);
+}
+
+#[tokio::test]
+async fn confirmed_and_unsubscribed_subscribers_are_not_purged() {
+    let app = spawn_app().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+
+    subscribe(&app, "confirmed").await;
+    let email_request = &app.email_server.received_requests().await.unwrap()[0];
+    let confirmation_link = app.get_confirmation_link_from_email_body(email_request);
+    reqwest::get(confirmation_link.html)
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+    subscribe(&app, "unsubscribed").await;
+    sqlx::query!("UPDATE subscriptions SET status = 'unsubscribed' WHERE name = 'unsubscribed'")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+    app.expire_confirmation_links().await;
+    sqlx::query!("UPDATE subscriptions SET subscribed_at = now() - interval '1 year'")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+
+    assert_eq!(app.purge_stale_subscriptions().await, 0);
+    assert_eq!(
+        remaining_subscribers(&app).await,
+        vec!["confirmed", "unsubscribed"]
+    );
+}
+
+#[tokio::test]
+async fn used_confirmation_links_still_tell_the_subscription_is_confirmed_after_a_purge() {
+    let app = spawn_app().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+
+    subscribe(&app, "confirmed").await;
+    let email_request = &app.email_server.received_requests().await.unwrap()[0];
+    let confirmation_link = app.get_confirmation_link_from_email_body(email_request);
+    reqwest::get(confirmation_link.html.clone())
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+    app.expire_confirmation_links().await;
+    app.purge_stale_subscriptions().await;
+
+    let response = reqwest::get(confirmation_link.html).await.unwrap();
+
+    assert_eq!(response.status().as_u16(), 200);
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["status"], "already_confirmed");
+}
@@ -2,8 +2,43 @@ use wiremock::{
     matchers::{method, path},
     Mock, ResponseTemplate,
 };
+use zero2prod::domains::UnsubscribeToken;
 
-use crate::helpers::spawn_app;
+use crate::helpers::{spawn_app, ConfirmationLink, TestApp};
+
+const BODY: &str = "name=danil%20hendra&email=danilhendrasr%40gmail.com";
+
+/// Subscribes with `BODY`, and returns the link of the confirmation email if one was sent.
+async fn subscribe(app: &TestApp) -> Option<ConfirmationLink> {
+    let n_emails_before = app.email_server.received_requests().await.unwrap().len();
+
+    let response = app.post_subscription(BODY.into()).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let email_requests = app.email_server.received_requests().await.unwrap();
+    email_requests[n_emails_before..]
+        .last()
+        .map(|email_request| app.get_confirmation_link_from_email_body(email_request))
+}
+
+async fn post_subscription_accepting(app: &TestApp, body: &str, accept: &str) -> reqwest::Response {
+    app.http_client
+        .post(format!("{}/subscriptions", app.address))
+        .header("Content-Type", "application/x-www-form-urlencoded")
+        .header("Accept", accept)
+        .body(body.to_string())
+        .send()
+        .await
+        .expect("Failed to execute request.")
+}
+
+async fn subscription_status(app: &TestApp) -> String {
+    sqlx::query!("SELECT status FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch the subscription status.")
+        .status
+}
 
 #[tokio::test]
 async fn subscribe_returns_200_if_data_valid() {
@@ -68,6 +103,72 @@ async fn subscribe_returns_400_when_fields_are_present_but_empty() {
     }
 }
 
+#[tokio::test]
+async fn browsers_are_asked_to_check_their_inbox() {
+    let app = spawn_app().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+
+    let response = post_subscription_accepting(&app, BODY, "text/html,*/*;q=0.8").await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    let html_page = response.text().await.unwrap();
+    assert!(html_page.contains("Thanks for subscribing! Follow the link we have emailed you"));
+}
+
+#[tokio::test]
+async fn api_clients_get_a_json_body() {
+    let app = spawn_app().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+
+    let response = post_subscription_accepting(&app, BODY, "application/json").await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["status"], "pending_confirmation");
+}
+
+#[tokio::test]
+async fn browsers_see_what_is_invalid_with_the_form_filled_in() {
+    let app = spawn_app().await;
+
+    let response = post_subscription_accepting(
+        &app,
+        "name=danil%20hendra&email=invalid-email",
+        "text/html,*/*;q=0.8",
+    )
+    .await;
+
+    assert_eq!(response.status().as_u16(), 400);
+    let html_page = response.text().await.unwrap();
+    assert!(html_page.contains("invalid-email is not a valid email"));
+    assert!(html_page.contains(r#"value="danil hendra""#));
+}
+
+#[tokio::test]
+async fn api_clients_see_what_is_invalid_in_a_json_body() {
+    let app = spawn_app().await;
+
+    let response = post_subscription_accepting(
+        &app,
+        "name=&email=danilhendrasr%40gmail.com",
+        "application/json",
+    )
+    .await;
+
+    assert_eq!(response.status().as_u16(), 400);
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["status"], "invalid_data");
+    assert_eq!(body["message"], " is not a valid subscriber name");
+}
+
 #[tokio::test]
 async fn subscribe_returns_400_if_data_invalid() {
     let app = spawn_app().await;
@@ -136,3 +237,114 @@ async fn subscribe_if_there_is_database_error() {
     let response = app.post_subscription(body.into()).await;
     assert_eq!(response.status().as_u16(), 500);
 }
+
+#[tokio::test]
+async fn subscribing_again_before_confirming_resends_the_same_confirmation_link() {
+    let app = spawn_app().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(2)
+        .mount(&app.email_server)
+        .await;
+
+    let first_link = subscribe(&app).await.unwrap();
+    let second_link = subscribe(&app).await.unwrap();
+
+    assert_eq!(first_link.html, second_link.html);
+    assert_eq!(subscription_status(&app).await, "pending_confirmation");
+}
+
+#[tokio::test]
+async fn subscribing_again_once_the_link_expired_sends_a_new_link() {
+    let app = spawn_app().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(2)
+        .mount(&app.email_server)
+        .await;
+
+    let expired_link = subscribe(&app).await.unwrap();
+    app.expire_confirmation_links().await;
+    let new_link = subscribe(&app).await.unwrap();
+
+    assert_ne!(expired_link.html, new_link.html);
+    reqwest::get(new_link.html)
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+    assert_eq!(subscription_status(&app).await, "confirmed");
+}
+
+#[tokio::test]
+async fn subscribing_again_once_confirmed_sends_a_notice_instead_of_a_link() {
+    let app = spawn_app().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(2)
+        .mount(&app.email_server)
+        .await;
+    let confirmation_link = subscribe(&app).await.unwrap();
+    reqwest::get(confirmation_link.html)
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+
+    // Like for a pending subscription, the answer waits for an email to be sent
+    let response = app.post_subscription(BODY.into()).await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    let email_request = &app.email_server.received_requests().await.unwrap()[1];
+    let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+    assert_eq!(body["Subject"], "You are already subscribed");
+    assert!(!body["TextBody"]
+        .as_str()
+        .unwrap()
+        .contains("subscription_token"));
+    assert_eq!(subscription_status(&app).await, "confirmed");
+}
+
+#[tokio::test]
+async fn unsubscribed_subscribers_can_opt_back_in_with_a_new_confirmation() {
+    let app = spawn_app().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(2)
+        .mount(&app.email_server)
+        .await;
+    let old_link = subscribe(&app).await.unwrap();
+    reqwest::get(old_link.html.clone())
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+    let subscriber_id = sqlx::query!("SELECT id FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .id;
+    let token = UnsubscribeToken::generate(subscriber_id, &app.hmac_secret);
+    app.post_unsubscribe(token.as_ref()).await;
+
+    // The old link doesn't subscribe them again
+    assert_eq!(
+        reqwest::get(old_link.html).await.unwrap().status().as_u16(),
+        401
+    );
+    assert_eq!(subscription_status(&app).await, "unsubscribed");
+
+    let new_link = subscribe(&app).await.unwrap();
+    assert_eq!(subscription_status(&app).await, "unsubscribed");
+
+    reqwest::get(new_link.html)
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+    assert_eq!(subscription_status(&app).await, "confirmed");
+}
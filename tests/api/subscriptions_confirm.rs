@@ -64,3 +64,163 @@ async fn clicking_on_confirmation_link_confirms_the_subscription() {
     assert_eq!(saved.name, "danil hendra");
     assert_eq!(saved.status, "confirmed");
 }
+
+const BROWSER_ACCEPT: &str = "text/html,application/xhtml+xml,*/*;q=0.8";
+
+#[tokio::test]
+async fn browsers_following_the_link_get_a_confirmation_page() {
+    let app = spawn_app().await;
+    let body = "name=danil%20hendra&email=danilhendrasr%40gmail.com";
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+
+    app.post_subscription(body.into()).await;
+
+    let email_request = &app.email_server.received_requests().await.unwrap()[0];
+    let confirmation_link = app.get_confirmation_link_from_email_body(email_request);
+
+    let response = app
+        .http_client
+        .get(confirmation_link.html)
+        .header("Accept", BROWSER_ACCEPT)
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status().as_u16(), 200);
+    assert!(response
+        .headers()
+        .get("Content-Type")
+        .unwrap()
+        .to_str()
+        .unwrap()
+        .starts_with("text/html"));
+    let html_page = response.text().await.unwrap();
+    assert!(html_page.contains("Your subscription is confirmed, you will receive our next issues."));
+}
+
+#[tokio::test]
+async fn api_clients_following_the_link_get_a_json_body() {
+    let app = spawn_app().await;
+    let body = "name=danil%20hendra&email=danilhendrasr%40gmail.com";
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+
+    app.post_subscription(body.into()).await;
+
+    let email_request = &app.email_server.received_requests().await.unwrap()[0];
+    let confirmation_link = app.get_confirmation_link_from_email_body(email_request);
+
+    let response = app
+        .http_client
+        .get(confirmation_link.html)
+        .header("Accept", "application/json")
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status().as_u16(), 200);
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["status"], "confirmed");
+}
+
+#[tokio::test]
+async fn following_a_used_link_again_tells_the_subscription_is_already_confirmed() {
+    let app = spawn_app().await;
+    let body = "name=danil%20hendra&email=danilhendrasr%40gmail.com";
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+
+    app.post_subscription(body.into()).await;
+
+    let email_request = &app.email_server.received_requests().await.unwrap()[0];
+    let confirmation_link = app.get_confirmation_link_from_email_body(email_request);
+
+    let response = reqwest::get(confirmation_link.html.clone()).await.unwrap();
+    assert_eq!(response.status().as_u16(), 200);
+
+    let response = reqwest::get(confirmation_link.html).await.unwrap();
+    assert_eq!(response.status().as_u16(), 200);
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["status"], "already_confirmed");
+
+    let n_unused_tokens = sqlx::query!(
+        r#"SELECT COUNT(*) AS "count!" FROM subscription_tokens WHERE consumed_at IS NULL"#
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap()
+    .count;
+    assert_eq!(n_unused_tokens, 0);
+}
+
+#[tokio::test]
+async fn unknown_confirmation_links_are_rejected_with_401() {
+    let app = spawn_app().await;
+
+    let response = app
+        .http_client
+        .get(format!(
+            "{}/subscriptions/confirm?subscription_token=unknown",
+            app.address
+        ))
+        .header("Accept", BROWSER_ACCEPT)
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status().as_u16(), 401);
+    let html_page = response.text().await.unwrap();
+    assert!(html_page.contains("This confirmation link is invalid or has already been used."));
+    assert!(html_page.contains(r#"<form action="/subscriptions" method="POST">"#));
+}
+
+#[tokio::test]
+async fn expired_confirmation_links_are_rejected_with_410() {
+    let app = spawn_app().await;
+    let body = "name=danil%20hendra&email=danilhendrasr%40gmail.com";
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+
+    app.post_subscription(body.into()).await;
+    app.expire_confirmation_links().await;
+
+    let email_request = &app.email_server.received_requests().await.unwrap()[0];
+    let confirmation_link = app.get_confirmation_link_from_email_body(email_request);
+
+    let response = app
+        .http_client
+        .get(confirmation_link.html)
+        .header("Accept", BROWSER_ACCEPT)
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status().as_u16(), 410);
+    let html_page = response.text().await.unwrap();
+    assert!(html_page.contains("This confirmation link has expired."));
+    // The form to ask for a new link is filled in
+    assert!(html_page.contains(r#"value="danilhendrasr@gmail.com""#));
+
+    let saved = sqlx::query!("SELECT status FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch saved subscription.");
+    assert_eq!(saved.status, "pending_confirmation");
+}
@@ -0,0 +1,267 @@
+use uuid::Uuid;
+use wiremock::{
+    matchers::{any, method, path},
+    Mock, ResponseTemplate,
+};
+use zero2prod::domains::UnsubscribeToken;
+
+use crate::helpers::{
+    accept_every_email, create_confirmed_subscriber, publish_newsletter, spawn_app, TestApp,
+};
+
+async fn subscriber_id(app: &TestApp) -> Uuid {
+    sqlx::query!("SELECT id FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch the subscriber.")
+        .id
+}
+
+async fn subscription_status(app: &TestApp) -> String {
+    sqlx::query!("SELECT status FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch the subscription status.")
+        .status
+}
+
+#[tokio::test]
+async fn unsubscribing_without_a_token_is_rejected_with_400() {
+    let app = spawn_app().await;
+
+    let response = app
+        .http_client
+        .post(format!("{}/subscriptions/unsubscribe", app.address))
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status().as_u16(), 400);
+}
+
+#[tokio::test]
+async fn unsubscribing_with_a_forged_token_is_rejected_with_401() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+
+    let forged_token = format!(
+        "{}.{}",
+        subscriber_id(&app).await.to_simple(),
+        "ab".repeat(32)
+    );
+
+    assert_eq!(
+        app.get_unsubscribe(&forged_token).await.status().as_u16(),
+        401
+    );
+    assert_eq!(
+        app.post_unsubscribe(&forged_token).await.status().as_u16(),
+        401
+    );
+    assert_eq!(subscription_status(&app).await, "confirmed");
+}
+
+#[tokio::test]
+async fn newsletter_issues_contain_an_unsubscribe_link() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+
+    Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        .respond_with(accept_every_email)
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    publish_newsletter(&app).await;
+    app.dispatch_all_pending_emails().await;
+
+    let email_request = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let unsubscribe_link = app.get_unsubscribe_link_from_email_body(&email_request);
+    let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+    assert!(body[0]["HtmlBody"].as_str().unwrap().contains(&format!(
+        r#"<a href="{}">Unsubscribe</a>"#,
+        unsubscribe_link
+    )));
+
+    let (_, token) = unsubscribe_link
+        .query_pairs()
+        .find(|(key, _)| key == "token")
+        .unwrap();
+    let token = UnsubscribeToken::parse(token.into_owned(), &app.hmac_secret).unwrap();
+    assert_eq!(token.subscriber_id(), subscriber_id(&app).await);
+}
+
+#[tokio::test]
+async fn following_the_unsubscribe_link_asks_for_a_confirmation() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    let token = UnsubscribeToken::generate(subscriber_id(&app).await, &app.hmac_secret);
+
+    let response = app.get_unsubscribe(token.as_ref()).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let html_page = response.text().await.unwrap();
+    assert!(html_page.contains(&format!(
+        r#"<form action="/subscriptions/unsubscribe?token={}" method="POST">"#,
+        token.as_ref()
+    )));
+    assert_eq!(subscription_status(&app).await, "confirmed");
+}
+
+#[tokio::test]
+async fn confirming_the_unsubscription_unsubscribes_the_subscriber() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    let token = UnsubscribeToken::generate(subscriber_id(&app).await, &app.hmac_secret);
+
+    let response = app.post_unsubscribe(token.as_ref()).await;
+    assert_eq!(response.status().as_u16(), 200);
+    assert!(response
+        .text()
+        .await
+        .unwrap()
+        .contains("You have been unsubscribed"));
+    assert_eq!(subscription_status(&app).await, "unsubscribed");
+
+    // Unsubscribing twice is fine
+    let response = app.post_unsubscribe(token.as_ref()).await;
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn unsubscribed_subscribers_do_not_receive_newsletter_issues() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    let token = UnsubscribeToken::generate(subscriber_id(&app).await, &app.hmac_secret);
+    app.post_unsubscribe(token.as_ref()).await;
+    app.test_user.login(&app).await;
+
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    publish_newsletter(&app).await;
+    app.dispatch_all_pending_emails().await;
+}
+
+#[tokio::test]
+async fn unsubscribing_cancels_the_pending_deliveries_to_the_subscriber() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    let issue_id = publish_newsletter(&app).await;
+    let token = UnsubscribeToken::generate(subscriber_id(&app).await, &app.hmac_secret);
+    app.post_unsubscribe(token.as_ref()).await;
+    app.dispatch_all_pending_emails().await;
+
+    let status = app.get_issue_delivery_status_json(issue_id).await;
+    assert_eq!(status["total_recipients"], 0);
+    assert_eq!(status["pending"], 0);
+    assert!(!status["completed_at"].is_null());
+}
+
+#[tokio::test]
+async fn newsletter_issues_have_list_unsubscribe_headers() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+
+    Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        .respond_with(accept_every_email)
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    publish_newsletter(&app).await;
+    app.dispatch_all_pending_emails().await;
+
+    let email_request = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+    let token = UnsubscribeToken::generate(subscriber_id(&app).await, &app.hmac_secret);
+
+    assert_eq!(
+        body[0]["Headers"],
+        serde_json::json!([
+            {
+                "Name": "List-Unsubscribe",
+                "Value": format!(
+                    "<mailto:{}?subject=unsubscribe>, <{}/subscriptions/unsubscribe/one-click?token={}>",
+                    body[0]["From"].as_str().unwrap(),
+                    app.address,
+                    token.as_ref()
+                ),
+            },
+            {
+                "Name": "List-Unsubscribe-Post",
+                "Value": "List-Unsubscribe=One-Click",
+            },
+        ])
+    );
+}
+
+#[tokio::test]
+async fn one_click_unsubscribe_unsubscribes_the_subscriber() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    let token = UnsubscribeToken::generate(subscriber_id(&app).await, &app.hmac_secret);
+
+    let response = app
+        .post_unsubscribe_one_click(token.as_ref(), "List-Unsubscribe=One-Click")
+        .await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(subscription_status(&app).await, "unsubscribed");
+}
+
+#[tokio::test]
+async fn one_click_unsubscribe_rejects_invalid_requests() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    let token = UnsubscribeToken::generate(subscriber_id(&app).await, &app.hmac_secret);
+    let forged_token = format!(
+        "{}.{}",
+        subscriber_id(&app).await.to_simple(),
+        "ab".repeat(32)
+    );
+
+    let test_cases = vec![
+        (token.as_ref(), "", 400),
+        (token.as_ref(), "List-Unsubscribe=Maybe", 400),
+        (forged_token.as_str(), "List-Unsubscribe=One-Click", 401),
+    ];
+    for (token, body, expected_status) in test_cases {
+        let response = app.post_unsubscribe_one_click(token, body).await;
+        assert_eq!(
+            response.status().as_u16(),
+            expected_status,
+            "The API did not fail with {} when the payload was {:?}.",
+            expected_status,
+            body
+        );
+    }
+    assert_eq!(subscription_status(&app).await, "confirmed");
+}
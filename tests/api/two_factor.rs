@@ -0,0 +1,202 @@
+use zero2prod::authentication::{generate_recovery_codes, save_two_factor_settings, TotpSecret};
+
+use crate::helpers::{spawn_app, TestApp};
+
+fn now() -> i64 {
+    chrono::Utc::now().timestamp()
+}
+
+/// A code that isn't valid right now, even with the allowed clock drift.
+fn invalid_code(secret: &TotpSecret) -> String {
+    let valid_codes = [-30, 0, 30].map(|drift| secret.code(now() + drift));
+    (0..10)
+        .map(|digit| digit.to_string().repeat(6))
+        .find(|code| !valid_codes.contains(code))
+        .unwrap()
+}
+
+fn assert_is_redirect_to(response: &reqwest::Response, location: &str) {
+    assert_eq!(response.status().as_u16(), 303);
+    assert_eq!(response.headers().get("Location").unwrap(), location);
+}
+
+/// Turns two-factor authentication on for the test user, returning their secret and recovery codes.
+async fn enroll_test_user(app: &TestApp) -> (TotpSecret, Vec<String>) {
+    let secret = TotpSecret::generate();
+    let recovery_codes = generate_recovery_codes();
+    save_two_factor_settings(app.test_user.id, &secret, 0, &recovery_codes, &app.db_pool)
+        .await
+        .unwrap();
+    (secret, recovery_codes)
+}
+
+async fn post_password(app: &TestApp) -> reqwest::Response {
+    app.post_login(&serde_json::json!({
+        "username": &app.test_user.username,
+        "password": &app.test_user.password,
+    }))
+    .await
+}
+
+fn extract_secret(settings_html: &str) -> TotpSecret {
+    let start = settings_html.find("<code>").unwrap() + "<code>".len();
+    let end = settings_html[start..].find("</code>").unwrap() + start;
+    TotpSecret::parse(settings_html[start..end].to_string()).unwrap()
+}
+
+#[tokio::test]
+async fn enrolling_turns_two_factor_authentication_on() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    let settings_html = app.get_two_factor_settings_html().await;
+    assert!(settings_html.contains("Two-factor authentication is off."));
+    assert!(settings_html.contains("otpauth://totp/"));
+    // The secret survives a reload, in case it has been scanned already
+    let secret = extract_secret(&settings_html);
+    assert_eq!(
+        extract_secret(&app.get_two_factor_settings_html().await).expose_base32(),
+        secret.expose_base32()
+    );
+
+    let response = app.post_enable_2fa(&secret.code(now())).await;
+    assert_eq!(response.status().as_u16(), 200);
+    let recovery_codes_html = response.text().await.unwrap();
+    assert_eq!(recovery_codes_html.matches("<li><code>").count(), 10);
+
+    let settings_html = app.get_two_factor_settings_html().await;
+    assert!(settings_html.contains("Two-factor authentication is on."));
+}
+
+#[tokio::test]
+async fn enrolling_requires_a_valid_code() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let secret = extract_secret(&app.get_two_factor_settings_html().await);
+
+    let response = app.post_enable_2fa(&invalid_code(&secret)).await;
+    assert_is_redirect_to(&response, "/admin/2fa");
+
+    let settings_html = app.get_two_factor_settings_html().await;
+    assert!(settings_html.contains("Invalid code"));
+    assert!(settings_html.contains("Two-factor authentication is off."));
+}
+
+#[tokio::test]
+async fn users_with_two_factor_authentication_need_a_code_to_log_in() {
+    let app = spawn_app().await;
+    let (secret, _) = enroll_test_user(&app).await;
+
+    let response = post_password(&app).await;
+    assert_is_redirect_to(&response, "/login/2fa");
+    // Not logged in yet
+    assert_is_redirect_to(&app.get_admin_dashboard().await, "/login");
+    assert_eq!(app.get_login_2fa().await.status().as_u16(), 200);
+
+    let response = app.post_login_2fa(&secret.code(now())).await;
+    assert_is_redirect_to(&response, "/admin/dashboard");
+    assert_eq!(app.get_admin_dashboard().await.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn the_second_step_requires_the_password_first() {
+    let app = spawn_app().await;
+    let (secret, _) = enroll_test_user(&app).await;
+
+    assert_is_redirect_to(&app.get_login_2fa().await, "/login");
+    let response = app.post_login_2fa(&secret.code(now())).await;
+    assert_is_redirect_to(&response, "/login");
+    assert_is_redirect_to(&app.get_admin_dashboard().await, "/login");
+}
+
+#[tokio::test]
+async fn an_invalid_code_does_not_log_in() {
+    let app = spawn_app().await;
+    let (secret, _) = enroll_test_user(&app).await;
+    post_password(&app).await;
+
+    let response = app.post_login_2fa(&invalid_code(&secret)).await;
+    assert_is_redirect_to(&response, "/login/2fa");
+
+    let form_html = app.get_login_2fa().await.text().await.unwrap();
+    assert!(form_html.contains("Invalid code."));
+    assert_is_redirect_to(&app.get_admin_dashboard().await, "/login");
+}
+
+#[tokio::test]
+async fn a_code_cannot_be_used_twice() {
+    let app = spawn_app().await;
+    let (secret, _) = enroll_test_user(&app).await;
+    let code = secret.code(now());
+    post_password(&app).await;
+    app.post_login_2fa(&code).await;
+    app.post_logout().await;
+
+    post_password(&app).await;
+    let response = app.post_login_2fa(&code).await;
+
+    assert_is_redirect_to(&response, "/login/2fa");
+}
+
+#[tokio::test]
+async fn each_recovery_code_logs_in_once() {
+    let app = spawn_app().await;
+    let (_, recovery_codes) = enroll_test_user(&app).await;
+
+    post_password(&app).await;
+    let response = app.post_login_2fa(&recovery_codes[0]).await;
+    assert_is_redirect_to(&response, "/admin/dashboard");
+    app.post_logout().await;
+
+    post_password(&app).await;
+    let response = app.post_login_2fa(&recovery_codes[0]).await;
+    assert_is_redirect_to(&response, "/login/2fa");
+    let response = app.post_login_2fa(&recovery_codes[1]).await;
+    assert_is_redirect_to(&response, "/admin/dashboard");
+}
+
+#[tokio::test]
+async fn too_many_invalid_codes_require_the_password_again() {
+    let app = spawn_app().await;
+    let (secret, _) = enroll_test_user(&app).await;
+    post_password(&app).await;
+
+    let invalid_code = invalid_code(&secret);
+
+    for _ in 0..2 {
+        assert_is_redirect_to(&app.post_login_2fa(&invalid_code).await, "/login/2fa");
+    }
+    assert_is_redirect_to(&app.post_login_2fa(&invalid_code).await, "/login");
+    assert!(app
+        .get_login_html()
+        .await
+        .contains("Too many invalid codes, please log in again."));
+
+    // Even a valid code is too late
+    let response = app.post_login_2fa(&secret.code(now())).await;
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn two_factor_authentication_can_be_turned_off() {
+    let app = spawn_app().await;
+    let (secret, recovery_codes) = enroll_test_user(&app).await;
+    post_password(&app).await;
+    app.post_login_2fa(&recovery_codes[0]).await;
+
+    let response = app.post_disable_2fa(&invalid_code(&secret)).await;
+    assert_is_redirect_to(&response, "/admin/2fa");
+    assert!(app
+        .get_two_factor_settings_html()
+        .await
+        .contains("Invalid code."));
+
+    app.post_disable_2fa(&recovery_codes[1]).await;
+    assert!(app
+        .get_two_factor_settings_html()
+        .await
+        .contains("Two-factor authentication is off."));
+
+    app.post_logout().await;
+    assert_is_redirect_to(&post_password(&app).await, "/admin/dashboard");
+}
@@ -0,0 +1,255 @@
+use secrecy::Secret;
+use uuid::Uuid;
+use zero2prod::authentication::{
+    create_user, delete_user, find_user_id, list_users, reset_password, set_disabled, set_email,
+    set_role, Role, UserManagementError,
+};
+use zero2prod::domains::SubscriberEmail;
+
+use crate::helpers::{publish_newsletter, spawn_app, TestApp};
+
+const PASSWORD: &str = "a-long-enough-password";
+
+async fn login_status(app: &TestApp, username: &str, password: &str) -> u16 {
+    let response = app
+        .post_login(&serde_json::json!({
+            "username": username,
+            "password": password,
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 303);
+
+    match response
+        .headers()
+        .get("Location")
+        .unwrap()
+        .to_str()
+        .unwrap()
+    {
+        "/admin/dashboard" => 200,
+        _ => 401,
+    }
+}
+
+#[tokio::test]
+async fn a_created_user_can_log_in() {
+    let app = spawn_app().await;
+
+    create_user(
+        "ursula",
+        Secret::new(PASSWORD.into()),
+        Role::Editor,
+        &app.db_pool,
+    )
+    .await
+    .unwrap();
+
+    assert_eq!(login_status(&app, "ursula", PASSWORD).await, 200);
+}
+
+#[tokio::test]
+async fn created_users_are_listed() {
+    let app = spawn_app().await;
+
+    let user_id = create_user(
+        "ursula",
+        Secret::new(PASSWORD.into()),
+        Role::Editor,
+        &app.db_pool,
+    )
+    .await
+    .unwrap();
+
+    let users = list_users(&app.db_pool).await.unwrap();
+    assert!(users
+        .iter()
+        .any(|user| user.id == user_id && user.username == "ursula"));
+    assert!(users
+        .iter()
+        .any(|user| user.username == app.test_user.username));
+}
+
+#[tokio::test]
+async fn usernames_must_be_unique() {
+    let app = spawn_app().await;
+
+    let outcome = create_user(
+        &app.test_user.username,
+        Secret::new(PASSWORD.into()),
+        Role::Viewer,
+        &app.db_pool,
+    )
+    .await;
+
+    assert!(matches!(
+        outcome,
+        Err(UserManagementError::UsernameTaken(_))
+    ));
+}
+
+#[tokio::test]
+async fn the_password_policy_applies_to_new_and_reset_passwords() {
+    let app = spawn_app().await;
+
+    for password in ["too-short", &"a".repeat(129)] {
+        let outcome = create_user(
+            "ursula",
+            Secret::new(password.into()),
+            Role::Editor,
+            &app.db_pool,
+        )
+        .await;
+        assert!(matches!(outcome, Err(UserManagementError::InvalidPassword)));
+
+        let outcome =
+            reset_password(app.test_user.id, Secret::new(password.into()), &app.db_pool).await;
+        assert!(matches!(outcome, Err(UserManagementError::InvalidPassword)));
+    }
+}
+
+#[tokio::test]
+async fn a_reset_password_replaces_the_previous_one() {
+    let app = spawn_app().await;
+
+    reset_password(app.test_user.id, Secret::new(PASSWORD.into()), &app.db_pool)
+        .await
+        .unwrap();
+
+    assert_eq!(
+        login_status(&app, &app.test_user.username, &app.test_user.password).await,
+        401
+    );
+    assert_eq!(
+        login_status(&app, &app.test_user.username, PASSWORD).await,
+        200
+    );
+}
+
+#[tokio::test]
+async fn unknown_users_are_reported() {
+    let app = spawn_app().await;
+
+    let outcome = find_user_id("nobody", &app.db_pool).await;
+    assert!(matches!(outcome, Err(UserManagementError::UnknownUser)));
+
+    let outcome = reset_password(Uuid::new_v4(), Secret::new(PASSWORD.into()), &app.db_pool).await;
+    assert!(matches!(outcome, Err(UserManagementError::UnknownUser)));
+
+    let outcome = delete_user(Uuid::new_v4(), &app.db_pool).await;
+    assert!(matches!(outcome, Err(UserManagementError::UnknownUser)));
+}
+
+#[tokio::test]
+async fn a_deleted_user_can_no_longer_log_in() {
+    let app = spawn_app().await;
+    // Leaves saved responses behind, referencing the user
+    app.test_user.login(&app).await;
+    publish_newsletter(&app).await;
+
+    delete_user(app.test_user.id, &app.db_pool).await.unwrap();
+
+    assert_eq!(
+        login_status(&app, &app.test_user.username, &app.test_user.password).await,
+        401
+    );
+}
+
+/// Leaves the test user as the only enabled owner.
+async fn demote_every_other_owner(app: &TestApp) {
+    for user in list_users(&app.db_pool).await.unwrap() {
+        if user.id != app.test_user.id && user.role == Role::Owner {
+            set_role(user.id, Role::Editor, &app.db_pool).await.unwrap();
+        }
+    }
+}
+
+#[tokio::test]
+async fn the_last_owner_cannot_be_deleted_disabled_or_demoted() {
+    let app = spawn_app().await;
+    demote_every_other_owner(&app).await;
+    let owner_id = app.test_user.id;
+
+    let outcome = delete_user(owner_id, &app.db_pool).await;
+    assert!(matches!(outcome, Err(UserManagementError::LastOwner)));
+    let outcome = set_disabled(owner_id, true, &app.db_pool).await;
+    assert!(matches!(outcome, Err(UserManagementError::LastOwner)));
+    let outcome = set_role(owner_id, Role::Viewer, &app.db_pool).await;
+    assert!(matches!(outcome, Err(UserManagementError::LastOwner)));
+
+    assert_eq!(
+        login_status(&app, &app.test_user.username, &app.test_user.password).await,
+        200
+    );
+}
+
+#[tokio::test]
+async fn the_last_owner_can_manage_everyone_else() {
+    let app = spawn_app().await;
+    demote_every_other_owner(&app).await;
+    let user_id = create_user(
+        "ursula",
+        Secret::new(PASSWORD.into()),
+        Role::Owner,
+        &app.db_pool,
+    )
+    .await
+    .unwrap();
+
+    // Another owner is left, either way
+    set_role(app.test_user.id, Role::Viewer, &app.db_pool)
+        .await
+        .unwrap();
+    set_role(app.test_user.id, Role::Owner, &app.db_pool)
+        .await
+        .unwrap();
+    set_role(user_id, Role::Editor, &app.db_pool).await.unwrap();
+    set_disabled(user_id, true, &app.db_pool).await.unwrap();
+    delete_user(user_id, &app.db_pool).await.unwrap();
+}
+
+#[tokio::test]
+async fn a_disabled_user_cannot_log_in_until_enabled_again() {
+    let app = spawn_app().await;
+    let user_id = create_user(
+        "ursula",
+        Secret::new(PASSWORD.into()),
+        Role::Viewer,
+        &app.db_pool,
+    )
+    .await
+    .unwrap();
+
+    set_disabled(user_id, true, &app.db_pool).await.unwrap();
+    assert_eq!(login_status(&app, "ursula", PASSWORD).await, 401);
+
+    set_disabled(user_id, false, &app.db_pool).await.unwrap();
+    assert_eq!(login_status(&app, "ursula", PASSWORD).await, 200);
+}
+
+#[tokio::test]
+async fn email_addresses_are_unique_regardless_of_case() {
+    let app = spawn_app().await;
+    let user_id = create_user(
+        "ursula",
+        Secret::new(PASSWORD.into()),
+        Role::Editor,
+        &app.db_pool,
+    )
+    .await
+    .unwrap();
+    let email = SubscriberEmail::parse("ursula@example.com".into()).unwrap();
+    set_email(app.test_user.id, Some(&email), &app.db_pool)
+        .await
+        .unwrap();
+
+    let email = SubscriberEmail::parse("Ursula@Example.com".into()).unwrap();
+    let outcome = set_email(user_id, Some(&email), &app.db_pool).await;
+    assert!(matches!(outcome, Err(UserManagementError::EmailTaken)));
+
+    // A user can keep their own address
+    set_email(app.test_user.id, Some(&email), &app.db_pool)
+        .await
+        .unwrap();
+    let outcome = set_email(Uuid::new_v4(), None, &app.db_pool).await;
+    assert!(matches!(outcome, Err(UserManagementError::UnknownUser)));
+}
@@ -0,0 +1,237 @@
+use secrecy::Secret;
+use uuid::Uuid;
+use zero2prod::authentication::{
+    create_password_reset_token, reset_password, reset_password_with_token, set_email,
+};
+use zero2prod::domains::SubscriberEmail;
+
+use crate::helpers::{spawn_app, TestApp, TestUser};
+
+const OTHER_USER_AGENT: &str = "Another browser";
+
+fn assert_is_redirect_to(response: &reqwest::Response, location: &str) {
+    assert_eq!(response.status().as_u16(), 303);
+    assert_eq!(response.headers().get("Location").unwrap(), location);
+}
+
+/// A client with its own cookies, logged in as `user`.
+async fn log_in_from_another_device(app: &TestApp, user: &TestUser) -> reqwest::Client {
+    let client = reqwest::Client::builder()
+        .redirect(reqwest::redirect::Policy::none())
+        .cookie_store(true)
+        .user_agent(OTHER_USER_AGENT)
+        .build()
+        .unwrap();
+    let response = client
+        .post(format!("{}/login", app.address))
+        .form(&serde_json::json!({
+            "username": user.username,
+            "password": user.password,
+        }))
+        .send()
+        .await
+        .unwrap();
+    assert_is_redirect_to(&response, "/admin/dashboard");
+
+    client
+}
+
+async fn get_dashboard(app: &TestApp, client: &reqwest::Client) -> reqwest::Response {
+    client
+        .get(format!("{}/admin/dashboard", app.address))
+        .send()
+        .await
+        .unwrap()
+}
+
+async fn other_session_id(app: &TestApp) -> Uuid {
+    sqlx::query!(
+        "SELECT id FROM user_sessions WHERE user_agent = $1",
+        OTHER_USER_AGENT
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap()
+    .id
+}
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_see_your_sessions() {
+    let app = spawn_app().await;
+
+    assert_is_redirect_to(&app.get_sessions().await, "/login");
+}
+
+#[tokio::test]
+async fn every_session_of_the_user_is_listed() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    log_in_from_another_device(&app, &app.test_user).await;
+
+    let html_page = app.get_sessions_html().await;
+
+    assert!(html_page.contains(OTHER_USER_AGENT));
+    assert!(html_page.contains("127.0.0.1"));
+    assert!(html_page.contains("This session"));
+    assert!(html_page.contains(&format!(
+        "/admin/sessions/{}/revoke",
+        other_session_id(&app).await
+    )));
+}
+
+#[tokio::test]
+async fn the_sessions_of_other_users_are_not_listed() {
+    let app = spawn_app().await;
+    let other_user = TestUser::generate();
+    other_user.store(&app.db_pool).await;
+    log_in_from_another_device(&app, &other_user).await;
+    app.test_user.login(&app).await;
+
+    assert!(!app.get_sessions_html().await.contains(OTHER_USER_AGENT));
+}
+
+#[tokio::test]
+async fn a_revoked_session_is_logged_out() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let other_device = log_in_from_another_device(&app, &app.test_user).await;
+    assert_eq!(
+        get_dashboard(&app, &other_device).await.status().as_u16(),
+        200
+    );
+
+    let response = app.post_revoke_session(other_session_id(&app).await).await;
+
+    assert_is_redirect_to(&response, "/admin/sessions");
+    let html_page = app.get_sessions_html().await;
+    assert!(html_page.contains("The session has been logged out."));
+    assert!(!html_page.contains(OTHER_USER_AGENT));
+    assert_is_redirect_to(&get_dashboard(&app, &other_device).await, "/login");
+    assert_eq!(app.get_admin_dashboard().await.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn the_sessions_of_other_users_cannot_be_revoked() {
+    let app = spawn_app().await;
+    let other_user = TestUser::generate();
+    other_user.store(&app.db_pool).await;
+    let other_device = log_in_from_another_device(&app, &other_user).await;
+    app.test_user.login(&app).await;
+
+    app.post_revoke_session(other_session_id(&app).await).await;
+
+    assert!(app
+        .get_sessions_html()
+        .await
+        .contains("There is no such session."));
+    assert_eq!(
+        get_dashboard(&app, &other_device).await.status().as_u16(),
+        200
+    );
+}
+
+#[tokio::test]
+async fn every_other_session_can_be_revoked_at_once() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let other_devices = [
+        log_in_from_another_device(&app, &app.test_user).await,
+        log_in_from_another_device(&app, &app.test_user).await,
+    ];
+
+    let response = app.post_revoke_other_sessions().await;
+
+    assert_is_redirect_to(&response, "/admin/sessions");
+    assert!(app
+        .get_sessions_html()
+        .await
+        .contains("Every other session has been logged out."));
+    for other_device in &other_devices {
+        assert_is_redirect_to(&get_dashboard(&app, other_device).await, "/login");
+    }
+    assert_eq!(app.get_admin_dashboard().await.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn changing_the_password_logs_the_other_sessions_out() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let other_device = log_in_from_another_device(&app, &app.test_user).await;
+    let new_password = Uuid::new_v4().to_string();
+
+    app.post_change_password(&serde_json::json!({
+        "current_password": &app.test_user.password,
+        "new_password": &new_password,
+        "new_password_check": &new_password,
+    }))
+    .await;
+
+    assert!(app
+        .get_change_password_html()
+        .await
+        .contains("Your other sessions have been logged out."));
+    assert_is_redirect_to(&get_dashboard(&app, &other_device).await, "/login");
+    assert_eq!(app.get_admin_dashboard().await.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn resetting_a_forgotten_password_logs_every_session_out() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let email = SubscriberEmail::parse("ursula@example.com".into()).unwrap();
+    set_email(app.test_user.id, Some(&email), &app.db_pool)
+        .await
+        .unwrap();
+    let reset_token = create_password_reset_token(&email, &app.db_pool)
+        .await
+        .unwrap()
+        .unwrap();
+
+    reset_password_with_token(
+        &reset_token.token,
+        Secret::new(Uuid::new_v4().to_string()),
+        &app.db_pool,
+    )
+    .await
+    .unwrap()
+    .unwrap();
+
+    assert_is_redirect_to(&app.get_admin_dashboard().await, "/login");
+}
+
+#[tokio::test]
+async fn resetting_a_password_from_the_command_line_logs_every_session_out() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    reset_password(
+        app.test_user.id,
+        Secret::new(Uuid::new_v4().to_string()),
+        &app.db_pool,
+    )
+    .await
+    .unwrap();
+
+    assert_is_redirect_to(&app.get_admin_dashboard().await, "/login");
+    let n_sessions = sqlx::query!(r#"SELECT COUNT(*) AS "n!" FROM user_sessions"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .n;
+    assert_eq!(n_sessions, 0);
+}
+
+#[tokio::test]
+async fn logging_out_ends_the_session() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    app.post_logout().await;
+
+    let n_sessions = sqlx::query!(r#"SELECT COUNT(*) AS "n!" FROM user_sessions"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .n;
+    assert_eq!(n_sessions, 0);
+}